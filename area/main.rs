@@ -1,4 +1,6 @@
-use scraper::{Html, Selector};
+use auto_selecter1::models::{parse_change_string, ScraperError};
+use auto_selecter1::scraper_logic::normalize_minus;
+use scraper::{ElementRef, Html, Selector};
 use reqwest::blocking::get;
 
 // --- データ構造 ---
@@ -12,51 +14,41 @@ struct StockData {
     update_time: String,
 }
 
-// --- ヘルパー関数：前日比の文字列を金額とパーセントに分割 ---
-fn parse_change_string(combined: &str) -> (String, String) {
-    if let Some(paren_index) = combined.find('(') {
-        let abs = combined[..paren_index].trim().to_string();
-        let pct_part = &combined[paren_index + 1..];
-        let pct = if let Some(end_paren_index) = pct_part.find(')') {
-            pct_part[..end_paren_index].trim().to_string()
-        } else {
-            "".to_string()
-        };
-        (abs, pct)
-    } else {
-        (combined.trim().to_string(), "".to_string())
-    }
+type ScrapeResult = Result<StockData, Box<dyn std::error::Error>>;
+
+// --- セレクタ文字列を解析するヘルパー関数 ---
+// セレクタはリテラルなので失敗はバグだが、panic せずエラーを返して残りの銘柄の処理を続けられるようにする
+fn parse_selector(selector: &str) -> Result<Selector, ScraperError> {
+    Selector::parse(selector).map_err(|e| ScraperError::SelectorParse(format!("{}: {:?}", selector, e)))
 }
 
 // --- 個別株価ページのスクレイピング関数 ---
 fn scrape_stock_page_data(document: &Html) -> Result<StockData, Box<dyn std::error::Error>> {
-    let container_sel = Selector::parse("div[class*='PriceBoard__main']").unwrap();
+    let container_sel = parse_selector("div[class*='PriceBoard__main']")?;
     let container = document.select(&container_sel).next().ok_or("Main container not found")?;
 
-    let name_sel = Selector::parse("header h2").unwrap();
+    let name_sel = parse_selector("header h2")?;
     let name = container
         .select(&name_sel)
         .next()
         .map(|e| e.text().collect::<String>().trim().to_string())
         .unwrap_or_default();
 
-    let code_sel = Selector::parse("span[class*='PriceBoard__code']").unwrap();
+    let code_sel = parse_selector("span[class*='PriceBoard__code']")?;
     let code = container
         .select(&code_sel)
         .next()
         .map(|e| e.text().collect::<String>().trim().to_string())
         .unwrap_or_default();
 
-    let price_sel =
-        Selector::parse("span[class*='PriceBoard__price'] span[class*='StyledNumber__value']")
-            .unwrap();
+    let price_sel = parse_selector("span[class*='PriceBoard__price'] span[class*='StyledNumber__value']")?;
     let price = container
         .select(&price_sel)
         .next()
         .map(|e| e.text().collect::<String>().trim().to_string())
         .unwrap_or_default();
 
-    let change_sel = Selector::parse("div[class*='PriceChangeLabel']").unwrap();
+    let change_sel = parse_selector("div[class*='PriceChangeLabel']")?;
     let combined_change = container
         .select(&change_sel)
         .next()
@@ -71,7 +63,7 @@ fn scrape_stock_page_data(document: &Html) -> Result<StockData, Box<dyn std::err
         .unwrap_or_default();
     let (change_abs, change_pct) = parse_change_string(&combined_change);
 
-    let time_sel = Selector::parse("ul[class*='PriceBoard__times'] time").unwrap();
+    let time_sel = parse_selector("ul[class*='PriceBoard__times'] time")?;
     let update_time = container
         .select(&time_sel)
         .next()
@@ -90,7 +82,7 @@ fn scrape_stock_page_data(document: &Html) -> Result<StockData, Box<dyn std::err
 
 // --- 指数ページ（^DJIなど）のスクレイピング関数 ---
 fn scrape_index_data(document: &Html, code: &str) -> Result<StockData, Box<dyn std::error::Error>> {
-    let name_sel = Selector::parse("h1").unwrap();
+    let name_sel = parse_selector("h1")?;
     let raw_name = document
         .select(&name_sel)
         .next()
@@ -98,13 +90,13 @@ fn scrape_index_data(document: &Html, code: &str) -> Result<StockData, Box<dyn s
         .unwrap_or_default();
     let name = raw_name.replace("の指数情報・推移", "").trim().to_string();
 
-    let container_sel = Selector::parse("div[class*='_BasePriceBoard__main']").unwrap();
+    let container_sel = parse_selector("div[class*='_BasePriceBoard__main']")?;
     let container = match document.select(&container_sel).next() {
         Some(c) => c,
         None => return Err(format!("Index container not found for {}.", code).into())
     };
 
-    let price_block_sel = Selector::parse("div[class*='_BasePriceBoard__price']").unwrap();
+    let price_block_sel = parse_selector("div[class*='_BasePriceBoard__price']")?;
     let price_block_text = container
         .select(&price_block_sel)
         .next()
@@ -132,7 +124,7 @@ fn scrape_index_data(document: &Html, code: &str) -> Result<StockData, Box<dyn s
     let (change_abs, change_pct) = parse_change_string(&combined_change);
 
     let mut update_time = "".to_string();
-    let list_items_sel = Selector::parse("ul li").unwrap();
+    let list_items_sel = parse_selector("ul li")?;
     let mut found_realtime = false;
     for li in document.select(&list_items_sel) {
         let text = li.text().collect::<String>();
@@ -157,29 +149,27 @@ fn scrape_index_data(document: &Html, code: &str) -> Result<StockData, Box<dyn s
 
 // --- PriceBoard系ページ（日経平均, FXなど）のスクレイピング関数 ---
 fn scrape_priceboard_data(document: &Html, code: &str) -> Result<StockData, Box<dyn std::error::Error>> {
-    let container_sel = Selector::parse("div[class*='PriceBoard__main']").unwrap();
+    let container_sel = parse_selector("div[class*='PriceBoard__main']")?;
     let container = match document.select(&container_sel).next() {
         Some(c) => c,
         None => return Err(format!("PriceBoard container not found for {}.", code).into())
     };
 
-    let name_sel = Selector::parse("header h2").unwrap();
+    let name_sel = parse_selector("header h2")?;
     let name = container
         .select(&name_sel)
         .next()
         .map(|e| e.text().collect::<String>().trim().to_string())
         .unwrap_or_default();
 
-    let price_sel =
-        Selector::parse("span[class*='PriceBoard__price'] span[class*='StyledNumber__value']")
-            .unwrap();
+    let price_sel = parse_selector("span[class*='PriceBoard__price'] span[class*='StyledNumber__value']")?;
     let price = container
         .select(&price_sel)
         .next()
         .map(|e| e.text().collect::<String>().trim().to_string())
         .unwrap_or_default();
 
-    let change_sel = Selector::parse("div[class*='PriceChangeLabel']").unwrap();
+    let change_sel = parse_selector("div[class*='PriceChangeLabel']")?;
     let combined_change = container
         .select(&change_sel)
         .next()
@@ -187,7 +177,7 @@ fn scrape_priceboard_data(document: &Html, code: &str) -> Result<StockData, Box<
         .unwrap_or_default();
     let (change_abs, change_pct) = parse_change_string(&combined_change);
 
-    let time_sel = Selector::parse("ul[class*='PriceBoard__times'] time").unwrap();
+    let time_sel = parse_selector("ul[class*='PriceBoard__times'] time")?;
     let update_time = container
         .select(&time_sel)
         .next()
@@ -204,30 +194,122 @@ fn scrape_priceboard_data(document: &Html, code: &str) -> Result<StockData, Box<
     })
 }
 
+// --- ラベル（"Bid" など）の近くから条件に合う span のテキストを探すヘルパー関数 ---
+// ラベルの親要素から最大 max_levels 階層まで遡り、近い階層のものを優先する
+fn find_span_text_near_label(
+    document: &Html,
+    label: &str,
+    max_levels: usize,
+    matches: impl Fn(&str) -> bool,
+) -> Option<String> {
+    let span_sel = Selector::parse("span").ok()?;
+    let label_node = document
+        .root_element()
+        .descendants()
+        .find(|node| node.value().as_text().map(|t| t.trim() == label).unwrap_or(false))?;
+
+    let mut ancestor = label_node.parent();
+    for _ in 0..max_levels {
+        let element = ElementRef::wrap(ancestor?)?;
+        for span in element.select(&span_sel) {
+            let text = span.text().collect::<String>().trim().to_string();
+            if text != label && matches(&text) {
+                return Some(text);
+            }
+        }
+        ancestor = element.parent();
+    }
+    None
+}
+
+// --- FXページ（USDJPY=FX など）のスクレイピング関数 ---
+// FXのボードは株価ページの PriceBoard__main とは構造が異なるため、
+// smp の FX 用ファインダーと同じく "Bid" / "Change" ラベルを目印にする
+fn scrape_fx_data(document: &Html, code: &str) -> Result<StockData, Box<dyn std::error::Error>> {
+    let is_number = |text: &str| !text.is_empty() && text.replace(',', "").parse::<f64>().is_ok();
+    let is_signed_number = |text: &str| {
+        let text = normalize_minus(text);
+        (text.starts_with('+') || text.starts_with('-')) && text.len() > 1 && is_number(&text[1..])
+    };
+
+    let price = find_span_text_near_label(document, "Bid", 4, is_number)
+        .ok_or_else(|| format!("FX rate (Bid) not found for {}.", code))?;
+    let change_abs = find_span_text_near_label(document, "Change", 4, is_signed_number).unwrap_or_default();
+    let update_time = find_span_text_near_label(document, "Bid", 6, |text| {
+        text.contains(':') && text.contains('(') && text.contains(')') && text.len() < 20
+    })
+    .unwrap_or_default();
+
+    let name_sel = parse_selector("h2")?;
+    let name = document
+        .select(&name_sel)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .unwrap_or_default();
+
+    Ok(StockData {
+        name,
+        code: code.to_string(),
+        price,
+        change_abs,
+        change_pct: String::new(), // FXページには前日比(%)が表示されない
+        update_time,
+    })
+}
+
+// --- FXコードの判定 ("=FX" と "=X" の両方を受け付ける) ---
+fn is_fx_code(code: &str) -> bool {
+    code.ends_with("=FX") || code.ends_with("=X")
+}
+
+// --- コードからURLを組み立てる関数 (FXは "=FX" に正規化) ---
+fn build_url(code: &str) -> String {
+    match code.strip_suffix("=X") {
+        Some(pair) => format!("https://finance.yahoo.co.jp/quote/{}=FX", pair),
+        None => format!("https://finance.yahoo.co.jp/quote/{}", code),
+    }
+}
+
 // --- 処理の振り分け関数 ---
 fn scrape_data(code: &str) -> Result<StockData, Box<dyn std::error::Error>> {
-    let url = format!("https://finance.yahoo.co.jp/quote/{}", code);
+    let url = build_url(code);
     let html = get(&url)?.text()?;
-    let document = Html::parse_document(&html);
+    scrape_html(&html, code)
+}
+
+// --- 取得済みHTMLをページの種類ごとのスクレイピング関数に振り分ける ---
+fn scrape_html(html: &str, code: &str) -> Result<StockData, Box<dyn std::error::Error>> {
+    let document = Html::parse_document(html);
 
     if code.starts_with('^') {
         scrape_index_data(&document, code)
-    } else if code.ends_with(".O") || code.ends_with("=X") {
+    } else if is_fx_code(code) {
+        scrape_fx_data(&document, code)
+    } else if code.ends_with(".O") {
         scrape_priceboard_data(&document, code)
     } else {
         scrape_stock_page_data(&document)
     }
 }
 
+// --- 複数銘柄をまとめて処理する関数 ---
+// 1銘柄の失敗（エラー）で残りの銘柄の処理が止まらないよう、結果は銘柄ごとに返す
+fn scrape_all<F>(codes: &[&str], scrape: F) -> Vec<(String, ScrapeResult)>
+where
+    F: Fn(&str) -> ScrapeResult,
+{
+    codes.iter().map(|code| (code.to_string(), scrape(code))).collect()
+}
+
 // --- メイン処理 ---
 fn main() {
-    let stock_codes = vec!["^DJI", "998407.O", "USDJPY=X", "6758.T", "8729.T", "5016.T", "4755.T"];
+    let stock_codes = vec!["^DJI", "998407.O", "USDJPY=FX", "6758.T", "8729.T", "5016.T", "4755.T"];
 
     println!("--- 複数銘柄の株価情報取得を開始 ---");
     println!();
 
-    for code in stock_codes {
-        match scrape_data(code) {
+    for (code, result) in scrape_all(&stock_codes, scrape_data) {
+        match result {
             Ok(data) => {
                 println!("--- {} ---", data.name);
                 println!("  🏷️ 銘柄コード : {}", data.code);
@@ -245,4 +327,54 @@ fn main() {
     }
 
     println!("--- 全ての処理が完了しました ---");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_url_for_fx_codes() {
+        assert!(is_fx_code("USDJPY=FX"));
+        assert!(is_fx_code("USDJPY=X"));
+        assert_eq!(build_url("USDJPY=FX"), "https://finance.yahoo.co.jp/quote/USDJPY=FX");
+        assert_eq!(build_url("USDJPY=X"), "https://finance.yahoo.co.jp/quote/USDJPY=FX");
+        assert_eq!(build_url("6758.T"), "https://finance.yahoo.co.jp/quote/6758.T");
+    }
+
+    #[test]
+    fn test_scrape_fx_data_usdjpy() {
+        let document = Html::parse_document(include_str!("../smp/fixtures/fx_usdjpy.html"));
+        let data = scrape_fx_data(&document, "USDJPY=X").unwrap();
+        assert_eq!(data.name, "米ドル/円");
+        assert_eq!(data.code, "USDJPY=X");
+        assert_eq!(data.price, "149.8520");
+        assert_eq!(data.change_abs, "+0.2130");
+        assert_eq!(data.update_time, "10:15(JST)");
+
+        assert!(scrape_fx_data(&Html::parse_document("<html><body></body></html>"), "USDJPY=X").is_err());
+    }
+
+    #[test]
+    fn test_parse_selector_reports_error_instead_of_panicking() {
+        assert!(parse_selector("div[class*='PriceBoard__main']").is_ok());
+        assert!(matches!(parse_selector("div[[broken"), Err(ScraperError::SelectorParse(_))));
+    }
+
+    #[test]
+    fn test_bad_code_does_not_abort_batch() {
+        let pages = |code: &str| -> ScrapeResult {
+            match code {
+                "6758.T" => scrape_html(include_str!("../smp/fixtures/stock_6758.html"), code),
+                "USDJPY=FX" => scrape_html(include_str!("../smp/fixtures/fx_usdjpy.html"), code),
+                _ => scrape_html("<html><body><p>ページが見つかりません</p></body></html>", code),
+            }
+        };
+
+        let results = scrape_all(&["6758.T", "9999.T", "USDJPY=FX"], pages);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].1.as_ref().unwrap().price, "3,456");
+        assert!(results[1].1.is_err());
+        assert_eq!(results[2].1.as_ref().unwrap().price, "149.8520");
+    }
+}
@@ -0,0 +1,62 @@
+//! Compares fetching a page through `ReqwestFetcher` with calling `reqwest::Client` directly, to
+//! check that going through the (statically dispatched) `Fetcher` trait adds no measurable cost.
+//! The page is served from a local keep-alive server, so no network access is needed.
+//!
+//! Run with `cargo bench --bench fetcher`.
+
+use auto_selecter1::fetcher::{Fetcher, ReqwestFetcher};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+/// Serves the Sony fixture for every request, keeping connections open, and returns its base URL.
+async fn serve_fixture() -> String {
+    let body = include_str!("../smp/fixtures/stock_6758.html");
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let response = response.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                // One read per request is enough for the small GETs sent here.
+                while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {
+                    if socket.write_all(response.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    format!("http://{}", addr)
+}
+
+fn bench_fetcher(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let url = format!("{}/quote/6758.T", runtime.block_on(serve_fixture()));
+
+    let client = reqwest::Client::new();
+    c.bench_function("reqwest_client_get", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let response = client.get(black_box(&url)).send().await.unwrap();
+                response.text().await.unwrap()
+            })
+        })
+    });
+
+    let fetcher = ReqwestFetcher::new();
+    c.bench_function("reqwest_fetcher_get", |b| {
+        b.iter(|| runtime.block_on(fetcher.get(black_box(&url))).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_fetcher);
+criterion_main!(benches);
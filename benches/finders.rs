@@ -0,0 +1,74 @@
+//! Times the heuristic finders on a large quote page, without any network access.
+//!
+//! Run with `cargo bench --bench finders`.
+
+use auto_selecter1::config::SearchFallback;
+use auto_selecter1::scraper_logic::{
+    find_name_dynamically, find_prominent_number, find_stock_change_percent_selector, find_stock_price_selector,
+    DEFAULT_MAX_PRICE_CANDIDATES,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use scraper::Html;
+
+/// The Sony quote page padded with news and ranking blocks, so the DOM is about as
+/// large as a real page (a few thousand elements) while staying deterministic.
+fn large_quote_page() -> String {
+    let page = include_str!("../smp/fixtures/stock_6758.html");
+    let mut filler = String::new();
+    for i in 0..400 {
+        filler.push_str(&format!(
+            "<section class=\"NewsList__item__{i}\"><h3>関連ニュース {i}</h3>\
+             <ul><li><a href=\"/news/{i}\">見出し {i}</a></li>\
+             <li><span class=\"StyledNumber__value__3rXW\">{price}</span></li>\
+             <li><time>{hour:02}:{minute:02}</time></li></ul></section>\n",
+            i = i,
+            price = 1000 + i,
+            hour = i % 24,
+            minute = i % 60,
+        ));
+    }
+    page.replace("  </main>", &format!("{}  </main>", filler))
+}
+
+/// A pathological page: one board holding a 2,000 × 10 table of numbers and no price container,
+/// so the price finder has nothing to stop early on.
+fn numeric_heavy_page() -> String {
+    let mut rows = String::new();
+    for row in 0..2000 {
+        rows.push_str("<tr>");
+        for col in 0..10 {
+            rows.push_str(&format!("<td class=\"Cell__value\">{}</td>", 1000 + row * 10 + col));
+        }
+        rows.push_str("</tr>\n");
+    }
+    format!("<html><body><div class=\"Board\"><h2>ソニーグループ(株)</h2><table>{}</table></div></body></html>", rows)
+}
+
+fn bench_finders(c: &mut Criterion) {
+    let document = Html::parse_document(&large_quote_page());
+
+    c.bench_function("find_name_dynamically", |b| {
+        b.iter(|| block_on(find_name_dynamically(black_box(&document))).unwrap())
+    });
+    c.bench_function("find_stock_price_selector", |b| {
+        b.iter(|| {
+            block_on(find_stock_price_selector(black_box(&document), "ソニーグループ(株)", "6758")).unwrap()
+        })
+    });
+    // Library counterpart of `find_percent_selector_near_zenjitsuhi` from the original scraper.
+    c.bench_function("find_stock_change_percent_selector", |b| {
+        b.iter(|| block_on(find_stock_change_percent_selector(black_box(&document), "前日比", SearchFallback::Off)).unwrap())
+    });
+
+    let numeric_document = Html::parse_document(&numeric_heavy_page());
+    c.bench_function("find_prominent_number_capped", |b| {
+        b.iter(|| find_prominent_number(black_box(numeric_document.root_element()), "6758", DEFAULT_MAX_PRICE_CANDIDATES))
+    });
+    c.bench_function("find_prominent_number_uncapped", |b| {
+        b.iter(|| find_prominent_number(black_box(numeric_document.root_element()), "6758", usize::MAX))
+    });
+}
+
+criterion_group!(benches, bench_finders);
+criterion_main!(benches);
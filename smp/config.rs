@@ -0,0 +1,246 @@
+use crate::cookies::Cookie;
+use crate::fetcher::{AdaptiveTimeout, ConditionalCache};
+use crate::models::{ScraperError, StockData};
+use crate::scraper_logic;
+use crate::selectors::SelectorCache;
+use crate::value_parser::DecimalPrecision;
+use crate::CodeType;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A place the price finder anchors its search on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriceAnchor {
+    /// The instrument's name as found on the page.
+    Name,
+    /// An element whose whole text equals this label, e.g. "前日比" or "現在値".
+    Label(String),
+}
+
+impl PriceAnchor {
+    /// The default cascade: "前日比", the company name, "現在値", then "株価".
+    pub fn defaults() -> Vec<PriceAnchor> {
+        vec![
+            PriceAnchor::Label("前日比".to_string()),
+            PriceAnchor::Name,
+            PriceAnchor::Label("現在値".to_string()),
+            PriceAnchor::Label("株価".to_string()),
+        ]
+    }
+}
+
+/// Where the change finders look when nothing matches in the area around their anchor
+/// (the anchor's 8th ancestor).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFallback {
+    /// Give up after the scoped search.
+    Off,
+    /// Search again within this many ancestors of the anchor.
+    Ancestors(usize),
+    /// Search the whole document.
+    Document,
+}
+
+/// Settings shared by the library-level scraping functions.
+///
+/// `max_concurrent` and `requests_per_second` are independent limits that are both
+/// acquired before every fetch: the concurrency limit caps how many requests are
+/// in flight at once, while the rate limit caps how often a new request may be
+/// dispatched. With `max_concurrent = 10` and `requests_per_second = Some(2.0)`,
+/// at most 2 requests start per second even though up to 10 may be outstanding.
+#[derive(Debug, Clone)]
+pub struct ScraperConfig {
+    /// Maximum number of requests in flight at the same time (at least 1).
+    pub max_concurrent: usize,
+    /// Maximum number of requests started per second; `None` means unlimited, as does a rate that
+    /// isn't a positive number.
+    pub requests_per_second: Option<f64>,
+    /// Whether redirects are followed. When disabled, a redirect is reported as
+    /// `ScraperError::UnexpectedRedirect`, which shows when Yahoo bounces a code to a search
+    /// or consent page instead of a quote page.
+    pub follow_redirects: bool,
+    /// Maximum number of redirects followed when `follow_redirects` is set; `None` uses reqwest's default (10).
+    pub max_redirects: Option<usize>,
+    /// Cookies the client starts with, e.g. from a logged-in session (see `cookies::load_cookie_file`).
+    /// Cookies set by responses are kept as well, so they carry over between requests of a batch.
+    pub cookies: Vec<Cookie>,
+    /// HTTP timeout of a single request, from connecting until the body has been read; `None` uses
+    /// reqwest's default (no timeout). Retries and later pages of a code each get their own.
+    pub timeout: Option<Duration>,
+    /// Wall-clock budget for everything done for one code (all of its requests and parsing);
+    /// `None` means no limit. Unlike `timeout` this bounds the whole scrape.
+    pub timeout_per_code: Option<Duration>,
+    /// Bounds every request by a timeout derived from recent latencies (see `AdaptiveTimeout`);
+    /// `None` leaves requests bounded only by `timeout` and `timeout_per_code`.
+    pub adaptive_timeout: Option<Arc<AdaptiveTimeout>>,
+    /// Anchors the price finder tries in order until one yields a price.
+    pub price_anchors: Vec<PriceAnchor>,
+    /// Forces every code to be handled as this type instead of classifying it from its shape,
+    /// for instruments the automatic classification gets wrong.
+    pub code_type: Option<CodeType>,
+    /// How many times a failed page fetch is retried; only retryable errors
+    /// (see `ScraperError::is_retryable`) are. 0 disables retrying.
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled after each further attempt.
+    pub retry_backoff: Duration,
+    /// User-supplied selectors by field name ("code", "price", "change", "change_percent",
+    /// "update_time"). They take precedence over every other source; see `selectors::FieldSelectors`.
+    pub selector_overrides: HashMap<String, String>,
+    /// Keeps selectors discovered on one page for later pages of the same market; `None` disables caching.
+    pub selector_cache: Option<Arc<SelectorCache>>,
+    /// Try the hardcoded selectors of Yahoo's layout (see `selectors::static_selector`) before the
+    /// dynamic finders' own. On by default; off leaves each field to the finders.
+    pub static_selectors: bool,
+    /// When set, each page's ETag / Last-Modified and result are kept, and the next scrape of the
+    /// same code makes a conditional request that reuses the result on a 304 (see `ConditionalCache`).
+    pub conditional_cache: Option<Arc<ConditionalCache>>,
+    /// Rewrites the price, change and change percent of every record with a fixed number of decimals
+    /// per instrument type (see `ValueParser::normalize_decimals`), before `post_process` runs.
+    /// `None` (the default) keeps the strings as scraped.
+    pub decimal_precision: Option<DecimalPrecision>,
+    /// Called on every successfully scraped record before it is returned, e.g. to convert
+    /// units or add a computed value. It runs after the page has been fetched, parsed and all
+    /// fields filled in, and isn't called for failed codes.
+    pub post_process: Option<fn(&mut StockData)>,
+    /// Accepted lengths of a stock code, when validating the requested code and when looking for
+    /// the code on the page. Defaults to 4; use `4..=5` to also accept 5-digit codes.
+    pub code_lengths: RangeInclusive<usize>,
+    /// Proxy every request goes through, e.g. "http://proxy.example:8080"; `None` uses reqwest's
+    /// default (the system proxy settings).
+    pub proxy: Option<String>,
+    /// `User-Agent` header sent with every request; `None` uses reqwest's default.
+    pub user_agent: Option<String>,
+    /// A second host serving the same quote pages, e.g. "https://m.finance.yahoo.co.jp", tried when
+    /// the page from finance.yahoo.co.jp has no price (or can't be scraped at all, as with a challenge
+    /// page), even after the one refetch. The path is kept; only the scheme and host are replaced.
+    /// The fields the primary page lacks are filled in from the secondary one.
+    ///
+    /// The secondary may use a different layout: the static, cached and user selectors aren't meant
+    /// for it, so its values come from the dynamic finders alone, and selectors found there aren't
+    /// cached. Its links (e.g. `image_url`) are resolved against the secondary's URL.
+    pub fallback_base_url: Option<String>,
+    /// Second pass of the change, change-percent and combined-change finders when the
+    /// scoped search finds nothing. A widened search that succeeds is logged (at info level).
+    pub search_fallback: SearchFallback,
+    /// Keep the combined change text ("+120(+1.52%)") in `StockData::change_raw`. Off by default,
+    /// since it costs an extra lookup per page and repeats what `change` and `change_percent` hold.
+    pub include_raw_change: bool,
+    /// Collect the codes listed under 関連銘柄 (related stocks) into `StockData::related_codes`.
+    pub include_related: bool,
+    /// When a stock's quote page doesn't show the margin balances (信用買残 / 信用売残), fetch them
+    /// from its 信用残 page (`/quote/<code>.T/margin`). Costs one more request per such stock.
+    pub with_margin: bool,
+    /// Read the latest prints of a stock's 歩み値 (time and sales) table into `StockData::recent_ticks`.
+    pub include_ticks: bool,
+    /// How many 歩み値 rows `include_ticks` keeps at most.
+    pub max_ticks: usize,
+    /// When no price is found at all, not even after the refetch or on `fallback_base_url`, fall
+    /// back to the most price-like number on the whole page
+    /// (see `scraper_logic::find_price_like_number`), tagged `Confidence::Guess`. Off by default,
+    /// since the guess can be wrong; it keeps the output non-empty when the layout breaks badly.
+    pub aggressive: bool,
+    /// Read a stock's PTS (after-hours) quote into `StockData::pts_price` and `StockData::pts_change`.
+    pub with_pts: bool,
+    /// Keep the markup of the element each field was read from in `StockData::debug_elements`,
+    /// for bug reports about a wrong match. Off by default; it makes every record much larger.
+    pub debug_elements: bool,
+}
+
+impl Default for ScraperConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            requests_per_second: None,
+            follow_redirects: true,
+            max_redirects: None,
+            cookies: Vec::new(),
+            timeout: None,
+            timeout_per_code: None,
+            adaptive_timeout: None,
+            price_anchors: PriceAnchor::defaults(),
+            code_type: None,
+            max_retries: 0,
+            retry_backoff: Duration::from_millis(500),
+            selector_overrides: HashMap::new(),
+            selector_cache: None,
+            static_selectors: true,
+            conditional_cache: None,
+            decimal_precision: None,
+            post_process: None,
+            code_lengths: scraper_logic::DEFAULT_CODE_LENGTHS,
+            proxy: None,
+            user_agent: None,
+            fallback_base_url: None,
+            search_fallback: SearchFallback::Document,
+            include_raw_change: false,
+            include_related: false,
+            with_margin: false,
+            include_ticks: false,
+            max_ticks: 10,
+            with_pts: false,
+            aggressive: false,
+            debug_elements: false,
+        }
+    }
+}
+
+impl ScraperConfig {
+    /// The default settings, with these overridden by environment variables when they are set:
+    ///
+    /// - `AUTO_SELECTER_TIMEOUT_MS`: `timeout` (the HTTP timeout), in milliseconds
+    /// - `AUTO_SELECTER_PROXY`: `proxy`
+    /// - `AUTO_SELECTER_USER_AGENT`: `user_agent`
+    ///
+    /// Precedence is command-line flag > environment variable > default: callers such as the
+    /// `smp` binary start from this and then apply the flags that were given.
+    pub fn from_env() -> Result<Self, ScraperError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `from_env` with the variables looked up through `var`.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ScraperError> {
+        let var = |name: &str| var(name).map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let mut config = Self::default();
+        if let Some(value) = var("AUTO_SELECTER_TIMEOUT_MS") {
+            let millis = value
+                .parse::<u64>()
+                .ok()
+                .filter(|ms| *ms > 0)
+                .ok_or_else(|| ScraperError::InvalidConfig(format!("AUTO_SELECTER_TIMEOUT_MS={}: expected a positive number of milliseconds", value)))?;
+            config.timeout = Some(Duration::from_millis(millis));
+        }
+        config.proxy = var("AUTO_SELECTER_PROXY");
+        config.user_agent = var("AUTO_SELECTER_USER_AGENT");
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vars() {
+        let vars: HashMap<&str, &str> = [
+            ("AUTO_SELECTER_TIMEOUT_MS", "2500"),
+            ("AUTO_SELECTER_PROXY", "http://proxy.example:8080"),
+            ("AUTO_SELECTER_USER_AGENT", " "),
+        ]
+        .into_iter()
+        .collect();
+        let config = ScraperConfig::from_vars(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(config.timeout_per_code, None);
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy.example:8080"));
+        assert_eq!(config.user_agent, None);
+
+        let config = ScraperConfig::from_vars(|_| None).unwrap();
+        assert_eq!(config.timeout, None);
+        assert_eq!(config.proxy, None);
+
+        let bad = ScraperConfig::from_vars(|name| (name == "AUTO_SELECTER_TIMEOUT_MS").then(|| "soon".to_string()));
+        assert!(matches!(bad, Err(ScraperError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::models::ScraperError;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One entry of a Netscape-format cookie file (as written by curl or browser export tools).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    /// Domain the cookie belongs to, without the leading dot.
+    pub domain: String,
+    /// Whether the cookie is also sent to subdomains of `domain`.
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    /// Expiry as a Unix timestamp; 0 means a session cookie.
+    pub expires: u64,
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    /// Returns true when the cookie has an expiry that already passed.
+    pub fn is_expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.expires != 0 && self.expires < now
+    }
+
+    /// The URL the cookie is registered against in a cookie jar.
+    pub fn url(&self) -> String {
+        let scheme = if self.secure { "https" } else { "http" };
+        format!("{}://{}{}", scheme, self.domain, self.path)
+    }
+
+    /// The cookie rendered as a `Set-Cookie` header value.
+    pub fn to_set_cookie(&self) -> String {
+        let mut header = format!("{}={}; Path={}", self.name, self.value, self.path);
+        if self.include_subdomains {
+            header.push_str(&format!("; Domain={}", self.domain));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        header
+    }
+}
+
+/// Reads and parses a Netscape-format cookie file.
+pub fn load_cookie_file(path: &Path) -> Result<Vec<Cookie>, ScraperError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        ScraperError::InvalidCookieFile(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    parse_netscape_cookies(&text)
+        .map_err(|e| ScraperError::InvalidCookieFile(format!("{}: {}", path.display(), e)))
+}
+
+/// Parses the contents of a Netscape-format cookie file.
+///
+/// Each entry is one line of seven tab-separated fields:
+/// domain, include-subdomains flag, path, secure flag, expiry, name and value.
+/// Blank lines and `#` comments are skipped; the `#HttpOnly_` domain prefix written by curl is accepted.
+pub fn parse_netscape_cookies(text: &str) -> Result<Vec<Cookie>, String> {
+    let mut cookies = Vec::new();
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim_end_matches('\r');
+        let line = match line.strip_prefix("#HttpOnly_") {
+            Some(rest) => rest,
+            None if line.trim().is_empty() || line.starts_with('#') => continue,
+            None => line,
+        };
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(format!(
+                "line {}: expected 7 tab-separated fields, found {}",
+                line_number,
+                fields.len()
+            ));
+        }
+
+        let domain = fields[0].trim_start_matches('.');
+        if domain.is_empty() {
+            return Err(format!("line {}: empty domain", line_number));
+        }
+        let include_subdomains = parse_flag(fields[1])
+            .ok_or_else(|| format!("line {}: invalid include-subdomains flag {:?}", line_number, fields[1]))?;
+        if !fields[2].starts_with('/') {
+            return Err(format!("line {}: path must start with '/', found {:?}", line_number, fields[2]));
+        }
+        let secure = parse_flag(fields[3])
+            .ok_or_else(|| format!("line {}: invalid secure flag {:?}", line_number, fields[3]))?;
+        let expires = fields[4]
+            .parse::<u64>()
+            .map_err(|_| format!("line {}: invalid expiry {:?}", line_number, fields[4]))?;
+        if fields[5].is_empty() {
+            return Err(format!("line {}: empty cookie name", line_number));
+        }
+
+        cookies.push(Cookie {
+            domain: domain.to_string(),
+            include_subdomains,
+            path: fields[2].to_string(),
+            secure,
+            expires,
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+        });
+    }
+    Ok(cookies)
+}
+
+fn parse_flag(field: &str) -> Option<bool> {
+    match field {
+        "TRUE" => Some(true),
+        "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_netscape_cookies() {
+        let text = "# Netscape HTTP Cookie File\n\
+                    \n\
+                    .yahoo.co.jp\tTRUE\t/\tTRUE\t0\tB\tabc123\n\
+                    #HttpOnly_finance.yahoo.co.jp\tFALSE\t/quote\tFALSE\t4102444800\tT\tz=1&a=2\n";
+        let cookies = parse_netscape_cookies(text).unwrap();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].domain, "yahoo.co.jp");
+        assert!(cookies[0].include_subdomains);
+        assert!(cookies[0].secure);
+        assert_eq!(cookies[0].name, "B");
+        assert_eq!(cookies[0].value, "abc123");
+        assert_eq!(cookies[1].domain, "finance.yahoo.co.jp");
+        assert_eq!(cookies[1].path, "/quote");
+        assert_eq!(cookies[1].expires, 4102444800);
+        assert_eq!(cookies[1].value, "z=1&a=2");
+        assert!(!cookies[1].is_expired());
+    }
+
+    #[test]
+    fn test_parse_netscape_cookies_rejects_malformed_lines() {
+        let too_few = ".yahoo.co.jp\tTRUE\t/\tTRUE\t0\tB\n";
+        assert!(parse_netscape_cookies(too_few).unwrap_err().starts_with("line 1:"));
+
+        let bad_flag = "# header\n.yahoo.co.jp\tyes\t/\tTRUE\t0\tB\tv\n";
+        assert!(parse_netscape_cookies(bad_flag).unwrap_err().starts_with("line 2:"));
+
+        let bad_expiry = ".yahoo.co.jp\tTRUE\t/\tTRUE\tsoon\tB\tv\n";
+        assert!(parse_netscape_cookies(bad_expiry).unwrap_err().contains("expiry"));
+    }
+}
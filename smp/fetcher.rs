@@ -0,0 +1,901 @@
+use crate::config::ScraperConfig;
+use crate::models::{ScraperError, StockData};
+use reqwest::cookie::Jar;
+use reqwest::redirect::Policy;
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A fetched page: the HTTP status, the redirect target (if any) and the body text.
+#[derive(Debug, Clone, Default)]
+pub struct Response {
+    pub status: u16,
+    /// The `Location` header of a redirect response.
+    pub location: Option<String>,
+    /// The URL the page was served from after following redirects; `None` when the fetcher doesn't know it.
+    pub final_url: Option<String>,
+    /// The `ETag` and `Last-Modified` headers, for conditional requests on the next fetch.
+    pub validators: Validators,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.status)
+    }
+
+    /// 304: the page hasn't changed since the fetch the request's validators came from.
+    pub fn is_not_modified(&self) -> bool {
+        self.status == 304
+    }
+}
+
+/// Cache validators of a fetched page, sent back as `If-None-Match` / `If-Modified-Since`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// The HTTP layer used by the scrapers.
+/// Abstracted so that status/retry handling can be tested with canned responses.
+///
+/// The scrape functions are generic over it (`&impl Fetcher`) rather than taking a `dyn Fetcher`,
+/// so the batch path calls the fetcher directly, without boxing a future per request. Implementors
+/// can still write the methods as `async fn`; the returned futures must be `Send`.
+pub trait Fetcher: Send + Sync {
+    fn get(&self, url: &str) -> impl Future<Output = Result<Response, ScraperError>> + Send;
+
+    /// Like `get`, but asks the server to answer 304 Not Modified when the page still matches
+    /// `validators`. Fetchers that can't send them make a plain `get`.
+    fn get_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> impl Future<Output = Result<Response, ScraperError>> + Send {
+        let _ = validators;
+        self.get(url)
+    }
+
+    /// Like `get`, but skips any copy of the page the fetcher keeps, for fetching a page again
+    /// because the copy at hand looked incomplete. Fetchers without a cache make a plain `get`.
+    fn get_fresh(&self, url: &str) -> impl Future<Output = Result<Response, ScraperError>> + Send {
+        self.get(url)
+    }
+}
+
+/// A fetcher whose `get` is the wrapped fetcher's `get_fresh`, to make every request of a helper
+/// such as `get_with_retry` bypass a cache.
+pub(crate) struct Fresh<'a, F>(pub &'a F);
+
+impl<F: Fetcher> Fetcher for Fresh<'_, F> {
+    async fn get(&self, url: &str) -> Result<Response, ScraperError> {
+        self.0.get_fresh(url).await
+    }
+}
+
+/// The last result and validators of each page fetched with `ScraperConfig::conditional_cache` set,
+/// keyed by URL. When polling, the next fetch of a page is conditional, and a 304 answer reuses the
+/// stored result instead of downloading and scraping the page again.
+#[derive(Debug, Default)]
+pub struct ConditionalCache {
+    entries: Mutex<HashMap<String, CachedResult>>,
+}
+
+/// A result kept by `ConditionalCache`.
+#[derive(Debug, Clone)]
+pub struct CachedResult {
+    pub validators: Validators,
+    pub data: StockData,
+}
+
+impl ConditionalCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, url: &str) -> Option<CachedResult> {
+        self.entries.lock().unwrap().get(url).cloned()
+    }
+
+    /// Keeps `data` for `url`; pages served without validators can't be revalidated and aren't kept.
+    pub fn insert(&self, url: &str, validators: Validators, data: StockData) {
+        if !validators.is_empty() {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), CachedResult { validators, data });
+        }
+    }
+}
+
+/// A per-request timeout that follows how fast the site currently answers: `factor` times an
+/// exponential moving average of recent successful fetch latencies, clamped to `min..=max`.
+/// Until the first success is recorded the timeout is `max`.
+///
+/// Shared through `ScraperConfig::adaptive_timeout`, so every fetch of a batch (and of later
+/// batches using the same config) feeds the same average.
+#[derive(Debug)]
+pub struct AdaptiveTimeout {
+    pub factor: f64,
+    pub min: Duration,
+    pub max: Duration,
+    average: Mutex<Option<Duration>>,
+}
+
+impl AdaptiveTimeout {
+    /// Weight of the newest latency in the moving average.
+    const SMOOTHING: f64 = 0.2;
+
+    /// A timeout of 4× the average latency, kept within `min..=max`.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            factor: 4.0,
+            min,
+            max: max.max(min),
+            average: Mutex::new(None),
+        }
+    }
+
+    /// The timeout for the next request.
+    pub fn current(&self) -> Duration {
+        match *self.average.lock().unwrap() {
+            Some(average) => average.mul_f64(self.factor).clamp(self.min, self.max),
+            None => self.max,
+        }
+    }
+
+    /// Feeds the latency of a successful fetch into the average.
+    pub fn record(&self, latency: Duration) {
+        let mut average = self.average.lock().unwrap();
+        *average = Some(match *average {
+            Some(previous) => previous.mul_f64(1.0 - Self::SMOOTHING) + latency.mul_f64(Self::SMOOTHING),
+            None => latency,
+        });
+    }
+}
+
+impl Default for AdaptiveTimeout {
+    /// Between 1 and 30 seconds.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(30))
+    }
+}
+
+/// Whether this build has a TLS backend for reqwest (the crate's `native-tls` or `rustls-tls` feature).
+///
+/// This checks this crate's features, not reqwest's: reqwest doesn't report its backends, and `cfg!`
+/// only sees the features of the crate it is in. Each of ours turns on a reqwest backend, so `true`
+/// is always right. With both of ours off, reqwest may still have a backend that another crate in
+/// the build enabled (Cargo unifies features); https is refused then all the same, and enabling
+/// `rustls-tls` or `native-tls` here as well lifts that.
+const TLS_ENABLED: bool = cfg!(any(feature = "native-tls", feature = "rustls-tls"));
+
+fn require_tls(tls_enabled: bool) -> Result<(), ScraperError> {
+    if tls_enabled {
+        Ok(())
+    } else {
+        Err(ScraperError::InvalidConfig(
+            "TLS backend not enabled; enable the rustls-tls or native-tls feature".to_string(),
+        ))
+    }
+}
+
+/// The real fetcher, backed by a shared `reqwest::Client`.
+///
+/// Yahoo is only served over https, so the crate must be built with a TLS backend: the
+/// `native-tls` or `rustls-tls` feature (both are on by default). Without one, `with_config`
+/// fails and so does every https request, with `ScraperError::InvalidConfig` saying so.
+#[derive(Default)]
+pub struct ReqwestFetcher {
+    client: reqwest::Client,
+}
+
+impl ReqwestFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a fetcher whose client applies the redirect policy, initial cookies, proxy, user agent
+    /// and HTTP timeout from `config`.
+    /// The client keeps a cookie jar, so cookies set by one response are sent with later requests.
+    pub fn with_config(config: &ScraperConfig) -> Result<Self, ScraperError> {
+        Self::build(config, TLS_ENABLED)
+    }
+
+    fn build(config: &ScraperConfig, tls_enabled: bool) -> Result<Self, ScraperError> {
+        require_tls(tls_enabled)?;
+        let policy = if config.follow_redirects {
+            Policy::limited(config.max_redirects.unwrap_or(10))
+        } else {
+            Policy::none()
+        };
+        let jar = Jar::default();
+        for cookie in config.cookies.iter().filter(|c| !c.is_expired()) {
+            let url = cookie.url().parse::<reqwest::Url>().map_err(|e| {
+                ScraperError::InvalidCookieFile(format!("Invalid domain {:?} for cookie {}: {}", cookie.domain, cookie.name, e))
+            })?;
+            jar.add_cookie_str(&cookie.to_set_cookie(), &url);
+        }
+        let mut builder = reqwest::Client::builder().redirect(policy).cookie_provider(Arc::new(jar));
+        if let Some(proxy) = &config.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| ScraperError::InvalidConfig(format!("Invalid proxy {:?}: {}", proxy, e)))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(user_agent) = &config.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| ScraperError::Other(format!("Failed to build HTTP client: {}", e)))?;
+        Ok(Self { client })
+    }
+}
+
+impl ReqwestFetcher {
+    async fn send(&self, request: reqwest::RequestBuilder, url: &str) -> Result<Response, ScraperError> {
+        if url.starts_with("https://") {
+            require_tls(TLS_ENABLED)?;
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| request_error(e, format!("Request to {} failed", url)))?;
+        let status = response.status().as_u16();
+        let header = |name: reqwest::header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let location = header(reqwest::header::LOCATION);
+        let final_url = Some(response.url().to_string());
+        let validators = Validators {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        };
+        let body = response
+            .text()
+            .await
+            .map_err(|e| request_error(e, format!("Failed to read body from {}", url)))?;
+        Ok(Response {
+            status,
+            location,
+            final_url,
+            validators,
+            body,
+        })
+    }
+}
+
+impl Fetcher for ReqwestFetcher {
+    async fn get(&self, url: &str) -> Result<Response, ScraperError> {
+        self.send(self.client.get(url), url).await
+    }
+
+    async fn get_conditional(&self, url: &str, validators: &Validators) -> Result<Response, ScraperError> {
+        let mut request = self.client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        self.send(request, url).await
+    }
+}
+
+/// Whether a failed request may succeed when tried again: timeouts, 5xx statuses, connections
+/// refused (e.g. while the server restarts), reset or aborted by the peer, and connections closed
+/// before the response or its body was complete. DNS and TLS failures and invalid URLs fail the
+/// same way every time.
+fn is_retryable(err: &reqwest::Error) -> bool {
+    if err.is_timeout() {
+        return true;
+    }
+    if let Some(status) = err.status() {
+        return status.is_server_error();
+    }
+    if err.is_builder() || err.is_redirect() {
+        return false;
+    }
+    // Past the connect step the request went out and the exchange was cut short, e.g. hyper's
+    // "connection closed before message completed", or the body stopped before its length.
+    if err.is_body() || (err.is_request() && !err.is_connect()) {
+        return true;
+    }
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io_error.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+            );
+        }
+        source = cause.source();
+    }
+    false
+}
+
+/// Wraps a reqwest error as `ScraperError::Network` when `is_retryable` allows another attempt,
+/// and as `ScraperError::Request` otherwise.
+fn request_error(err: reqwest::Error, context: String) -> ScraperError {
+    let message = format!("{}: {}", context, err);
+    if is_retryable(&err) {
+        ScraperError::Network(message)
+    } else {
+        ScraperError::Request(message)
+    }
+}
+
+/// Wraps another fetcher with an on-disk cache of pages, for re-running without hitting the server.
+/// A successful page is written to a file in `dir` named after its URL; later requests for the same
+/// URL are served from that file while it is younger than `max_age` (any age when `None`).
+/// Cache misses go to the wrapped fetcher.
+pub struct CachingFetcher<F> {
+    inner: F,
+    dir: PathBuf,
+    max_age: Option<Duration>,
+}
+
+impl<F: Fetcher> CachingFetcher<F> {
+    /// Creates `dir` if needed.
+    pub fn new(inner: F, dir: &Path, max_age: Option<Duration>) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            inner,
+            dir: dir.to_path_buf(),
+            max_age,
+        })
+    }
+
+    /// The cache file of `url`, named after its host, path and query so that the quote page, its
+    /// `/margin` and `/history?page=N` pages and the same page on another host don't share a file.
+    /// Bytes other than ASCII letters, digits, `.` and `-` are written as `_XX` hex, which keeps
+    /// the name unique per URL. `None` (no caching) when the name would be too long for a file.
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let without_fragment = without_scheme.split('#').next().unwrap_or_default();
+        let mut name = String::new();
+        for byte in without_fragment.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || (byte == b'.' && !name.is_empty()) {
+                name.push(byte as char);
+            } else {
+                name.push_str(&format!("_{:02X}", byte));
+            }
+        }
+        if name.is_empty() || name.len() > 200 {
+            return None;
+        }
+        Some(self.dir.join(format!("{}.html", name)))
+    }
+
+    fn read_fresh(&self, path: &Path) -> Option<String> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if self.max_age.is_some_and(|max_age| age >= max_age) {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    /// Serves `url` from its cache file when fresh (and `use_cache` is set), otherwise fetches it
+    /// through the inner fetcher (conditionally when `validators` are given) and stores a successful page.
+    async fn fetch(&self, url: &str, validators: Option<&Validators>, use_cache: bool) -> Result<Response, ScraperError> {
+        let path = self.cache_path(url);
+        if let Some(body) = path.as_deref().filter(|_| use_cache).and_then(|path| self.read_fresh(path)) {
+            return Ok(Response {
+                status: 200,
+                body,
+                ..Default::default()
+            });
+        }
+        let response = match validators {
+            Some(validators) => self.inner.get_conditional(url, validators).await?,
+            None => self.inner.get(url).await?,
+        };
+        if let Some(path) = path.filter(|_| response.is_success()) {
+            // A page that can't be stored is simply fetched again next time.
+            let _ = fs::write(&path, &response.body);
+        }
+        Ok(response)
+    }
+}
+
+impl<F: Fetcher> Fetcher for CachingFetcher<F> {
+    async fn get(&self, url: &str) -> Result<Response, ScraperError> {
+        self.fetch(url, None, true).await
+    }
+
+    async fn get_conditional(&self, url: &str, validators: &Validators) -> Result<Response, ScraperError> {
+        self.fetch(url, Some(validators), true).await
+    }
+
+    /// Fetches the page from the inner fetcher and replaces its cache file.
+    async fn get_fresh(&self, url: &str) -> Result<Response, ScraperError> {
+        self.fetch(url, None, false).await
+    }
+}
+
+/// Fetches `url`, treating an error status as a `ScraperError` (redirects are returned as-is),
+/// and retries failures that `ScraperError::is_retryable` accepts up to `config.max_retries` times.
+pub async fn get_with_retry(fetcher: &impl Fetcher, url: &str, config: &ScraperConfig) -> Result<Response, ScraperError> {
+    get_with_retry_conditional(fetcher, url, None, config).await
+}
+
+/// `get_with_retry`, making conditional requests when `validators` are given
+/// (a 304 answer is returned as-is, like a redirect).
+///
+/// With `config.adaptive_timeout` set, each attempt is bounded by its current value (a request
+/// that runs out is a retryable `ScraperError::Network`), and the latency of each answered
+/// request is recorded.
+pub async fn get_with_retry_conditional(
+    fetcher: &impl Fetcher,
+    url: &str,
+    validators: Option<&Validators>,
+    config: &ScraperConfig,
+) -> Result<Response, ScraperError> {
+    let mut backoff = config.retry_backoff;
+    let mut attempt = 0;
+    loop {
+        let request = async {
+            match validators {
+                Some(validators) => fetcher.get_conditional(url, validators).await,
+                None => fetcher.get(url).await,
+            }
+        };
+        let fetched = match &config.adaptive_timeout {
+            Some(adaptive) => {
+                let limit = adaptive.current();
+                let started = tokio::time::Instant::now();
+                match tokio::time::timeout(limit, request).await {
+                    Ok(fetched) => {
+                        if fetched.is_ok() {
+                            adaptive.record(started.elapsed());
+                        }
+                        fetched
+                    }
+                    Err(_) => Err(ScraperError::Network(format!("{} timed out after {:?}", url, limit))),
+                }
+            }
+            None => request.await,
+        };
+        let result = match fetched {
+            Ok(response) if response.is_success() || response.is_redirect() => Ok(response),
+            Ok(response) => Err(ScraperError::from_status(response.status, url)),
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e) if e.is_retryable() && attempt < config.max_retries => {
+                attempt += 1;
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Mutex;
+
+    /// Serves canned responses per URL and records every requested URL.
+    /// Queued responses are returned in order; the last one keeps being returned once the queue is drained.
+    #[derive(Default)]
+    pub struct MockFetcher {
+        responses: Mutex<HashMap<String, VecDeque<Result<Response, ScraperError>>>>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl MockFetcher {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Queues a page with the given status and body for `url`.
+        pub fn with_page(self, url: &str, status: u16, body: &str) -> Self {
+            self.push(
+                url,
+                Ok(Response {
+                    status,
+                    body: body.to_string(),
+                    ..Default::default()
+                }),
+            );
+            self
+        }
+
+        /// Queues an arbitrary result (e.g. a network error) for `url`.
+        pub fn push(&self, url: &str, result: Result<Response, ScraperError>) {
+            self.responses
+                .lock()
+                .unwrap()
+                .entry(url.to_string())
+                .or_default()
+                .push_back(result);
+        }
+
+        /// Number of times `url` was requested.
+        pub fn request_count(&self, url: &str) -> usize {
+            self.requests.lock().unwrap().iter().filter(|u| *u == url).count()
+        }
+    }
+
+    impl Fetcher for MockFetcher {
+        async fn get(&self, url: &str) -> Result<Response, ScraperError> {
+            self.requests.lock().unwrap().push(url.to_string());
+            let mut responses = self.responses.lock().unwrap();
+            match responses.get_mut(url) {
+                Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+                Some(queue) if !queue.is_empty() => queue[0].clone(),
+                _ => Err(ScraperError::Other(format!("No mock response for {}", url))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves a single redirect response on a local port and returns its base URL.
+    async fn serve_redirect_once() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            let response = "HTTP/1.1 302 Found\r\nLocation: /search?query=9999\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    /// Answers each connection with `responses[i]` (extra headers) and echoes the request head as the body.
+    async fn serve_echo(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for extra_headers in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 2048];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    extra_headers,
+                    request.len(),
+                    request
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_fetcher_sends_and_keeps_cookies() {
+        let base = serve_echo(vec!["Set-Cookie: session=xyz; Path=/\r\n", ""]).await;
+        let config = ScraperConfig {
+            cookies: crate::cookies::parse_netscape_cookies("127.0.0.1\tFALSE\t/\tFALSE\t0\tB\tabc123\n").unwrap(),
+            ..Default::default()
+        };
+        let fetcher = ReqwestFetcher::with_config(&config).unwrap();
+
+        let first = fetcher.get(&format!("{}/quote/6758.T", base)).await.unwrap();
+        assert!(first.body.contains("B=abc123"));
+        assert!(!first.body.contains("session=xyz"));
+
+        let second = fetcher.get(&format!("{}/quote/7203.T", base)).await.unwrap();
+        assert!(second.body.contains("B=abc123"));
+        assert!(second.body.contains("session=xyz"));
+    }
+
+    /// Answers a request carrying `If-None-Match: "v1"` with 304 and any other one with the page and that ETag.
+    async fn serve_etag(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for _ in 0..requests {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 2048];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Fri, 27 Dec 2024 06:00:00 GMT\r\nContent-Length: 4\r\nConnection: close\r\n\r\npage".to_string()
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_adaptive_timeout_follows_average_latency() {
+        let timeout = AdaptiveTimeout::new(Duration::from_millis(500), Duration::from_secs(10));
+        assert_eq!(timeout.current(), Duration::from_secs(10));
+
+        timeout.record(Duration::from_millis(200));
+        assert_eq!(timeout.current(), Duration::from_millis(800));
+        // 0.8 × 200ms + 0.2 × 1200ms = 400ms.
+        timeout.record(Duration::from_millis(1200));
+        assert_eq!(timeout.current(), Duration::from_millis(1600));
+
+        for _ in 0..8 {
+            timeout.record(Duration::from_millis(10));
+        }
+        assert_eq!(timeout.current(), Duration::from_millis(500));
+
+        timeout.record(Duration::from_secs(60));
+        assert_eq!(timeout.current(), Duration::from_secs(10));
+    }
+
+    /// Answers every request after `delay`.
+    struct SlowFetcher {
+        delay: Duration,
+    }
+
+    impl Fetcher for SlowFetcher {
+        async fn get(&self, _url: &str) -> Result<Response, ScraperError> {
+            tokio::time::sleep(self.delay).await;
+            Ok(Response { status: 200, ..Default::default() })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_get_with_retry_applies_adaptive_timeout() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let adaptive = Arc::new(AdaptiveTimeout::new(Duration::from_millis(100), Duration::from_secs(5)));
+        let config = ScraperConfig {
+            adaptive_timeout: Some(adaptive.clone()),
+            ..Default::default()
+        };
+
+        let fast = SlowFetcher { delay: Duration::from_millis(50) };
+        assert!(get_with_retry(&fast, url, &config).await.is_ok());
+        assert_eq!(adaptive.current(), Duration::from_millis(200));
+
+        // A request well within the old fixed limits now fails fast.
+        let slow = SlowFetcher { delay: Duration::from_millis(300) };
+        assert!(matches!(get_with_retry(&slow, url, &config).await, Err(ScraperError::Network(_))));
+        assert_eq!(adaptive.current(), Duration::from_millis(200));
+
+        let without = ScraperConfig::default();
+        assert!(get_with_retry(&slow, url, &without).await.is_ok());
+    }
+
+    /// Accepts one connection and handles it with `respond`.
+    async fn serve_once<F, Fut>(respond: F) -> std::net::SocketAddr
+    where
+        F: FnOnce(tokio::net::TcpStream) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            respond(socket).await;
+        });
+        addr
+    }
+
+    async fn request_failure(client: &reqwest::Client, url: &str) -> reqwest::Error {
+        client.get(url).send().await.and_then(|r| r.error_for_status()).unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn test_with_config_applies_the_timeout() {
+        let addr = serve_once(|socket| async move {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            drop(socket);
+        })
+        .await;
+        let config = ScraperConfig {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let fetcher = ReqwestFetcher::with_config(&config).unwrap();
+        let started = std::time::Instant::now();
+        let result = fetcher.get(&format!("http://{}/", addr)).await;
+        assert!(matches!(result, Err(ScraperError::Network(_))));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_is_retryable_classifies_reqwest_errors() {
+        let client = reqwest::Client::builder().timeout(Duration::from_millis(200)).build().unwrap();
+
+        // Timeout: the server accepts and never answers.
+        let addr = serve_once(|socket| async move {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            drop(socket);
+        })
+        .await;
+        assert!(is_retryable(&request_failure(&client, &format!("http://{}/", addr)).await));
+
+        // Connection reset after the request was sent.
+        let addr = serve_once(|mut socket| async move {
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            socket.set_linger(Some(Duration::ZERO)).unwrap();
+            drop(socket);
+        })
+        .await;
+        assert!(is_retryable(&request_failure(&client, &format!("http://{}/", addr)).await));
+
+        // Connection closed without any response.
+        let addr = serve_once(|mut socket| async move {
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            drop(socket);
+        })
+        .await;
+        assert!(is_retryable(&request_failure(&client, &format!("http://{}/", addr)).await));
+
+        // Connection closed halfway through the body.
+        let addr = serve_once(|mut socket| async move {
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n<html>").await;
+        })
+        .await;
+        let response = client.get(format!("http://{}/", addr)).send().await.unwrap();
+        assert!(is_retryable(&response.text().await.unwrap_err()));
+
+        // 5xx is worth another attempt, 4xx isn't.
+        for (status, retryable) in [("503 Service Unavailable", true), ("403 Forbidden", false)] {
+            let addr = serve_once(move |mut socket| async move {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            })
+            .await;
+            assert_eq!(is_retryable(&request_failure(&client, &format!("http://{}/", addr)).await), retryable);
+        }
+
+        // TLS: the server doesn't speak it.
+        let addr = serve_once(|mut socket| async move {
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket.write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n").await;
+        })
+        .await;
+        assert!(!is_retryable(&request_failure(&client, &format!("https://{}/", addr)).await));
+
+        // An invalid URL never works; a refused connection may once the server is back.
+        assert!(!is_retryable(&request_failure(&client, "not a url").await));
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        assert!(is_retryable(&request_failure(&client, &format!("http://{}/", addr)).await));
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_fetcher_conditional_request_gets_304() {
+        let url = format!("{}/quote/6758.T", serve_etag(2).await);
+        let fetcher = ReqwestFetcher::new();
+
+        let first = fetcher.get(&url).await.unwrap();
+        assert_eq!(first.body, "page");
+        assert_eq!(first.validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(first.validators.last_modified.as_deref(), Some("Fri, 27 Dec 2024 06:00:00 GMT"));
+
+        let second = fetcher.get_conditional(&url, &first.validators).await.unwrap();
+        assert!(second.is_not_modified());
+        assert_eq!(second.body, "");
+    }
+
+    #[tokio::test]
+    async fn test_caching_fetcher_serves_pages_from_disk() {
+        let dir = std::env::temp_dir().join(format!("auto_selecter1_cache_{}", std::process::id()));
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let inner = mock::MockFetcher::new().with_page(url, 200, "<html>6758</html>");
+        let fetcher = CachingFetcher::new(inner, &dir, None).unwrap();
+
+        assert_eq!(fetcher.get(url).await.unwrap().body, "<html>6758</html>");
+        let cached = dir.join("finance.yahoo.co.jp_2Fquote_2F6758.T.html");
+        assert_eq!(fs::read_to_string(&cached).unwrap(), "<html>6758</html>");
+        assert_eq!(fetcher.get(url).await.unwrap().body, "<html>6758</html>");
+        assert_eq!(fetcher.inner.request_count(url), 1);
+
+        // An expired entry falls through to a live fetch; error pages are never cached.
+        let expired = CachingFetcher::new(fetcher.inner, &dir, Some(Duration::ZERO)).unwrap();
+        expired.get(url).await.unwrap();
+        assert_eq!(expired.inner.request_count(url), 2);
+        let missing = "https://finance.yahoo.co.jp/quote/9999.T";
+        expired.inner.push(missing, Ok(Response { status: 404, ..Default::default() }));
+        expired.get(missing).await.unwrap();
+        assert!(!dir.join("finance.yahoo.co.jp_2Fquote_2F9999.T.html").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_caching_fetcher_keys_on_the_full_url() {
+        let dir = std::env::temp_dir().join(format!("auto_selecter1_cache_keys_{}", std::process::id()));
+        let urls = [
+            "https://finance.yahoo.co.jp/quote/7203.T",
+            "https://finance.yahoo.co.jp/quote/7203.T/margin",
+            "https://finance.yahoo.co.jp/quote/7203.T/history?page=2",
+            "https://mirror.example.com/quote/7203.T",
+        ];
+        let inner = urls
+            .iter()
+            .fold(mock::MockFetcher::new(), |inner, url| inner.with_page(url, 200, url));
+        let fetcher = CachingFetcher::new(inner, &dir, None).unwrap();
+
+        for _ in 0..2 {
+            for url in urls {
+                assert_eq!(fetcher.get(url).await.unwrap().body, url);
+            }
+        }
+        for url in urls {
+            assert_eq!(fetcher.inner.request_count(url), 1);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), urls.len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_caching_fetcher_forwards_conditional_requests() {
+        let dir = std::env::temp_dir().join(format!("auto_selecter1_cache_conditional_{}", std::process::id()));
+        let url = format!("{}/quote/6758.T", serve_etag(2).await);
+        let fetcher = CachingFetcher::new(ReqwestFetcher::new(), &dir, Some(Duration::ZERO)).unwrap();
+
+        let first = fetcher.get(&url).await.unwrap();
+        let second = fetcher.get_conditional(&url, &first.validators).await.unwrap();
+        assert!(second.is_not_modified());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reqwest_fetcher_requires_a_tls_backend() {
+        assert!(ReqwestFetcher::build(&ScraperConfig::default(), true).is_ok());
+        match ReqwestFetcher::build(&ScraperConfig::default(), false) {
+            Err(ScraperError::InvalidConfig(message)) => assert!(message.contains("TLS backend not enabled")),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_fetcher_without_redirects_returns_location() {
+        let base = serve_redirect_once().await;
+        let config = ScraperConfig {
+            follow_redirects: false,
+            ..Default::default()
+        };
+        let fetcher = ReqwestFetcher::with_config(&config).unwrap();
+
+        let response = fetcher.get(&format!("{}/quote/9999.T", base)).await.unwrap();
+        assert!(response.is_redirect());
+        assert_eq!(response.location.as_deref(), Some("/search?query=9999"));
+    }
+}
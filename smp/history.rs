@@ -0,0 +1,196 @@
+use crate::fetcher::{Fetcher, ReqwestFetcher};
+use crate::models::{DailyBar, ScraperError};
+use crate::value_parser::ValueParser;
+use crate::{build_url_for, get_code_type, normalize_code, validate_code};
+use scraper::{ElementRef, Html, Selector};
+
+// Daily prices live on the 時系列 page (`/quote/<code>/history`), not on the quote page. It lists
+// about 20 trading days per page, newest first; older days are on `?page=2`, `?page=3`, ...
+/// Upper bound on the pages one call fetches, about five years of trading days.
+const MAX_PAGES: usize = 60;
+
+/// The history page of `code`; `page` counts from 1.
+fn build_history_url(code: &str, page: usize) -> String {
+    let code = normalize_code(code);
+    let url = format!("{}/history", build_url_for(&code, get_code_type(&code)));
+    if page > 1 {
+        format!("{}?page={}", url, page)
+    } else {
+        url
+    }
+}
+
+/// Rewrites a date as shown in the table, "2024年12月27日" or "2024/12/27", as "2024-12-27".
+fn parse_history_date(text: &str) -> Option<String> {
+    let parts: Vec<&str> = text
+        .trim()
+        .trim_end_matches('日')
+        .split(['年', '月', '/'])
+        .collect();
+    let [year, month, day] = parts[..] else {
+        return None;
+    };
+    let year = year.parse::<u32>().ok().filter(|year| (1900..=9999).contains(year))?;
+    let month = month.parse::<u32>().ok().filter(|month| (1..=12).contains(month))?;
+    let day = day.parse::<u32>().ok().filter(|day| (1..=31).contains(day))?;
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Reads one row of the history table: date, open, high, low, close and, when shown, volume.
+/// The header and notes such as a stock split (a row whose prices aren't numbers) give `None`.
+fn parse_history_row(row: ElementRef, cell_selector: &Selector, parser: &ValueParser) -> Option<DailyBar> {
+    let cells: Vec<String> = row
+        .select(cell_selector)
+        .map(|cell| cell.text().collect::<String>().trim().to_string())
+        .collect();
+    let [date, open, high, low, close, rest @ ..] = &cells[..] else {
+        return None;
+    };
+    Some(DailyBar {
+        date: parse_history_date(date)?,
+        open: parser.parse(open)?,
+        high: parser.parse(high)?,
+        low: parser.parse(low)?,
+        close: parser.parse(close)?,
+        // Indices have no volume column; a "---" volume is a day without trades.
+        volume: rest
+            .first()
+            .and_then(|volume| ValueParser::parse_share_count(volume))
+            .map(|volume| volume as u64),
+    })
+}
+
+/// Parses the bars of one history page, in the order listed (newest first).
+fn parse_history_page(body: &str, parser: &ValueParser) -> Result<Vec<DailyBar>, ScraperError> {
+    let document = Html::parse_document(body);
+    let parse_selector = |selector: &str| Selector::parse(selector).map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)));
+    let row_selector = parse_selector("table tr")?;
+    let cell_selector = parse_selector("th, td")?;
+    Ok(document
+        .select(&row_selector)
+        .filter_map(|row| parse_history_row(row, &cell_selector, parser))
+        .collect())
+}
+
+/// Fetches the latest `rows` daily bars of a code, newest first, using the given fetcher.
+///
+/// Pages are fetched until `rows` bars are collected, a page lists none, or a page only repeats
+/// days already seen (as when the site ignores a page number past the oldest page). Fewer than
+/// `rows` bars are returned when the history is shorter; no bars at all is an error.
+pub async fn scrape_history_with(fetcher: &impl Fetcher, code: &str, rows: usize) -> Result<Vec<DailyBar>, ScraperError> {
+    let code = normalize_code(code);
+    validate_code(&code)?;
+    let parser = ValueParser::for_code(&code);
+    let mut bars: Vec<DailyBar> = Vec::new();
+    for page in 1..=MAX_PAGES {
+        if bars.len() >= rows {
+            break;
+        }
+        let url = build_history_url(&code, page);
+        let response = fetcher.get(&url).await?;
+        if !response.is_success() {
+            return Err(ScraperError::from_status(response.status, &url));
+        }
+        let page_bars: Vec<DailyBar> = parse_history_page(&response.body, &parser)?
+            .into_iter()
+            .filter(|bar| !bars.iter().any(|seen| seen.date == bar.date))
+            .collect();
+        if page_bars.is_empty() {
+            break;
+        }
+        bars.extend(page_bars);
+    }
+    if bars.is_empty() && rows > 0 {
+        return Err(ScraperError::Other(format!("No daily prices found for {}", code)));
+    }
+    bars.truncate(rows);
+    Ok(bars)
+}
+
+/// Fetches the latest `rows` daily bars (date, open, high, low, close, volume) of a code from
+/// Yahoo's history page, newest first.
+pub async fn scrape_history(code: &str, rows: usize) -> Result<Vec<DailyBar>, ScraperError> {
+    scrape_history_with(&ReqwestFetcher::new(), code, rows).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::mock::MockFetcher;
+
+    const PAGE_2: &str = r#"<table>
+        <tr><th>日付</th><th>始値</th><th>高値</th><th>安値</th><th>終値</th><th>出来高</th></tr>
+        <tr><th>2024年12月23日</th><td>3,390</td><td>3,410</td><td>3,360</td><td>3,401</td><td>4,500,200</td></tr>
+        <tr><th>2024年12月20日</th><td>3,350</td><td>3,395</td><td>3,341</td><td>3,388</td><td>5,021,000</td></tr>
+    </table>"#;
+
+    #[test]
+    fn test_build_history_url() {
+        assert_eq!(build_history_url("6758", 1), "https://finance.yahoo.co.jp/quote/6758.T/history");
+        assert_eq!(build_history_url(" 6758", 3), "https://finance.yahoo.co.jp/quote/6758.T/history?page=3");
+        assert_eq!(build_history_url("^DJI", 1), "https://finance.yahoo.co.jp/quote/%5EDJI/history");
+    }
+
+    #[test]
+    fn test_parse_history_date() {
+        assert_eq!(parse_history_date("2024年12月27日").as_deref(), Some("2024-12-27"));
+        assert_eq!(parse_history_date("2024年1月5日").as_deref(), Some("2024-01-05"));
+        assert_eq!(parse_history_date("2024/1/5").as_deref(), Some("2024-01-05"));
+        assert_eq!(parse_history_date("日付"), None);
+        assert_eq!(parse_history_date("2024年13月1日"), None);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_history_parses_the_table() {
+        let fetcher = MockFetcher::new().with_page(&build_history_url("6758", 1), 200, include_str!("fixtures/history_6758.html"));
+
+        let bars = scrape_history_with(&fetcher, "6758", 3).await.unwrap();
+        assert_eq!(
+            bars[0],
+            DailyBar {
+                date: "2024-12-27".to_string(),
+                open: 3480.0,
+                high: 3512.0,
+                low: 3441.0,
+                close: 3456.0,
+                volume: Some(8_123_400),
+            }
+        );
+        assert_eq!(bars.iter().map(|bar| bar.date.as_str()).collect::<Vec<_>>(), ["2024-12-27", "2024-12-26", "2024-12-25"]);
+        assert_eq!(bars[2].low, 3430.5);
+        assert_eq!(fetcher.request_count(&build_history_url("6758", 2)), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_history_follows_pages() {
+        let fetcher = MockFetcher::new()
+            .with_page(&build_history_url("6758", 1), 200, include_str!("fixtures/history_6758.html"))
+            .with_page(&build_history_url("6758", 2), 200, PAGE_2)
+            // A page number past the oldest page that is answered with the oldest page again.
+            .with_page(&build_history_url("6758", 3), 200, PAGE_2);
+
+        // The split note is skipped, so page 1 holds four bars.
+        let bars = scrape_history_with(&fetcher, "6758", 5).await.unwrap();
+        assert_eq!(bars.len(), 5);
+        assert_eq!(bars[3].date, "2024-12-24");
+        assert_eq!(bars[4].date, "2024-12-23");
+        assert_eq!(fetcher.request_count(&build_history_url("6758", 3)), 0);
+
+        let bars = scrape_history_with(&fetcher, "6758", 100).await.unwrap();
+        assert_eq!(bars.len(), 6);
+        assert_eq!(bars[5].date, "2024-12-20");
+        assert_eq!(fetcher.request_count(&build_history_url("6758", 4)), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_history_errors() {
+        let fetcher = MockFetcher::new()
+            .with_page(&build_history_url("9999", 1), 404, "")
+            .with_page(&build_history_url("7203", 1), 200, "<html><body><p>データがありません</p></body></html>");
+
+        assert!(matches!(scrape_history_with(&fetcher, "9999", 10).await, Err(ScraperError::NotFound(_))));
+        assert!(matches!(scrape_history_with(&fetcher, "7203", 10).await, Err(ScraperError::Other(_))));
+        assert!(matches!(scrape_history_with(&fetcher, "12", 10).await, Err(ScraperError::InvalidCode(_))));
+        assert_eq!(scrape_history_with(&fetcher, "7203", 0).await.unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::models::Market;
+
+/// An instrument the scrapers know by name, so its page can be anchored on the name
+/// before anything has been scraped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnownInstrument {
+    /// The code as users pass it, e.g. "6758" or "998407.O".
+    pub code: &'static str,
+    /// The name exactly as the quote page shows it.
+    pub name: &'static str,
+    pub market: Market,
+    /// Quote page to use instead of the one built from the code.
+    pub url: Option<&'static str>,
+}
+
+/// Every instrument with a known name. Adding one is a one-line edit here.
+pub const KNOWN_INSTRUMENTS: &[KnownInstrument] = &[
+    KnownInstrument {
+        code: "6758",
+        name: "ソニーグループ(株)",
+        market: Market::TokyoStock,
+        url: None,
+    },
+    KnownInstrument {
+        code: "7203",
+        name: "トヨタ自動車(株)",
+        market: Market::TokyoStock,
+        url: None,
+    },
+    KnownInstrument {
+        code: "998407.O",
+        name: "日経平均株価",
+        market: Market::Nikkei,
+        url: Some("https://finance.yahoo.co.jp/quote/998407.O"),
+    },
+    KnownInstrument {
+        code: "^DJI",
+        name: "NYダウ",
+        market: Market::Dji,
+        url: Some("https://finance.yahoo.co.jp/quote/%5EDJI"),
+    },
+];
+
+/// Looks a code up in `KNOWN_INSTRUMENTS`. The comparison ignores case and percent-encoding,
+/// so "%5EDJI" finds "^DJI".
+pub fn known_instrument(code: &str) -> Option<&'static KnownInstrument> {
+    let code = urlencoding::decode(code.trim()).map(|code| code.into_owned()).unwrap_or_default();
+    KNOWN_INSTRUMENTS
+        .iter()
+        .find(|instrument| instrument.code.eq_ignore_ascii_case(&code))
+}
+
+/// Looks an instrument up by the name its page shows.
+pub fn known_instrument_by_name(name: &str) -> Option<&'static KnownInstrument> {
+    KNOWN_INSTRUMENTS.iter().find(|instrument| instrument.name == name)
+}
+
+/// The known indices (DJI, Nikkei, ...), in registry order.
+pub fn known_indices() -> impl Iterator<Item = &'static KnownInstrument> {
+    KNOWN_INSTRUMENTS
+        .iter()
+        .filter(|instrument| matches!(instrument.market, Market::Dji | Market::Nikkei))
+}
+
+/// Returns true when `name` is the name of a known index (not a stock).
+pub fn is_known_index_name(name: &str) -> bool {
+    known_indices().any(|instrument| instrument.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_instrument_lookup() {
+        assert_eq!(known_instrument("6758").map(|i| i.name), Some("ソニーグループ(株)"));
+        assert_eq!(known_instrument("%5EDJI").map(|i| i.market), Some(Market::Dji));
+        assert_eq!(known_instrument("^dji").map(|i| i.name), Some("NYダウ"));
+        assert_eq!(known_instrument("5016"), None);
+
+        assert_eq!(known_instrument_by_name("日経平均株価").map(|i| i.code), Some("998407.O"));
+        assert!(is_known_index_name("NYダウ"));
+        assert_eq!(known_indices().map(|i| i.code).collect::<Vec<_>>(), ["998407.O", "^DJI"]);
+        assert!(!is_known_index_name("トヨタ自動車(株)"));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::fetcher::{Fetcher, ReqwestFetcher};
+use crate::models::ScraperError;
+use crate::{get_code_type, CodeType};
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+
+// The intraday series isn't part of the quote page's DOM; the chart loads it from
+// Yahoo's chart API, so it is fetched separately and a failure here never affects the snapshot scrape.
+const CHART_API_BASE: &str = "https://query1.finance.yahoo.com/v8/finance/chart";
+
+#[derive(Deserialize)]
+struct ChartResponse {
+    chart: Chart,
+}
+
+#[derive(Deserialize)]
+struct Chart {
+    result: Option<Vec<ChartResult>>,
+}
+
+#[derive(Deserialize)]
+struct ChartResult {
+    meta: ChartMeta,
+    #[serde(default)]
+    timestamp: Vec<i64>,
+    indicators: Indicators,
+}
+
+#[derive(Deserialize)]
+struct ChartMeta {
+    #[serde(default)]
+    gmtoffset: i32,
+}
+
+#[derive(Deserialize)]
+struct Indicators {
+    quote: Vec<Quote>,
+}
+
+#[derive(Deserialize)]
+struct Quote {
+    #[serde(default)]
+    close: Vec<Option<f64>>,
+}
+
+/// Maps a code to the symbol used by the chart API.
+fn chart_symbol(code: &str) -> String {
+    match get_code_type(code) {
+        CodeType::Dji => "^DJI".to_string(),
+        CodeType::Nikkei => "^N225".to_string(),
+        CodeType::Fx => match code.strip_suffix("=FX") {
+            Some(pair) => format!("{}=X", pair),
+            None => code.to_string(),
+        },
+        CodeType::Stock(_) => {
+            if code.ends_with(".O") {
+                code.to_string()
+            } else {
+                format!("{}.T", code)
+            }
+        }
+    }
+}
+
+/// Builds the chart API URL for today's 1-minute series.
+fn build_intraday_url(code: &str) -> String {
+    format!(
+        "{}/{}?interval=1m&range=1d",
+        CHART_API_BASE,
+        urlencoding::encode(&chart_symbol(code))
+    )
+}
+
+/// Parses the chart API JSON into (timestamp, price) pairs.
+/// Timestamps are RFC 3339 in the exchange's local offset; minutes without a trade are skipped.
+fn parse_intraday(body: &str) -> Result<Vec<(String, f64)>, ScraperError> {
+    let response: ChartResponse = serde_json::from_str(body)
+        .map_err(|e| ScraperError::Other(format!("Invalid intraday data: {}", e)))?;
+    let result = response
+        .chart
+        .result
+        .and_then(|results| results.into_iter().next())
+        .ok_or_else(|| ScraperError::Other("Intraday data contains no result".to_string()))?;
+    let offset = FixedOffset::east_opt(result.meta.gmtoffset)
+        .ok_or_else(|| ScraperError::Other(format!("Invalid gmtoffset: {}", result.meta.gmtoffset)))?;
+    let closes = result
+        .indicators
+        .quote
+        .into_iter()
+        .next()
+        .map(|quote| quote.close)
+        .unwrap_or_default();
+
+    let mut points = Vec::new();
+    for (timestamp, close) in result.timestamp.iter().zip(closes) {
+        if let (Some(price), Some(time)) = (close, DateTime::from_timestamp(*timestamp, 0)) {
+            points.push((time.with_timezone(&offset).to_rfc3339(), price));
+        }
+    }
+    Ok(points)
+}
+
+/// Fetches today's intraday price series for a code using the given fetcher.
+pub async fn fetch_intraday_with(fetcher: &impl Fetcher, code: &str) -> Result<Vec<(String, f64)>, ScraperError> {
+    let url = build_intraday_url(code);
+    let response = fetcher.get(&url).await?;
+    if !response.is_success() {
+        return Err(ScraperError::from_status(response.status, &url));
+    }
+    parse_intraday(&response.body)
+}
+
+/// Fetches today's intraday price series for a code as (timestamp, price) pairs.
+pub async fn fetch_intraday(code: &str) -> Result<Vec<(String, f64)>, ScraperError> {
+    fetch_intraday_with(&ReqwestFetcher::new(), code).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::mock::MockFetcher;
+
+    #[test]
+    fn test_build_intraday_url() {
+        assert_eq!(
+            build_intraday_url("6758"),
+            "https://query1.finance.yahoo.com/v8/finance/chart/6758.T?interval=1m&range=1d"
+        );
+        assert_eq!(
+            build_intraday_url("^DJI"),
+            "https://query1.finance.yahoo.com/v8/finance/chart/%5EDJI?interval=1m&range=1d"
+        );
+        assert_eq!(chart_symbol("998407.O"), "^N225");
+        assert_eq!(chart_symbol("USDJPY=FX"), "USDJPY=X");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_intraday_parses_points() {
+        let url = build_intraday_url("6758");
+        let fetcher = MockFetcher::new().with_page(&url, 200, include_str!("fixtures/chart_6758.json"));
+
+        let points = fetch_intraday_with(&fetcher, "6758").await.unwrap();
+        assert_eq!(
+            points,
+            vec![
+                ("2024-06-11T10:00:00+09:00".to_string(), 3462.0),
+                ("2024-06-11T10:01:00+09:00".to_string(), 3458.0),
+                ("2024-06-11T10:03:00+09:00".to_string(), 3456.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_intraday_without_result() {
+        let url = build_intraday_url("6758");
+        let fetcher = MockFetcher::new().with_page(&url, 200, r#"{"chart":{"result":null,"error":{"code":"Not Found"}}}"#);
+
+        assert!(fetch_intraday_with(&fetcher, "6758").await.is_err());
+    }
+}
@@ -0,0 +1,2101 @@
+use futures::future::join_all;
+use scraper::Html;
+use std::error::Error;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Semaphore;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub mod config;
+pub mod cookies;
+pub mod fetcher;
+pub mod history;
+pub mod instruments;
+pub mod intraday;
+pub mod models;
+pub mod output;
+pub mod rate_limiter;
+pub mod scraper_logic;
+pub mod search;
+pub mod selectors;
+pub mod value_parser;
+
+use config::ScraperConfig;
+use fetcher::{Fetcher, ReqwestFetcher};
+use rate_limiter::RateLimiter;
+
+pub use history::scrape_history;
+pub use intraday::fetch_intraday;
+pub use search::resolve_code;
+use models::{normalize_change_percent, parse_change_string, Confidence, Market, ParsedStockData, ScraperError, SessionPhase, StockData};
+use selectors::SelectorSource;
+use value_parser::ValueParser;
+
+/// How a code is scraped: which URL it maps to and which finders run on its page.
+/// Normally derived from the code by `get_code_type`, but it can be forced (see `ScraperConfig::code_type`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodeType {
+    Stock(StockKind),
+    Fx,
+    Dji,
+    Nikkei,
+}
+
+/// Sub-classification of `CodeType::Stock`.
+/// Codes alone can't tell an ETF from an ordinary stock, so this is refined after the page is fetched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StockKind {
+    Equity,
+    Etf,
+}
+
+impl FromStr for CodeType {
+    type Err = String;
+
+    /// Parses a type name such as "stock", "etf", "fx", "dji" or "nikkei" (case-insensitive).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "stock" | "equity" => Ok(CodeType::Stock(StockKind::Equity)),
+            "etf" => Ok(CodeType::Stock(StockKind::Etf)),
+            "fx" => Ok(CodeType::Fx),
+            "dji" | "dow" => Ok(CodeType::Dji),
+            "nikkei" | "n225" => Ok(CodeType::Nikkei),
+            _ => Err(format!(
+                "Unknown code type: {} (expected stock, etf, fx, dji or nikkei)",
+                s
+            )),
+        }
+    }
+}
+
+/// Classifies a code from its shape.
+pub fn get_code_type(code: &str) -> CodeType {
+    let upper_code = code.trim().to_uppercase();
+    if upper_code == "%5EDJI" || upper_code == "^DJI" || upper_code == "DJI" {
+        CodeType::Dji
+    } else if upper_code == "998407.O" || upper_code == ".N225" || upper_code == "%5EN225" {
+        CodeType::Nikkei
+    } else if upper_code.ends_with("=FX") || upper_code.ends_with("=X") {
+        CodeType::Fx
+    } else {
+        CodeType::Stock(StockKind::Equity)
+    }
+}
+
+/// Returns the canonical form of a code: FX pairs are upper-cased and use the "=FX" suffix
+/// ("usdjpy=x" → "USDJPY=FX", the form `area` uses is accepted); other codes are only trimmed.
+pub fn normalize_code(code: &str) -> String {
+    let code = code.trim();
+    if get_code_type(code) != CodeType::Fx {
+        return code.to_string();
+    }
+    let upper_code = code.to_uppercase();
+    match upper_code.strip_suffix("=X") {
+        Some(pair) => format!("{}=FX", pair),
+        None => upper_code,
+    }
+}
+
+/// Scheme and host of every quote page `build_url_for` returns.
+const YAHOO_BASE_URL: &str = "https://finance.yahoo.co.jp";
+
+/// Receives a stock code and the type it is handled as, and returns a URL for Yahoo Finance.
+fn build_url_for(code: &str, code_type: CodeType) -> String {
+    match code_type {
+        CodeType::Dji => "https://finance.yahoo.co.jp/quote/%5EDJI".to_string(),
+        CodeType::Nikkei => "https://finance.yahoo.co.jp/quote/998407.O".to_string(),
+        // Yahoo! Finance Japan serves FX pairs under "=FX"; "=X" is accepted as an alias,
+        // and a bare pair (only possible with a forced type) gets the suffix added.
+        CodeType::Fx => {
+            if code.ends_with("=FX") {
+                format!("https://finance.yahoo.co.jp/quote/{}", code)
+            } else {
+                let pair = code.strip_suffix("=X").unwrap_or(code);
+                format!("https://finance.yahoo.co.jp/quote/{}=FX", pair)
+            }
+        }
+        CodeType::Stock(_) => {
+            if code.ends_with(".O") {
+                format!("https://finance.yahoo.co.jp/quote/{}", code)
+            } else {
+                format!("https://finance.yahoo.co.jp/quote/{}.T", code)
+            }
+        }
+    }
+}
+
+/// Checks that a code can be turned into a quote URL.
+/// Index and FX codes are accepted as-is; stock codes must be a TSE code ("9432", "130A")
+/// or carry an explicit ".O" suffix.
+pub fn validate_code(code: &str) -> Result<(), ScraperError> {
+    validate_code_as(code, get_code_type(code))
+}
+
+/// Like `validate_code`, for a code handled as `code_type`.
+pub fn validate_code_as(code: &str, code_type: CodeType) -> Result<(), ScraperError> {
+    validate_code_with(code, code_type, &scraper_logic::DEFAULT_CODE_LENGTHS)
+}
+
+fn validate_code_with(code: &str, code_type: CodeType, code_lengths: &RangeInclusive<usize>) -> Result<(), ScraperError> {
+    match code_type {
+        CodeType::Stock(_) if !code.ends_with(".O") && !scraper_logic::is_code_pattern_of_len(code, code_lengths) => {
+            Err(ScraperError::InvalidCode(code.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Scrapes a single stock page dynamically without any prior knowledge of the stock's name.
+pub async fn scrape_dynamically(fetcher: &impl Fetcher, code: &str) -> Result<StockData, Box<dyn Error>> {
+    scrape_dynamically_with_config(fetcher, code, &ScraperConfig::default()).await
+}
+
+/// `scrape_dynamically` with the finder settings (e.g. `price_anchors`) taken from `config`.
+pub async fn scrape_dynamically_with_config(
+    fetcher: &impl Fetcher,
+    code: &str,
+    config: &ScraperConfig,
+) -> Result<StockData, Box<dyn Error>> {
+    let code = &normalize_code(code);
+    let code_type = config.code_type.unwrap_or_else(|| get_code_type(code));
+    validate_code_with(code, code_type, &config.code_lengths)?;
+    let url = build_url_for(code, code_type);
+    let started = Instant::now();
+
+    // While polling, an unchanged page (304) reuses the previous result.
+    let cached = config.conditional_cache.as_ref().and_then(|cache| cache.get(&url));
+    let mut response = fetch_page(fetcher, &url, cached.as_ref().map(|page| &page.validators), config).await?;
+    if let (true, Some(page)) = (response.is_not_modified(), &cached) {
+        let mut data = page.data.clone();
+        data.scrape_ms = started.elapsed().as_millis() as u64;
+        return Ok(data);
+    }
+
+    let mut scraped = scrape_document(&Html::parse_document(&response.body), code, config).await;
+    // A name without a price usually means the price board hadn't been rendered yet when the
+    // page was served; that is worth exactly one more fetch, past any cached copy of the page.
+    if let Ok(data) = &mut scraped {
+        if data.price.is_empty() {
+            log::info!("{} has a name but no price; fetching the page once more", code);
+            if let Ok(refetched) = fetch_page(&fetcher::Fresh(fetcher), &url, None, config).await {
+                if let Ok(refetched_data) = scrape_document(&Html::parse_document(&refetched.body), code, config).await {
+                    *data = refetched_data;
+                    response = refetched;
+                }
+            }
+            data.refetched = true;
+        }
+    }
+    if scraped.as_ref().map_or(true, |data| data.price.is_empty()) {
+        if let Some(fallback) = scrape_fallback(fetcher, &url, code, config).await {
+            scraped = Ok(match scraped {
+                Ok(mut data) => {
+                    fill_missing_fields(&mut data, fallback);
+                    data
+                }
+                Err(_) => fallback,
+            });
+        }
+    }
+    let mut data = scraped?;
+    if config.aggressive && data.price.is_empty() {
+        guess_price(&mut data, &Html::parse_document(&response.body), code);
+    }
+    let margin_missing = data.margin_buy.is_none() || data.margin_sell.is_none();
+    if config.with_margin && matches!(code_type, CodeType::Stock(_)) && margin_missing {
+        fill_margin_balances(fetcher, &url, &mut data, config).await;
+    }
+    data.image_url = data.image_url.and_then(|src| resolve_url(&url, &src));
+    data.scrape_ms = started.elapsed().as_millis() as u64;
+    if let Some(cache) = &config.conditional_cache {
+        cache.insert(&url, response.validators, data.clone());
+    }
+    Ok(data)
+}
+
+/// Scrapes the page at `url` from `config.fallback_base_url` instead; `None` when there is no
+/// fallback host, or its page can't be fetched or has no price either.
+async fn scrape_fallback(fetcher: &impl Fetcher, url: &str, code: &str, config: &ScraperConfig) -> Option<StockData> {
+    let base = config.fallback_base_url.as_deref()?;
+    let path = url.strip_prefix(YAHOO_BASE_URL)?;
+    let fallback_url = format!("{}{}", base.trim_end_matches('/'), path);
+    log::info!("no price for {} on {}; trying {}", code, url, fallback_url);
+    let response = match fetch_page(fetcher, &fallback_url, None, config).await {
+        Ok(response) => response,
+        Err(e) => {
+            log::info!("could not fetch {}: {}", fallback_url, e);
+            return None;
+        }
+    };
+    // The selectors kept for the primary layout don't fit the other one, and the selectors found
+    // there would only mislead later pages of the primary one.
+    let fallback_config = ScraperConfig {
+        selector_overrides: HashMap::new(),
+        selector_cache: None,
+        static_selectors: false,
+        ..config.clone()
+    };
+    let mut data = scrape_document(&Html::parse_document(&response.body), code, &fallback_config)
+        .await
+        .ok()?;
+    data.image_url = data.image_url.and_then(|src| resolve_url(&fallback_url, &src));
+    (!data.price.is_empty()).then_some(data)
+}
+
+/// Fills the quote fields `data` left empty with those of `fallback`, a record of the same code
+/// scraped from the fallback host.
+fn fill_missing_fields(data: &mut StockData, fallback: StockData) {
+    if data.price.is_empty() {
+        data.price = fallback.price;
+        data.unit = fallback.unit;
+        data.price_confidence = fallback.price_confidence;
+    }
+    for (field, value) in [
+        (&mut data.code, fallback.code),
+        (&mut data.name, fallback.name),
+        (&mut data.change, fallback.change),
+        (&mut data.change_percent, fallback.change_percent),
+        (&mut data.update_time, fallback.update_time),
+    ] {
+        if field.is_empty() {
+            *field = value;
+        }
+    }
+    data.direction = data.direction.or(fallback.direction);
+    data.image_url = data.image_url.take().or(fallback.image_url);
+}
+
+/// The last resort of `ScraperConfig::aggressive`, once every finder, the refetch and the fallback
+/// host came up empty: the most price-like number on `document`, tagged `Confidence::Guess`.
+fn guess_price(data: &mut StockData, document: &Html, code: &str) {
+    if let Some(price) = scraper_logic::find_price_like_number(document, code) {
+        data.unit = scraper_logic::split_price_unit(&price).1.map(str::to_string);
+        data.price = price;
+        data.price_confidence = Some(Confidence::Guess);
+    }
+}
+
+/// The 信用残 (margin balance) page of the stock whose quote page is at `quote_url`.
+fn margin_url(quote_url: &str) -> String {
+    format!("{}/margin", quote_url)
+}
+
+/// Fills in the margin balances `data` lacks from the stock's 信用残 page (see `margin_url`).
+/// A page that can't be fetched is only logged (at info level); the balances stay empty.
+async fn fill_margin_balances(fetcher: &impl Fetcher, quote_url: &str, data: &mut StockData, config: &ScraperConfig) {
+    let url = margin_url(quote_url);
+    match fetch_page(fetcher, &url, None, config).await {
+        Ok(response) => {
+            let document = Html::parse_document(&response.body);
+            data.margin_buy = data.margin_buy.take().or_else(|| scraper_logic::find_detail_field(&document, "信用買残"));
+            data.margin_sell = data.margin_sell.take().or_else(|| scraper_logic::find_detail_field(&document, "信用売残"));
+        }
+        Err(e) => log::info!("could not fetch the margin page {}: {}", url, e),
+    }
+}
+
+/// Fetches the page at `url`, conditionally when `validators` are given. A redirect is an error;
+/// a 304 answer to a conditional request is returned as-is.
+async fn fetch_page(
+    fetcher: &impl Fetcher,
+    url: &str,
+    validators: Option<&fetcher::Validators>,
+    config: &ScraperConfig,
+) -> Result<fetcher::Response, Box<dyn Error>> {
+    let response = fetcher::get_with_retry_conditional(fetcher, url, validators, config).await?;
+    if response.is_redirect() && !(validators.is_some() && response.is_not_modified()) {
+        return Err(Box::new(ScraperError::UnexpectedRedirect(
+            response.location.unwrap_or_default(),
+        )));
+    }
+    check_final_url(url, &response)?;
+    Ok(response)
+}
+
+/// Fails when redirects took the request for a code's page to a URL without that code, e.g. a
+/// consent wall. Scraping such a page would at best yield a record with the requested code filled
+/// in and nothing else, which looks valid but isn't.
+fn check_final_url(url: &str, response: &fetcher::Response) -> Result<(), ScraperError> {
+    let (Some(code), Some(final_url)) = (code_from_url(url), &response.final_url) else {
+        return Ok(());
+    };
+    if code_from_url(final_url).as_ref() == Some(&code) {
+        Ok(())
+    } else {
+        Err(ScraperError::RedirectedAway(url.to_string(), final_url.clone()))
+    }
+}
+
+/// Scrapes an arbitrary Yahoo quote URL (e.g. one with query parameters or a non-standard path)
+/// without building the URL from a code. The code is taken from the page when it shows one,
+/// otherwise from the URL's `/quote/<code>` segment.
+pub async fn scrape_url(url: &str) -> Result<StockData, ScraperError> {
+    scrape_url_with(&ReqwestFetcher::new(), url, &ScraperConfig::default()).await
+}
+
+/// `scrape_url` using the given fetcher and settings.
+pub async fn scrape_url_with(
+    fetcher: &impl Fetcher,
+    url: &str,
+    config: &ScraperConfig,
+) -> Result<StockData, ScraperError> {
+    let started = Instant::now();
+    let response = fetcher::get_with_retry(fetcher, url, config).await?;
+    if response.is_redirect() {
+        return Err(ScraperError::UnexpectedRedirect(response.location.unwrap_or_default()));
+    }
+    check_final_url(url, &response)?;
+    let document = Html::parse_document(&response.body);
+
+    let code = code_from_url(url).unwrap_or_default();
+    let mut data = scrape_document(&document, &code, config)
+        .await
+        .map_err(into_scraper_error)?;
+    if config.aggressive && data.price.is_empty() {
+        guess_price(&mut data, &document, &code);
+    }
+    data.image_url = data.image_url.and_then(|src| resolve_url(url, &src));
+    data.scrape_ms = started.elapsed().as_millis() as u64;
+    Ok(data)
+}
+
+/// Resolves a possibly relative link found on the page at `page_url`.
+fn resolve_url(page_url: &str, link: &str) -> Option<String> {
+    let base = reqwest::Url::parse(page_url).ok()?;
+    base.join(link).ok().map(String::from)
+}
+
+/// Best-effort code from a quote URL: the percent-decoded `/quote/<code>` segment without the ".T" suffix.
+pub(crate) fn code_from_url(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("/quote/")?;
+    let symbol = rest.split(['?', '#', '/']).next().filter(|s| !s.is_empty())?;
+    let symbol = urlencoding::decode(symbol).ok()?;
+    Some(symbol.strip_suffix(".T").unwrap_or(&symbol).to_string())
+}
+
+/// Price finder for index pages: the class hint, then the largest number on the board,
+/// then the anchor cascade used for stocks.
+async fn find_index_price(
+    document: &Html,
+    anchor_name: &str,
+    code: &str,
+    anchors: &[config::PriceAnchor],
+) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(selector) = scraper_logic::find_price_by_class_hint(document, code)
+        .or_else(|| scraper_logic::find_index_price_selector(document, anchor_name, code))
+    {
+        return Ok(Some(selector));
+    }
+    scraper_logic::find_stock_price_selector_with_anchors(document, anchor_name, code, anchors).await
+}
+
+/// Runs the dynamic finders against an already parsed page.
+async fn scrape_document(
+    document: &Html,
+    code: &str,
+    config: &ScraperConfig,
+) -> Result<StockData, Box<dyn Error>> {
+    let anchors = &config.price_anchors;
+    let mut code_type = config.code_type.unwrap_or_else(|| get_code_type(code));
+
+    // 1. Find the name and its selector first.
+    let (name_selector_opt, name_text) = scraper_logic::find_name_dynamically(document).await?;
+
+    if name_text.is_empty() {
+        return Err(Box::new(ScraperError::Other(
+            "Could not dynamically find a valid name.".to_string(),
+        )));
+    }
+
+    // 2. Use the found name as an anchor to find everything else.
+    let anchor_name = &name_text;
+
+    if let CodeType::Stock(_) = code_type {
+        if scraper_logic::is_etf_page(document, anchor_name) {
+            code_type = CodeType::Stock(StockKind::Etf);
+        }
+    }
+
+    let code_selector_opt = scraper_logic::find_text_pattern_selector_near_anchor(document, anchor_name, "code", &config.code_lengths).await?;
+    
+    let price_selector_opt;
+    let change_selector_opt;
+    let change_percent_selector_opt;
+    let update_time_selector_opt;
+
+    match code_type {
+        CodeType::Fx => {
+            // FX-specific logic
+            price_selector_opt = scraper_logic::find_fx_price_selector(document).await?;
+            change_selector_opt = scraper_logic::find_fx_change_selector(document).await?;
+            change_percent_selector_opt = None; // User requested to not scrape change_percent for FX
+            update_time_selector_opt = scraper_logic::find_fx_update_time_selector(document).await?;
+        }
+        CodeType::Dji => { // DJI-specific logic
+            price_selector_opt = find_index_price(document, anchor_name, code, anchors).await?;
+            change_selector_opt = scraper_logic::find_stock_change_selector(document, anchor_name, config.search_fallback).await?;
+            change_percent_selector_opt = scraper_logic::find_stock_change_percent_selector(document, anchor_name, config.search_fallback).await?;
+            update_time_selector_opt = scraper_logic::find_dji_update_time_selector(document).await?;
+        }
+        CodeType::Nikkei => { // Nikkei-specific logic
+            price_selector_opt = find_index_price(document, anchor_name, code, anchors).await?;
+            change_selector_opt = scraper_logic::find_stock_change_selector(document, anchor_name, config.search_fallback).await?;
+            change_percent_selector_opt = scraper_logic::find_stock_change_percent_selector(document, anchor_name, config.search_fallback).await?;
+            update_time_selector_opt = scraper_logic::find_nikkei_update_time_selector(document).await?;
+        }
+        CodeType::Stock(_) => {
+            // Stock-specific logic
+            let zenjitsuhi_anchor = "前日比";
+            price_selector_opt = scraper_logic::find_stock_price_selector_with_anchors(document, anchor_name, code, anchors).await?;
+            change_selector_opt = scraper_logic::find_stock_change_selector(document, zenjitsuhi_anchor, config.search_fallback).await?;
+            change_percent_selector_opt = scraper_logic::find_stock_change_percent_selector(document, zenjitsuhi_anchor, config.search_fallback).await?;
+            update_time_selector_opt = scraper_logic::find_stock_update_time_selector(document).await?;
+        }
+    }
+
+    // FX boards put Latin labels ("Bid", "Change") next to the pair's name; they have no English name.
+    let name_en = match code_type {
+        CodeType::Fx => None,
+        _ => scraper_logic::find_english_name(document, anchor_name),
+    };
+    let image_url = scraper_logic::find_image_src(document, anchor_name);
+    let direction = scraper_logic::find_price_direction(document, anchor_name);
+    let trading_state = match code_type {
+        CodeType::Stock(_) => Some(scraper_logic::find_trading_state(document, anchor_name)),
+        _ => None,
+    };
+    // FX trades around the clock, so its board has no session marker.
+    let session_status = match code_type {
+        CodeType::Fx => None,
+        _ => scraper_logic::find_session_status(document, anchor_name),
+    };
+    let session_phase = session_status.as_deref().and_then(SessionPhase::from_status);
+
+    // 3. Scrape data, trying for each field the user's selector, the cached one, the static one
+    //    and finally the one just discovered (see `selectors::FieldSelectors`).
+    let market = Market::from_code_type(&code_type);
+    let debug_elements = RefCell::new(BTreeMap::new());
+    let debug_match_counts = RefCell::new(BTreeMap::new());
+    let record_element = |name: &str, selector: Option<&str>| {
+        let Some(selector) = selector.filter(|_| config.debug_elements) else {
+            return;
+        };
+        if let Some(html) = selectors::element_html(document, selector) {
+            debug_elements.borrow_mut().insert(name.to_string(), html);
+            debug_match_counts
+                .borrow_mut()
+                .insert(name.to_string(), selectors::match_count(document, selector));
+        }
+    };
+    record_element("name", name_selector_opt.as_deref());
+    let field_with_source = |name: &str, discovered: &Option<String>| -> Option<(String, SelectorSource)> {
+        let cached = config.selector_cache.as_ref().and_then(|cache| cache.get(market, name));
+        let field_selectors = selectors::FieldSelectors {
+            user: config.selector_overrides.get(name).map(String::as_str),
+            cached: cached.as_deref(),
+            static_selector: selectors::static_selector(code_type, name).filter(|_| config.static_selectors),
+            discovered: discovered.as_deref(),
+        };
+        let (value, source) = selectors::scrape_field_layered(document, &field_selectors)?;
+        if let (SelectorSource::Discovered, Some(cache), Some(selector)) = (source, &config.selector_cache, discovered) {
+            // A position on this page says nothing about where the field sits on the next one.
+            if !selectors::is_positional(selector) {
+                cache.insert(market, name, selector);
+            }
+        }
+        record_element(name, field_selectors.selector(source));
+        Some((value, source))
+    };
+    let field = |name: &str, discovered: &Option<String>| -> String {
+        field_with_source(name, discovered).map(|(value, _)| value).unwrap_or_default()
+    };
+
+    // JSON-LD data naming the code outranks every selector but the user's own. A price `<meta>`
+    // tag doesn't say whose price it is, so it only outranks the selectors discovered on this page.
+    let structured = |value: String, confidence: Confidence| {
+        // Not read from an element; whatever the selectors matched isn't what was used.
+        debug_elements.borrow_mut().remove("price");
+        debug_match_counts.borrow_mut().remove("price");
+        (value, Some(confidence))
+    };
+    let (price, price_confidence) = match (
+        field_with_source("price", &price_selector_opt),
+        scraper_logic::find_structured_price(document, code),
+        scraper_logic::find_meta_price(document, code),
+    ) {
+        (Some((value, SelectorSource::User)), _, _) => (value, Some(Confidence::High)),
+        (_, Some(value), _) => structured(value, Confidence::High),
+        (Some((value, source @ (SelectorSource::Cached | SelectorSource::Static))), None, _) => (value, Some(source.confidence())),
+        (_, None, Some(value)) => structured(value, Confidence::Medium),
+        (Some((value, source)), None, None) => (value, Some(source.confidence())),
+        (None, None, None) => (String::new(), None),
+    };
+    let mut change = field("change", &change_selector_opt);
+    let mut change_percent = field("change_percent", &change_percent_selector_opt);
+
+    // Some pages render both changes in one element, e.g. "+120(+1.52%)"; split it.
+    // With `include_raw_change` the combined text is looked up (and kept) on every page.
+    let needs_combined_fallback = change.is_empty() || change_percent.is_empty();
+    let mut change_raw = None;
+    if (needs_combined_fallback || config.include_raw_change) && !matches!(code_type, CodeType::Fx) {
+        let change_anchor = match code_type {
+            CodeType::Stock(_) => "前日比",
+            _ => anchor_name.as_str(),
+        };
+        let combined_selector_opt = scraper_logic::find_combined_change_selector(document, change_anchor, config.search_fallback).await?;
+        if combined_selector_opt.is_some() {
+            let combined = scraper_logic::scrape_field(document, &combined_selector_opt, "combined_change");
+            record_element("combined_change", combined_selector_opt.as_deref());
+            if config.include_raw_change {
+                // Kept as shown, spaces included; the parts may be separate elements on their own
+                // lines of markup, and each line break (with its indentation) reads as one space.
+                change_raw = Some(combined.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" "));
+            }
+            let (change_abs, change_pct) = parse_change_string(&scraper_logic::normalize_minus(&combined));
+            if change.is_empty() {
+                change = change_abs;
+            }
+            if change_percent.is_empty() && !change_pct.is_empty() {
+                change_percent = change_pct;
+            }
+        }
+    }
+
+    let mut scraped_data = StockData {
+        name: name_text,
+        code: field("code", &code_selector_opt),
+        price,
+        price_confidence,
+        change,
+        change_percent: normalize_change_percent(&change_percent),
+        change_raw,
+        update_time: field("update_time", &update_time_selector_opt),
+        name_en,
+        trading_state,
+        session_status,
+        session_phase,
+        direction,
+        image_url,
+        ..Default::default()
+    };
+
+    if let CodeType::Stock(_) = code_type {
+        scraped_data.open = scraper_logic::find_detail_field(document, "始値");
+        scraped_data.previous_close = scraper_logic::find_detail_field(document, "前日終値");
+        scraped_data.margin_buy = scraper_logic::find_detail_field(document, "信用買残");
+        scraped_data.margin_sell = scraper_logic::find_detail_field(document, "信用売残");
+    }
+
+    (scraped_data.chart_range_high, scraped_data.chart_range_low) = scraper_logic::find_chart_range(document);
+
+    if let (CodeType::Stock(_), true) = (code_type, config.include_related) {
+        scraped_data.related_codes = scraper_logic::find_related_codes(document, code);
+    }
+
+    if let (CodeType::Stock(_), true) = (code_type, config.include_ticks) {
+        scraped_data.recent_ticks = scraper_logic::find_recent_ticks(document, config.max_ticks);
+    }
+
+    if let (CodeType::Stock(_), true) = (code_type, config.with_pts) {
+        let (pts_price, pts_change) = scraper_logic::find_pts_quote(document);
+        scraped_data.pts_price = pts_price.unwrap_or_default();
+        scraped_data.pts_change = pts_change.unwrap_or_default();
+    }
+
+    if let CodeType::Stock(StockKind::Etf) = code_type {
+        scraped_data.nav = scraper_logic::find_detail_field(document, "基準価額");
+        scraped_data.tracking_index = scraper_logic::find_detail_field(document, "連動対象")
+            .or_else(|| scraper_logic::find_detail_field(document, "連動対象指数"));
+    }
+
+    scraped_data.unit = scraper_logic::split_price_unit(&scraped_data.price).1.map(str::to_string);
+    scraped_data.debug_elements = debug_elements.into_inner();
+    scraped_data.debug_match_counts = debug_match_counts.into_inner();
+
+    // 4. Fill in missing data
+    if scraped_data.code.is_empty() {
+        scraped_data.code = code.to_string();
+    }
+    scraped_data.market = Some(market);
+
+    // 5. Sanity check: a quote page's title names the instrument. A title that mentions
+    //    neither usually means a redirect, consent wall or not-found page.
+    scraped_data.page_title = scraper_logic::find_page_title(document);
+    if let Some(title) = &scraped_data.page_title {
+        if !title_matches(title, code, &scraped_data.name) {
+            log::warn!(
+                "page title {:?} mentions neither {} nor {}; the page may be wrong",
+                title, code, scraped_data.name
+            );
+        }
+    }
+
+    Ok(scraped_data)
+}
+
+/// Returns true when the page title mentions the requested code (without its market suffix) or the scraped name.
+fn title_matches(title: &str, code: &str, name: &str) -> bool {
+    let base_code = [".T", ".O", "=FX", "=X"]
+        .iter()
+        .find_map(|suffix| code.strip_suffix(suffix))
+        .unwrap_or(code);
+    (!base_code.is_empty() && title.contains(base_code)) || (!name.is_empty() && title.contains(name))
+}
+
+/// Keeps a `ScraperError` as it is (so callers can still tell e.g. a 404 from a network error)
+/// and wraps any other error as `ScraperError::Other`.
+fn into_scraper_error(e: Box<dyn Error>) -> ScraperError {
+    match e.downcast::<ScraperError>() {
+        Ok(e) => *e,
+        Err(e) => ScraperError::Other(e.to_string()),
+    }
+}
+
+/// Scrapes a single code over the network with the default settings.
+pub async fn scrape_code(code: &str) -> Result<StockData, ScraperError> {
+    scrape_code_with_config(code, &ScraperConfig::default()).await
+}
+
+/// Scrapes a single code over the network with the given settings: the client is built from
+/// `config` (see `ReqwestFetcher::with_config`) and the scrape runs as `scrape_with_config`.
+/// Nothing is shared between calls, so differently configured scrapes can run side by side.
+pub async fn scrape_code_with_config(code: &str, config: &ScraperConfig) -> Result<StockData, ScraperError> {
+    let fetcher = ReqwestFetcher::with_config(config)?;
+    scrape_with_config(&fetcher, code, config).await
+}
+
+/// Looks up the code for a company name and scrapes it.
+pub async fn scrape_by_name(name: &str) -> Result<StockData, ScraperError> {
+    let code = resolve_code(name).await?;
+    scrape_code(&code).await
+}
+
+/// Runs `scrape_dynamically` for one code, failing with `ScraperError::CodeTimeout`
+/// when it doesn't finish within `timeout` (`None` means no limit).
+pub async fn scrape_with_timeout(
+    fetcher: &impl Fetcher,
+    code: &str,
+    timeout: Option<Duration>,
+) -> Result<StockData, ScraperError> {
+    let config = ScraperConfig {
+        timeout_per_code: timeout,
+        ..Default::default()
+    };
+    scrape_with_config(fetcher, code, &config).await
+}
+
+/// Scrapes one code with the finder settings from `config`, bounded by its `timeout_per_code`.
+/// A successful record has its decimals normalized when `config.decimal_precision` is set, and is
+/// passed through `config.post_process` before it is returned.
+pub async fn scrape_with_config(
+    fetcher: &impl Fetcher,
+    code: &str,
+    config: &ScraperConfig,
+) -> Result<StockData, ScraperError> {
+    let scrape = async {
+        scrape_dynamically_with_config(fetcher, code, config)
+            .await
+            .map_err(into_scraper_error)
+    };
+    let mut result = match config.timeout_per_code {
+        Some(limit) => tokio::time::timeout(limit, scrape)
+            .await
+            .unwrap_or_else(|_| Err(ScraperError::CodeTimeout(code.to_string(), limit))),
+        None => scrape.await,
+    };
+    if let (Ok(data), Some(precision)) = (&mut result, &config.decimal_precision) {
+        let code_type = config.code_type.unwrap_or_else(|| get_code_type(&normalize_code(code)));
+        ValueParser::for_code_type(&code_type).normalize_decimals(data, &code_type, precision);
+    }
+    if let (Ok(data), Some(post_process)) = (&mut result, config.post_process) {
+        post_process(data);
+    }
+    result
+}
+
+/// Key under which a batch scrapes a code only once: the quote URL it resolves to, case-folded,
+/// so `^dji`, `^DJI` and `DJI` (or `usdjpy=fx` and `USDJPY=FX`) share one result.
+fn batch_key(code: &str, config: &ScraperConfig) -> String {
+    let code = normalize_code(code);
+    let code_type = config.code_type.unwrap_or_else(|| get_code_type(&code));
+    build_url_for(&code, code_type).to_uppercase()
+}
+
+/// Scrapes several codes concurrently, returning one result per code in the input order.
+///
+/// Codes naming the same page (see `batch_key`) are scraped once; later occurrences get a copy
+/// of the first one's result.
+///
+/// Before each fetch a permit from the concurrency semaphore (`max_concurrent`) and a
+/// token from the rate limiter (`requests_per_second`) are both acquired.
+/// `timeout_per_code` starts counting once they are, so waiting for a slot doesn't use up the budget.
+pub async fn scrape_batch(
+    fetcher: &impl Fetcher,
+    codes: &[String],
+    config: &ScraperConfig,
+) -> Vec<(String, Result<StockData, ScraperError>)> {
+    scrape_batch_with_progress(fetcher, codes, config, |_, _, _, _| {}).await
+}
+
+/// `scrape_batch`, calling `progress(done, total, code, result)` as each code completes, failed or
+/// not, e.g. to drive a progress bar or to handle results before the whole batch is done. `total`
+/// counts the pages actually scraped, so codes repeated in `codes` are reported once (under their
+/// first occurrence) and `done` ends at `total`.
+pub async fn scrape_batch_with_progress(
+    fetcher: &impl Fetcher,
+    codes: &[String],
+    config: &ScraperConfig,
+    progress: impl Fn(usize, usize, &str, &Result<StockData, ScraperError>),
+) -> Vec<(String, Result<StockData, ScraperError>)> {
+    let semaphore = Semaphore::new(config.max_concurrent.max(1));
+    let rate_limiter = config.requests_per_second.map(RateLimiter::new);
+
+    let mut slots: HashMap<String, usize> = HashMap::new();
+    let mut unique_codes: Vec<&String> = Vec::new();
+    let slot_of: Vec<usize> = codes
+        .iter()
+        .map(|code| {
+            *slots.entry(batch_key(code, config)).or_insert_with(|| {
+                unique_codes.push(code);
+                unique_codes.len() - 1
+            })
+        })
+        .collect();
+
+    let total = unique_codes.len();
+    let done = AtomicUsize::new(0);
+    let tasks = unique_codes.into_iter().map(|code| {
+        let semaphore = &semaphore;
+        let rate_limiter = &rate_limiter;
+        let (done, progress) = (&done, &progress);
+        async move {
+            let result = {
+                let _permit = semaphore.acquire().await.expect("semaphore is never closed");
+                if let Some(rate_limiter) = rate_limiter {
+                    rate_limiter.acquire().await;
+                }
+                scrape_with_config(fetcher, code, config).await
+            };
+            progress(done.fetch_add(1, Ordering::SeqCst) + 1, total, code, &result);
+            result
+        }
+    });
+    let results = join_all(tasks).await;
+    codes
+        .iter()
+        .zip(slot_of)
+        .map(|(code, slot)| (code.clone(), results[slot].clone()))
+        .collect()
+}
+
+/// Key a code is filed under by `scrape_map`: FX pairs in their `=FX` form, index aliases as the
+/// index's code in `KNOWN_INSTRUMENTS` (e.g. "^dji" and "DJI" as "^DJI"), other codes upper-cased.
+fn map_key(code: &str, config: &ScraperConfig) -> String {
+    let code = normalize_code(code);
+    let market = Market::from_code_type(&config.code_type.unwrap_or_else(|| get_code_type(&code)));
+    match market {
+        Market::Dji | Market::Nikkei => instruments::KNOWN_INSTRUMENTS
+            .iter()
+            .find(|instrument| instrument.market == market)
+            .map_or(code, |instrument| instrument.code.to_string()),
+        Market::TokyoStock | Market::Fx => code.to_uppercase(),
+    }
+}
+
+/// Scrapes several codes (see `scrape_batch`) and returns the results keyed by normalized code
+/// (see `map_key`), so `results["6758"]` finds a record without scanning. Aliases of one code
+/// collapse into a single entry.
+pub async fn scrape_map(codes: &[String]) -> HashMap<String, Result<StockData, ScraperError>> {
+    scrape_map_with(&ReqwestFetcher::new(), codes, &ScraperConfig::default()).await
+}
+
+/// `scrape_map` using the given fetcher and settings.
+pub async fn scrape_map_with(
+    fetcher: &impl Fetcher,
+    codes: &[String],
+    config: &ScraperConfig,
+) -> HashMap<String, Result<StockData, ScraperError>> {
+    let mut results = HashMap::new();
+    for (code, result) in scrape_batch(fetcher, codes, config).await {
+        results.entry(map_key(&code, config)).or_insert(result);
+    }
+    results
+}
+
+/// Scrapes every index in `KNOWN_INSTRUMENTS` (the DJI and the Nikkei; adding an index there adds it
+/// here), e.g. for a market-overview header. The records are in registry order; any failure fails the call.
+pub async fn scrape_indices() -> Result<Vec<StockData>, ScraperError> {
+    scrape_indices_with(&ReqwestFetcher::new(), &ScraperConfig::default()).await
+}
+
+/// `scrape_indices` using the given fetcher and settings.
+pub async fn scrape_indices_with(fetcher: &impl Fetcher, config: &ScraperConfig) -> Result<Vec<StockData>, ScraperError> {
+    let codes: Vec<String> = instruments::known_indices().map(|index| index.code.to_string()).collect();
+    scrape_batch(fetcher, &codes, config)
+        .await
+        .into_iter()
+        .map(|(_, result)| result)
+        .collect()
+}
+
+/// Scrapes a single code and returns the raw strings together with their parsed numbers,
+/// so a suspicious parse can be cross-checked against what was actually on the page.
+pub async fn scrape_detailed(code: &str) -> Result<(StockData, ParsedStockData), ScraperError> {
+    let data = scrape_code(code).await?;
+    let parsed = ValueParser::for_code(code).parse_stock_data(&data);
+    Ok((data, parsed))
+}
+
+/// Scrapes a single code and returns only its current price as a number.
+pub async fn get_price(code: &str) -> Result<f64, ScraperError> {
+    let data = scrape_code(code).await?;
+    parse_price(&data, ValueParser::for_code(code))
+}
+
+fn parse_price(data: &StockData, parser: ValueParser) -> Result<f64, ScraperError> {
+    if data.price.is_empty() {
+        return Err(ScraperError::Other(format!("Price not found for {}", data.code)));
+    }
+    parser
+        .parse(&data.price)
+        .ok_or_else(|| ScraperError::Other(format!("Could not parse price '{}' for {}", data.price, data.code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchFallback;
+    use crate::fetcher::mock::MockFetcher;
+
+    #[tokio::test]
+    async fn test_scrape_document_etf_extracts_nav() {
+        let document = Html::parse_document(include_str!("fixtures/etf_1306.html"));
+        let data = scrape_document(&document, "1306", &ScraperConfig::default()).await.unwrap();
+
+        assert_eq!(data.code, "1306");
+        assert_eq!(data.nav.as_deref(), Some("2,744.38"));
+        assert_eq!(data.tracking_index.as_deref(), Some("TOPIX（東証株価指数）"));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_stock_has_no_etf_fields() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+
+        assert_eq!(data.name, "ソニーグループ(株)");
+        assert!(data.nav.is_none());
+        assert!(data.tracking_index.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_splits_combined_change() {
+        let document = Html::parse_document(include_str!("fixtures/stock_9984_combined.html"));
+        let data = scrape_document(&document, "9984", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "8,012");
+        assert_eq!(data.change, "+120");
+        assert_eq!(data.change_percent, "+1.52%");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_widens_search_for_distant_percent() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6954_percent_outside.html"));
+        let data = scrape_document(&document, "6954", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.change, "+35");
+        assert_eq!(data.change_percent, "+0.84%");
+
+        let config = ScraperConfig {
+            search_fallback: SearchFallback::Ancestors(12),
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "6954", &config).await.unwrap();
+        assert_eq!(data.change_percent, "+0.84%");
+
+        let config = ScraperConfig {
+            search_fallback: SearchFallback::Off,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "6954", &config).await.unwrap();
+        assert_eq!(data.change, "+35");
+        assert_eq!(data.change_percent, "");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_include_raw_change() {
+        let document = Html::parse_document(include_str!("fixtures/stock_9984_combined.html"));
+        let data = scrape_document(&document, "9984", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.change_raw, None);
+
+        let config = ScraperConfig {
+            include_raw_change: true,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "9984", &config).await.unwrap();
+        assert_eq!(data.change_raw.as_deref(), Some("+120(+1.52%)"));
+        assert_eq!(data.change, "+120");
+        assert_eq!(data.change_percent, "+1.52%");
+
+        // Spaces shown on the page are kept.
+        let html = include_str!("fixtures/stock_9984_combined.html").replace("+120(+1.52%)", " +120 (+1.52%) ");
+        let data = scrape_document(&Html::parse_document(&html), "9984", &config).await.unwrap();
+        assert_eq!(data.change_raw.as_deref(), Some("+120 (+1.52%)"));
+        assert_eq!(data.change, "+120");
+
+        // Separate elements: the split values are kept, and the raw text is their combination.
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &config).await.unwrap();
+        assert_eq!(data.change_raw.as_deref(), Some("-41 (-1.17%)"));
+        assert_eq!(data.change, "-41");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_price_with_unit() {
+        let html = include_str!("fixtures/fx_usdjpy.html").replace(">149.8520<", ">149.52円<");
+        let data = scrape_document(&Html::parse_document(&html), "USDJPY=FX", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "149.52円");
+        assert_eq!(data.unit.as_deref(), Some("円"));
+        assert_eq!(ValueParser::FX.parse(&data.price), Some(149.52));
+
+        let html = include_str!("fixtures/index_dji.html").replace(">40,123.45<", ">1,234 pt<");
+        let data = scrape_document(&Html::parse_document(&html), "^DJI", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "1,234 pt");
+        assert_eq!(data.unit.as_deref(), Some("pt"));
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.unit, None);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_include_ticks() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7203_ticks.html"));
+        let data = scrape_document(&document, "7203", &ScraperConfig::default()).await.unwrap();
+        assert!(data.recent_ticks.is_empty());
+
+        let config = ScraperConfig {
+            include_ticks: true,
+            max_ticks: 3,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "7203", &config).await.unwrap();
+        assert_eq!(data.recent_ticks.len(), 3);
+        assert_eq!(data.recent_ticks[0], ("15:00".to_string(), "2,851".to_string()));
+        assert_eq!(
+            serde_json::to_value(&data).unwrap()["recentTicks"][1],
+            serde_json::json!(["14:59:58", "2,850"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scrape_aggressive_price_guess() {
+        // A redesigned board that none of the price finders recognize.
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let broken = include_str!("fixtures/stock_6758_broken_layout.html");
+        let fetcher = MockFetcher::new().with_page(url, 200, broken);
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.price, "");
+        assert_eq!(data.price_confidence, None);
+
+        let config = ScraperConfig {
+            aggressive: true,
+            ..Default::default()
+        };
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.price_confidence, Some(Confidence::Guess));
+        assert_eq!(serde_json::to_value(&data).unwrap()["priceConfidence"], "Guess");
+        // The guess comes last: the page was still fetched once more first.
+        assert!(data.refetched);
+
+        // A price the finders do get is never replaced by a guess.
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_ne!(data.price_confidence, Some(Confidence::Guess));
+
+        // Nor is a price from the fallback host.
+        let mobile_url = "https://m.finance.yahoo.co.jp/quote/6758.T";
+        let config = ScraperConfig {
+            fallback_base_url: Some("https://m.finance.yahoo.co.jp".to_string()),
+            ..config
+        };
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, broken)
+            .with_page(mobile_url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.price_confidence, Some(Confidence::Low));
+        assert_eq!(fetcher.request_count(mobile_url), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_with_pts() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_pts.html"));
+        let data = scrape_document(&document, "8058", &ScraperConfig::default()).await.unwrap();
+        assert_eq!((data.pts_price.as_str(), data.pts_change.as_str()), ("", ""));
+
+        let config = ScraperConfig {
+            with_pts: true,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "8058", &config).await.unwrap();
+        assert_eq!(data.price, "2,874.5");
+        assert_eq!(data.pts_price, "2,880.0");
+        assert_eq!(data.pts_change, "+5.5");
+        assert_eq!(serde_json::to_value(&data).unwrap()["ptsPrice"], "2,880.0");
+
+        // No PTS quote while the market is open: the fields stay empty and out of the JSON.
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_session_open.html"));
+        let data = scrape_document(&document, "8058", &config).await.unwrap();
+        assert_eq!((data.pts_price.as_str(), data.pts_change.as_str()), ("", ""));
+        assert!(serde_json::to_value(&data).unwrap().get("ptsPrice").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_debug_elements() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert!(data.debug_elements.is_empty());
+
+        let config = ScraperConfig {
+            debug_elements: true,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "6758", &config).await.unwrap();
+        assert_eq!(
+            data.debug_elements.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["change", "change_percent", "code", "name", "price", "update_time"]
+        );
+        assert_eq!(data.debug_elements["name"], r#"<h2 class="PriceBoard__name__166W">ソニーグループ(株)</h2>"#);
+        assert!(data.debug_elements["price"].contains(">3,456<"));
+        assert_eq!(data.debug_match_counts["price"], 1);
+        assert_eq!(
+            data.debug_match_counts.keys().collect::<Vec<_>>(),
+            data.debug_elements.keys().collect::<Vec<_>>()
+        );
+
+        // A price taken from structured data wasn't read from any element.
+        let document = Html::parse_document(include_str!("fixtures/stock_7203_jsonld.html"));
+        let data = scrape_document(&document, "7203", &config).await.unwrap();
+        assert!(!data.debug_elements.contains_key("price"));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_pins_a_selector_that_matches_many_elements() {
+        // The news list before the price board has `<time>` elements too, so a bare `time`
+        // would read the first headline's date instead of the board's update time.
+        let document = Html::parse_document(include_str!("fixtures/stock_6758_many_times.html"));
+        let config = ScraperConfig {
+            debug_elements: true,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "6758", &config).await.unwrap();
+        assert_eq!(data.update_time, "15:00");
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.debug_elements["update_time"], "<time>15:00</time>");
+        assert_eq!(data.debug_match_counts["update_time"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_does_not_cache_positional_selectors() {
+        // The board's update time sits in markup shaped like the news list, so only its
+        // position (the fifth row) tells the two apart.
+        let html = include_str!("fixtures/stock_6758_many_times.html")
+            .replace(r#"<section class="NewsHeadline__2pQx">"#, "<section>")
+            .replace(
+                r#"<ul class="PriceBoard__times__3vgf"><li><time>15:00</time></li></ul>"#,
+                "<div><section><ul><li></li><li></li><li></li><li></li><li><time>15:00</time></li></ul></section></div>",
+            );
+        let cache = std::sync::Arc::new(selectors::SelectorCache::new());
+        let config = ScraperConfig {
+            selector_cache: Some(cache.clone()),
+            debug_elements: true,
+            ..Default::default()
+        };
+        let data = scrape_document(&Html::parse_document(&html), "6758", &config).await.unwrap();
+        assert_eq!(data.update_time, "15:00");
+        assert_eq!(data.debug_match_counts["update_time"], 1);
+        assert_eq!(cache.get(Market::TokyoStock, "update_time"), None);
+
+        // A selector pinned by its ancestors alone is kept.
+        let document = Html::parse_document(include_str!("fixtures/stock_6758_many_times.html"));
+        scrape_document(&document, "6758", &config).await.unwrap();
+        assert!(cache.get(Market::TokyoStock, "update_time").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_code_with_config_uses_the_given_config() {
+        let config = ScraperConfig {
+            proxy: Some("not a proxy url".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(scrape_code_with_config("6758", &config).await, Err(ScraperError::InvalidConfig(_))));
+
+        // Rejected before any request is made: five-digit codes need `code_lengths` widened.
+        assert!(matches!(
+            scrape_code_with_config("25935", &ScraperConfig::default()).await,
+            Err(ScraperError::InvalidCode(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_prefers_structured_price() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7203_jsonld.html"));
+        let data = scrape_document(&document, "7203", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "2845.5");
+        assert_eq!(data.price_confidence, Some(Confidence::High));
+
+        // A user selector still wins over the structured data.
+        let config = ScraperConfig {
+            selector_overrides: HashMap::from([("price".to_string(), "span.Ranking__count__1sQx".to_string())]),
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "7203", &config).await.unwrap();
+        assert_eq!(data.price, "1024");
+        assert_eq!(data.price_confidence, Some(Confidence::High));
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.price_confidence, Some(Confidence::Medium));
+
+        // JSON-LD for another instrument is ignored, and a `<meta>` price ranks below the static selector.
+        let html = include_str!("fixtures/stock_6758.html").replace(
+            "</head>",
+            r#"<script type="application/ld+json">{"tickerSymbol": "8058", "offers": {"price": "2,900"}}</script>
+            <meta itemprop="price" content="1,024"></head>"#,
+        );
+        let data = scrape_document(&Html::parse_document(&html), "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.price_confidence, Some(Confidence::Medium));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_with_margin_fetches_the_margin_page() {
+        let quote_url = "https://finance.yahoo.co.jp/quote/7203.T";
+        let margin_url = "https://finance.yahoo.co.jp/quote/7203.T/margin";
+        let fetcher = MockFetcher::new()
+            .with_page(quote_url, 200, include_str!("fixtures/stock_7203.html"))
+            .with_page(margin_url, 200, include_str!("fixtures/stock_7203_margin.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/6758.T", 200, include_str!("fixtures/stock_6758.html"));
+
+        let data = scrape_dynamically(&fetcher, "7203").await.unwrap();
+        assert_eq!(data.margin_buy, None);
+        assert_eq!(fetcher.request_count(margin_url), 0);
+
+        let config = ScraperConfig {
+            with_margin: true,
+            ..Default::default()
+        };
+        let data = scrape_dynamically_with_config(&fetcher, "7203", &config).await.unwrap();
+        assert_eq!(data.margin_buy.as_deref(), Some("4,123,800株"));
+        assert_eq!(data.margin_sell.as_deref(), Some("1,021,300株"));
+        assert_eq!(fetcher.request_count(margin_url), 1);
+
+        // The quote page already shows them: no extra request.
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.margin_buy.as_deref(), Some("2,345,600株"));
+        assert_eq!(fetcher.request_count("https://finance.yahoo.co.jp/quote/6758.T/margin"), 0);
+
+        // No margin page: the balances stay empty, the quote is still returned.
+        let fetcher = MockFetcher::new()
+            .with_page(quote_url, 200, include_str!("fixtures/stock_7203.html"))
+            .with_page(margin_url, 404, "Not Found");
+        let data = scrape_dynamically_with_config(&fetcher, "7203", &config).await.unwrap();
+        assert_eq!(data.margin_buy, None);
+        assert!(!data.price.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_with_margin_through_the_page_cache() {
+        let dir = std::env::temp_dir().join(format!("auto_selecter1_margin_cache_{}", std::process::id()));
+        let quote_url = "https://finance.yahoo.co.jp/quote/7203.T";
+        let margin_url = "https://finance.yahoo.co.jp/quote/7203.T/margin";
+        let inner = MockFetcher::new()
+            .with_page(quote_url, 200, include_str!("fixtures/stock_7203.html"))
+            .with_page(margin_url, 200, include_str!("fixtures/stock_7203_margin.html"));
+        let fetcher = fetcher::CachingFetcher::new(inner, &dir, None).unwrap();
+        let config = ScraperConfig {
+            with_margin: true,
+            ..Default::default()
+        };
+
+        // The second run reads both pages back from disk, each from its own file.
+        for _ in 0..2 {
+            let data = scrape_dynamically_with_config(&fetcher, "7203", &config).await.unwrap();
+            assert_eq!(data.margin_buy.as_deref(), Some("4,123,800株"));
+            assert_eq!(data.margin_sell.as_deref(), Some("1,021,300株"));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_include_related() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_lists.html"));
+        let data = scrape_document(&document, "8058", &ScraperConfig::default()).await.unwrap();
+        assert!(data.related_codes.is_empty());
+        assert!(serde_json::to_value(&data).unwrap().get("relatedCodes").is_none());
+
+        let config = ScraperConfig {
+            include_related: true,
+            ..Default::default()
+        };
+        let data = scrape_document(&document, "8058", &config).await.unwrap();
+        assert_eq!(data.related_codes, ["8031", "8001", "8053", "8002"]);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_chart_range() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_chart.html"));
+        let data = scrape_document(&document, "8058", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "2,874.5");
+        assert_eq!(data.chart_range_high.as_deref(), Some("2,891.0"));
+        assert_eq!(data.chart_range_low.as_deref(), Some("2,846.5"));
+
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["chartRangeHigh"], "2,891.0");
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.chart_range_high, None);
+        assert_eq!(data.chart_range_low, None);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_prefers_separate_change_elements() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.change, "-41");
+        assert_eq!(data.change_percent, "-1.17%");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_margin_balances() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.margin_buy.as_deref(), Some("2,345,600株"));
+        assert_eq!(data.margin_sell.as_deref(), Some("312,400株"));
+
+        let parsed = ValueParser::STOCK.parse_stock_data(&data);
+        assert_eq!(parsed.margin_buy, Some(2_345_600.0));
+        assert_eq!(parsed.margin_sell, Some(312_400.0));
+        assert_eq!(data.open.as_deref(), Some("3,480"));
+        assert_eq!(data.previous_close.as_deref(), Some("3,497"));
+        assert_eq!(parsed.gap, Some(-17.0));
+
+        let document = Html::parse_document(include_str!("fixtures/stock_7203.html"));
+        let data = scrape_document(&document, "7203", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.margin_buy, None);
+        assert_eq!(data.margin_sell, None);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_price_without_zenjitsuhi_anchor() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7974_genzaine.html"));
+        let data = scrape_document(&document, "7974", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.price, "8,123");
+        assert_eq!(data.code, "7974");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_detects_limit_up() {
+        use crate::models::TradingState;
+
+        let document = Html::parse_document(include_str!("fixtures/stock_4755_limit_up.html"));
+        let data = scrape_document(&document, "4755", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.trading_state, Some(TradingState::LimitUp));
+        assert_eq!(data.price, "1,120");
+        assert_eq!(data.change, "+150");
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.trading_state, Some(TradingState::Normal));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_direction_from_modifier_class() {
+        use crate::models::Direction;
+
+        let cases = [
+            (include_str!("fixtures/stock_7203_change_up.html"), "7203", Some(Direction::Up)),
+            (include_str!("fixtures/stock_8306_change_down.html"), "8306", Some(Direction::Down)),
+            (include_str!("fixtures/stock_9432_change_flat.html"), "9432", Some(Direction::Flat)),
+            (include_str!("fixtures/stock_6758.html"), "6758", None),
+        ];
+        for (html, code, expected) in cases {
+            let document = Html::parse_document(html);
+            let data = scrape_document(&document, code, &ScraperConfig::default()).await.unwrap();
+            assert_eq!(data.direction, expected, "{}", code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_selector_precedence() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let cache = std::sync::Arc::new(selectors::SelectorCache::new());
+        let mut config = ScraperConfig {
+            selector_cache: Some(cache.clone()),
+            ..Default::default()
+        };
+
+        // Nothing user-supplied: the static selector gives the price, and the update time,
+        // which has no static selector, is discovered and cached.
+        let data = scrape_document(&document, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.update_time, "15:00");
+        assert_eq!(cache.get(Market::TokyoStock, "price"), None);
+        assert!(cache.get(Market::TokyoStock, "update_time").is_some());
+
+        // A user selector wins over the static one.
+        config.selector_overrides.insert("price".to_string(), "span.StyledNumber__suffix__2SD5".to_string());
+        let data = scrape_document(&document, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "株");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_session_status() {
+        for (fixture, status, phase) in [
+            (include_str!("fixtures/stock_8058_session_open.html"), "ザラ場", SessionPhase::Open),
+            (include_str!("fixtures/stock_8058_session_closed.html"), "引け後", SessionPhase::Closed),
+        ] {
+            let document = Html::parse_document(fixture);
+            let data = scrape_document(&document, "8058", &ScraperConfig::default()).await.unwrap();
+            assert_eq!(data.session_status.as_deref(), Some(status));
+            assert_eq!(data.session_phase, Some(phase));
+            assert_eq!(data.price, "2,874.5");
+        }
+
+        // No marker on the board.
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.session_status, None);
+        assert_eq!(data.session_phase, None);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_keeps_page_title() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(
+            data.page_title.as_deref(),
+            Some("ソニーグループ(株)【6758】：株価・株式情報 - Yahoo!ファイナンス")
+        );
+    }
+
+    #[test]
+    fn test_title_matches() {
+        let title = "ソニーグループ(株)【6758】：株価・株式情報 - Yahoo!ファイナンス";
+        assert!(title_matches(title, "6758", ""));
+        assert!(title_matches(title, "6758.T", ""));
+        assert!(title_matches("米ドル/円【USDJPY】：外国為替 - Yahoo!ファイナンス", "USDJPY=FX", "米ドル/円"));
+        assert!(title_matches("NYダウ - Yahoo!ファイナンス", "%5EDJI", "NYダウ"));
+        assert!(!title_matches("Yahoo! JAPAN - 同意の確認", "6758", "ソニーグループ(株)"));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_english_name() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7203.html"));
+        let data = scrape_document(&document, "7203", &ScraperConfig::default()).await.unwrap();
+
+        assert_eq!(data.name, "トヨタ自動車(株)");
+        assert_eq!(data.name_en.as_deref(), Some("TOYOTA MOTOR CORPORATION"));
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let data = scrape_document(&document, "6758", &ScraperConfig::default()).await.unwrap();
+        assert!(data.name_en.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_fx_has_no_english_name() {
+        // The Latin "Bid" label sits next to the pair's name, where the English name finder looks.
+        let document = Html::parse_document(include_str!("fixtures/fx_usdjpy.html"));
+        let data = scrape_document(&document, "USDJPY=FX", &ScraperConfig::default()).await.unwrap();
+        assert!(scraper_logic::find_english_name(&document, &data.name).is_some());
+        assert_eq!(data.name_en, None);
+        assert!(serde_json::to_value(&data).unwrap().get("nameEn").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_index_page() {
+        let document = Html::parse_document(include_str!("fixtures/index_dji.html"));
+        let data = scrape_document(&document, "^DJI", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.market, Some(Market::Dji));
+
+        assert_eq!(data.name, "NYダウ");
+        assert_eq!(data.code, "^DJI");
+        assert_eq!(data.price, "40,123.45");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_index_name_in_h1() {
+        let document = Html::parse_document(include_str!("fixtures/index_n225_h1_name.html"));
+        let data = scrape_document(&document, "998407.O", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.name, "日経平均株価");
+        assert_eq!(data.price, "38,502.13");
+        assert_eq!(data.change, "-212.48");
+        assert_eq!(data.change_percent, "-0.55%");
+        assert_eq!(data.update_time, "15:15");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_document_index_level_among_other_numbers() {
+        let document = Html::parse_document(include_str!("fixtures/index_dji_numbers.html"));
+        let data = scrape_document(&document, "^DJI", &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.name, "NYダウ");
+        assert_eq!(data.price, "40,123.45");
+        assert_eq!(data.change, "+123.45");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_with_mock_fetcher() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert!(!data.refetched);
+        assert_eq!(fetcher.request_count(url), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_refetches_page_without_price() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        // The page as served before the price board finished rendering.
+        let name_only_page = include_str!("fixtures/stock_6758_name_only.html");
+
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, name_only_page)
+            .with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert!(data.refetched);
+        assert_eq!(fetcher.request_count(url), 2);
+
+        // Only one extra fetch, even when the price never shows up.
+        let fetcher = MockFetcher::new().with_page(url, 200, name_only_page);
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.name, "ソニーグループ(株)");
+        assert_eq!(data.price, "");
+        assert!(data.refetched);
+        assert_eq!(fetcher.request_count(url), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_refetch_skips_the_page_cache() {
+        let dir = std::env::temp_dir().join(format!("auto_selecter1_refetch_cache_{}", std::process::id()));
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let inner = MockFetcher::new()
+            .with_page(url, 200, include_str!("fixtures/stock_6758_name_only.html"))
+            .with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+        let fetcher = fetcher::CachingFetcher::new(inner, &dir, None).unwrap();
+
+        // The refetch goes past the cached name-only page, and the full page replaces it.
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert!(data.refetched);
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert!(!data.refetched);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_falls_back_to_the_secondary_host() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let mobile_url = "https://m.finance.yahoo.co.jp/quote/6758.T";
+        let config = ScraperConfig {
+            fallback_base_url: Some("https://m.finance.yahoo.co.jp/".to_string()),
+            ..Default::default()
+        };
+
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, include_str!("fixtures/stock_6758_name_only.html"))
+            .with_page(mobile_url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.change, "-41");
+        assert_eq!(data.name, "ソニーグループ(株)");
+        assert_eq!(fetcher.request_count(url), 2);
+        assert_eq!(fetcher.request_count(mobile_url), 1);
+
+        // A challenge page instead of the quote page: the secondary's record is used as a whole.
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, "<html><body><p>Please verify you are a human.</p></body></html>")
+            .with_page(mobile_url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+
+        // A page that has its price never touches the secondary.
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+        scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(fetcher.request_count(mobile_url), 0);
+
+        // When the secondary fails too, the primary's result stands.
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, include_str!("fixtures/stock_6758_name_only.html"))
+            .with_page(mobile_url, 503, "");
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "");
+        assert_eq!(data.name, "ソニーグループ(株)");
+
+        // Selectors meant for the primary layout aren't tried on the secondary's page: the user's
+        // price selector matches the "株" suffix there.
+        let overridden = ScraperConfig {
+            selector_overrides: HashMap::from([("price".to_string(), "span.StyledNumber__suffix__2SD5".to_string())]),
+            ..config.clone()
+        };
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, include_str!("fixtures/stock_6758_name_only.html"))
+            .with_page(mobile_url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_dynamically_with_config(&fetcher, "6758", &overridden).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(data.price_confidence, Some(Confidence::Low));
+
+        // Links on the secondary's page are resolved against its own URL.
+        let fetcher = MockFetcher::new()
+            .with_page("https://finance.yahoo.co.jp/quote/6501.T", 200, "<html><body><p>Please verify you are a human.</p></body></html>")
+            .with_page("https://m.finance.yahoo.co.jp/quote/6501.T", 200, include_str!("fixtures/stock_6501_logo.html"));
+        let data = scrape_dynamically_with_config(&fetcher, "6501", &config).await.unwrap();
+        assert_eq!(data.image_url.as_deref(), Some("https://m.finance.yahoo.co.jp/images/company_logo/6501.png"));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_reuses_result_when_page_not_modified() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let fetcher = MockFetcher::new();
+        fetcher.push(
+            url,
+            Ok(fetcher::Response {
+                status: 200,
+                validators: fetcher::Validators {
+                    etag: Some("\"v1\"".to_string()),
+                    last_modified: None,
+                },
+                body: include_str!("fixtures/stock_6758.html").to_string(),
+                ..Default::default()
+            }),
+        );
+        fetcher.push(url, Ok(fetcher::Response { status: 304, ..Default::default() }));
+        let config = ScraperConfig {
+            conditional_cache: Some(std::sync::Arc::new(fetcher::ConditionalCache::new())),
+            ..Default::default()
+        };
+
+        let first = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        let second = scrape_dynamically_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(second.price, first.price);
+        assert_eq!(second.name, "ソニーグループ(株)");
+        assert_eq!(fetcher.request_count(url), 2);
+
+        // Without a stored result a 304 can't be served from the cache.
+        let err = scrape_dynamically(&fetcher, "6758").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ScraperError>(), Some(ScraperError::UnexpectedRedirect(_))));
+    }
+
+    /// Pins the keys consumers of the JSON output see. A new optional field is a deliberate
+    /// change to this list; a renamed one breaks consumers and must not slip in unnoticed.
+    #[tokio::test]
+    async fn test_json_output_shape() {
+        let fetcher = MockFetcher::new()
+            .with_page("https://finance.yahoo.co.jp/quote/6758.T", 200, include_str!("fixtures/stock_6758.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/USDJPY=FX", 200, include_str!("fixtures/fx_usdjpy.html"));
+        let codes = vec!["6758".to_string(), "USDJPY=FX".to_string()];
+        let records: Vec<StockData> = scrape_batch(&fetcher, &codes, &ScraperConfig::default())
+            .await
+            .into_iter()
+            .map(|(_, result)| result.unwrap())
+            .collect();
+
+        let output = serde_json::json!(records);
+        let keys = |index: usize| -> Vec<String> {
+            let mut keys: Vec<String> = output[index].as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(
+            keys(0),
+            [
+                "changeAbs", "changePercent", "code", "marginBuy", "marginSell", "market", "name", "open",
+                "pageTitle", "previousClose", "price", "priceConfidence", "scrapeMs", "tradingState", "updateTime",
+            ]
+        );
+        assert_eq!(
+            keys(1),
+            [
+                "changeAbs", "changePercent", "code", "market", "name", "pageTitle", "price", "priceConfidence",
+                "scrapeMs", "updateTime",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_rejects_error_status() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let fetcher = MockFetcher::new().with_page(url, 503, "Service Unavailable");
+
+        let err = scrape_dynamically(&fetcher, "6758").await.unwrap_err();
+        assert!(err.to_string().contains("503"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scrape_retries_only_retryable_errors() {
+        let config = ScraperConfig {
+            max_retries: 2,
+            ..Default::default()
+        };
+
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let fetcher = MockFetcher::new();
+        fetcher.push(url, Err(ScraperError::Network("connection reset".to_string())));
+        fetcher.push(
+            url,
+            Ok(fetcher::Response {
+                status: 200,
+                body: include_str!("fixtures/stock_6758.html").to_string(),
+                ..Default::default()
+            }),
+        );
+        let data = scrape_with_config(&fetcher, "6758", &config).await.unwrap();
+        assert_eq!(data.price, "3,456");
+        assert_eq!(fetcher.request_count(url), 2);
+
+        let url = "https://finance.yahoo.co.jp/quote/9999.T";
+        let fetcher = MockFetcher::new().with_page(url, 404, "Not Found");
+        let err = scrape_with_config(&fetcher, "9999", &config).await.unwrap_err();
+        assert!(matches!(err, ScraperError::NotFound(_)));
+        assert_eq!(fetcher.request_count(url), 1);
+
+        let url = "https://finance.yahoo.co.jp/quote/7203.T";
+        let fetcher = MockFetcher::new().with_page(url, 503, "Service Unavailable");
+        let err = scrape_with_config(&fetcher, "7203", &config).await.unwrap_err();
+        assert!(matches!(err, ScraperError::HttpStatus(503, _)));
+        assert_eq!(fetcher.request_count(url), 3);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_url_custom_path() {
+        let url = "https://finance.yahoo.co.jp/portfolio/detail/sony?lang=ja#board";
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+        let data = scrape_url_with(&fetcher, url, &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.code, "6758");
+        assert_eq!(data.price, "3,456");
+
+        let url = "https://finance.yahoo.co.jp/quote/%5EDJI?term=1d";
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/index_dji.html"));
+        let data = scrape_url_with(&fetcher, url, &ScraperConfig::default()).await.unwrap();
+        assert_eq!(data.code, "^DJI");
+        assert_eq!(data.market, Some(Market::Dji));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_five_digit_code_when_configured() {
+        let url = "https://finance.yahoo.co.jp/quote/25935.T";
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/stock_25935_five_digit.html"));
+
+        let err = scrape_with_config(&fetcher, "25935", &ScraperConfig::default()).await.unwrap_err();
+        assert!(matches!(err, ScraperError::InvalidCode(_)));
+
+        let config = ScraperConfig {
+            code_lengths: 4..=5,
+            ..Default::default()
+        };
+        let data = scrape_with_config(&fetcher, "25935", &config).await.unwrap();
+        assert_eq!(data.code, "25935");
+        assert_eq!(data.price, "1,208");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_resolves_logo_url() {
+        let url = "https://finance.yahoo.co.jp/quote/6501.T";
+        let fetcher = MockFetcher::new()
+            .with_page(url, 200, include_str!("fixtures/stock_6501_logo.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/6758.T", 200, include_str!("fixtures/stock_6758.html"));
+
+        let data = scrape_dynamically(&fetcher, "6501").await.unwrap();
+        assert_eq!(data.image_url.as_deref(), Some("https://finance.yahoo.co.jp/images/company_logo/6501.png"));
+
+        // No logo in the price board.
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert_eq!(data.image_url, None);
+    }
+
+    #[test]
+    fn test_code_from_url() {
+        assert_eq!(code_from_url("https://finance.yahoo.co.jp/quote/6758.T/news?page=2").as_deref(), Some("6758"));
+        assert_eq!(code_from_url("https://finance.yahoo.co.jp/quote/USDJPY=FX").as_deref(), Some("USDJPY=FX"));
+        assert_eq!(code_from_url("https://finance.yahoo.co.jp/quote/"), None);
+        assert_eq!(code_from_url("https://example.com/sony"), None);
+    }
+
+    /// Records when each request starts, then serves the same page for every URL.
+    struct TimingFetcher {
+        starts: std::sync::Mutex<Vec<tokio::time::Instant>>,
+    }
+
+    impl Fetcher for TimingFetcher {
+        async fn get(&self, _url: &str) -> Result<fetcher::Response, ScraperError> {
+            self.starts.lock().unwrap().push(tokio::time::Instant::now());
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            Ok(fetcher::Response {
+                status: 200,
+                body: include_str!("fixtures/stock_6758.html").to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scrape_dynamically_records_duration() {
+        let fetcher = TimingFetcher { starts: Default::default() };
+        let data = scrape_dynamically(&fetcher, "6758").await.unwrap();
+        assert!(data.scrape_ms >= 300);
+    }
+
+    /// Answers every page immediately except the given slow code, which takes 5 seconds.
+    struct SlowCodeFetcher {
+        slow_code: &'static str,
+    }
+
+    impl Fetcher for SlowCodeFetcher {
+        async fn get(&self, url: &str) -> Result<fetcher::Response, ScraperError> {
+            if url.contains(self.slow_code) {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            Ok(fetcher::Response {
+                status: 200,
+                body: include_str!("fixtures/stock_6758.html").to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scrape_batch_times_out_slow_code_only() {
+        let fetcher = SlowCodeFetcher { slow_code: "7203" };
+        let codes: Vec<String> = vec!["6758".to_string(), "7203".to_string(), "8729".to_string()];
+        let config = ScraperConfig {
+            timeout_per_code: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+
+        let results = scrape_batch(&fetcher, &codes, &config).await;
+        assert!(results[0].1.is_ok());
+        assert!(matches!(
+            &results[1].1,
+            Err(ScraperError::CodeTimeout(code, limit)) if code == "7203" && *limit == Duration::from_secs(1)
+        ));
+        assert!(results[2].1.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_batch_scrapes_repeated_codes_once() {
+        let stock_url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let dji_url = "https://finance.yahoo.co.jp/quote/%5EDJI";
+        let fetcher = MockFetcher::new()
+            .with_page(stock_url, 200, include_str!("fixtures/stock_6758.html"))
+            .with_page(dji_url, 200, include_str!("fixtures/index_dji.html"));
+        let codes: Vec<String> = ["6758", "^dji", "6758", "^DJI"].iter().map(|c| c.to_string()).collect();
+
+        let results = scrape_batch(&fetcher, &codes, &ScraperConfig::default()).await;
+        assert_eq!(results.iter().map(|(code, _)| code.as_str()).collect::<Vec<_>>(), ["6758", "^dji", "6758", "^DJI"]);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(results[3].1.as_ref().unwrap().price, "40,123.45");
+        assert_eq!(fetcher.request_count(stock_url), 1);
+        assert_eq!(fetcher.request_count(dji_url), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_batch_reports_progress_for_every_code() {
+        let fetcher = MockFetcher::new()
+            .with_page("https://finance.yahoo.co.jp/quote/6758.T", 200, include_str!("fixtures/stock_6758.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/9999.T", 404, "");
+        let codes: Vec<String> = ["6758", "9999", "6758"].iter().map(|c| c.to_string()).collect();
+        let calls = std::sync::Mutex::new(Vec::new());
+
+        let results = scrape_batch_with_progress(&fetcher, &codes, &ScraperConfig::default(), |done, total, code, result| {
+            calls.lock().unwrap().push((done, total, code.to_string(), result.is_ok()));
+        })
+        .await;
+        assert!(results[1].1.is_err());
+
+        let mut calls = calls.into_inner().unwrap();
+        assert_eq!(calls.iter().map(|(done, total, _, _)| (*done, *total)).collect::<Vec<_>>(), [(1, 2), (2, 2)]);
+        calls.sort_by(|a, b| a.2.cmp(&b.2));
+        assert_eq!(
+            calls.iter().map(|(_, _, code, ok)| (code.as_str(), *ok)).collect::<Vec<_>>(),
+            [("6758", true), ("9999", false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scrape_map_keys_by_normalized_code() {
+        let fetcher = MockFetcher::new()
+            .with_page("https://finance.yahoo.co.jp/quote/6758.T", 200, include_str!("fixtures/stock_6758.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/%5EDJI", 200, include_str!("fixtures/index_dji.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/USDJPY=FX", 200, include_str!("fixtures/fx_usdjpy.html"));
+        let codes: Vec<String> = [" 6758", "^dji", "DJI", "usdjpy=x", "USDJPY=FX"].iter().map(|c| c.to_string()).collect();
+
+        let results = scrape_map_with(&fetcher, &codes, &ScraperConfig::default()).await;
+        let mut keys: Vec<&str> = results.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["6758", "USDJPY=FX", "^DJI"]);
+        assert_eq!(results["6758"].as_ref().unwrap().price, "3,456");
+        assert_eq!(results["^DJI"].as_ref().unwrap().price, "40,123.45");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_indices() {
+        let nikkei_url = "https://finance.yahoo.co.jp/quote/998407.O";
+        let dji_url = "https://finance.yahoo.co.jp/quote/%5EDJI";
+        let fetcher = MockFetcher::new()
+            .with_page(nikkei_url, 200, include_str!("fixtures/index_n225_h1_name.html"))
+            .with_page(dji_url, 200, include_str!("fixtures/index_dji.html"));
+
+        let indices = scrape_indices_with(&fetcher, &ScraperConfig::default()).await.unwrap();
+        let summary: Vec<(&str, &str, Option<Market>)> = indices
+            .iter()
+            .map(|data| (data.name.as_str(), data.price.as_str(), data.market))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("日経平均株価", "38,502.13", Some(Market::Nikkei)),
+                ("NYダウ", "40,123.45", Some(Market::Dji)),
+            ]
+        );
+
+        let failing = MockFetcher::new().with_page(nikkei_url, 503, "Service Unavailable");
+        assert!(scrape_indices_with(&failing, &ScraperConfig::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_batch_normalizes_decimals() {
+        let fetcher = MockFetcher::new()
+            .with_page("https://finance.yahoo.co.jp/quote/6758.T", 200, include_str!("fixtures/stock_6758.html"))
+            .with_page("https://finance.yahoo.co.jp/quote/USDJPY=FX", 200, include_str!("fixtures/fx_usdjpy.html"));
+        let codes = vec!["6758".to_string(), "USDJPY=FX".to_string()];
+        let config = ScraperConfig {
+            decimal_precision: Some(value_parser::DecimalPrecision {
+                stock: Some(1),
+                fx: Some(2),
+                ..value_parser::DecimalPrecision::DISPLAY
+            }),
+            ..Default::default()
+        };
+        let results = scrape_batch(&fetcher, &codes, &config).await;
+        let stock = results[0].1.as_ref().unwrap();
+        assert_eq!((stock.price.as_str(), stock.change.as_str()), ("3,456.0", "-41.0"));
+        assert_eq!(results[1].1.as_ref().unwrap().price, "149.85");
+
+        // Off by default.
+        let results = scrape_batch(&fetcher, &codes, &ScraperConfig::default()).await;
+        assert_eq!(results[0].1.as_ref().unwrap().price, "3,456");
+        assert_eq!(results[1].1.as_ref().unwrap().price, "149.8520");
+    }
+
+    #[tokio::test]
+    async fn test_scrape_batch_applies_post_process_hook() {
+        fn add_suffix(data: &mut StockData) {
+            data.name.push_str(" [checked]");
+        }
+
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let fetcher = MockFetcher::new().with_page(url, 200, include_str!("fixtures/stock_6758.html"));
+        let config = ScraperConfig {
+            post_process: Some(add_suffix),
+            ..Default::default()
+        };
+        let codes = vec!["6758".to_string(), "ABCDE".to_string()];
+
+        let results = scrape_batch(&fetcher, &codes, &config).await;
+        assert_eq!(results[0].1.as_ref().unwrap().name, "ソニーグループ(株) [checked]");
+        assert!(matches!(results[1].1, Err(ScraperError::InvalidCode(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scrape_batch_respects_rate_limit() {
+        let fetcher = TimingFetcher { starts: Default::default() };
+        let codes: Vec<String> = (0..8).map(|i| format!("{}", 6750 + i)).collect();
+        let config = ScraperConfig {
+            max_concurrent: 10,
+            requests_per_second: Some(2.0),
+            ..Default::default()
+        };
+
+        let results = scrape_batch(&fetcher, &codes, &config).await;
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+        let mut starts = fetcher.starts.lock().unwrap().clone();
+        starts.sort();
+        assert_eq!(starts.len(), 8);
+        // No more than 2 fetches may start within any one-second window.
+        for window in starts.windows(3) {
+            assert!(window[2] - window[0] >= std::time::Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_build_url_for_fx_codes() {
+        assert!(matches!(get_code_type("USDJPY=FX"), CodeType::Fx));
+        assert!(matches!(get_code_type("USDJPY=X"), CodeType::Fx));
+        assert_eq!(build_url_for("USDJPY=FX", CodeType::Fx), "https://finance.yahoo.co.jp/quote/USDJPY=FX");
+        assert_eq!(build_url_for("USDJPY=X", CodeType::Fx), "https://finance.yahoo.co.jp/quote/USDJPY=FX");
+        assert_eq!(build_url_for("EURJPY", CodeType::Fx), "https://finance.yahoo.co.jp/quote/EURJPY=FX");
+    }
+
+    #[test]
+    fn test_fx_suffixes_route_to_the_same_page() {
+        let expected_url = "https://finance.yahoo.co.jp/quote/USDJPY=FX";
+        for code in ["USDJPY=FX", "USDJPY=X", "usdjpy=fx", "usdjpy=x", " USDJPY=X "] {
+            assert_eq!(get_code_type(code), CodeType::Fx, "{}", code);
+            assert_eq!(normalize_code(code), "USDJPY=FX", "{}", code);
+            assert_eq!(build_url_for(&normalize_code(code), CodeType::Fx), expected_url, "{}", code);
+        }
+        assert_eq!(normalize_code("6758"), "6758");
+        assert_eq!(normalize_code("^DJI"), "^DJI");
+    }
+
+    #[test]
+    fn test_code_type_from_str() {
+        assert_eq!("fx".parse::<CodeType>(), Ok(CodeType::Fx));
+        assert_eq!("FX".parse::<CodeType>(), Ok(CodeType::Fx));
+        assert_eq!("stock".parse::<CodeType>(), Ok(CodeType::Stock(StockKind::Equity)));
+        assert_eq!("etf".parse::<CodeType>(), Ok(CodeType::Stock(StockKind::Etf)));
+        assert_eq!("Dow".parse::<CodeType>(), Ok(CodeType::Dji));
+        assert_eq!("nikkei".parse::<CodeType>(), Ok(CodeType::Nikkei));
+        assert!("bond".parse::<CodeType>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_code_type_override_forces_fx_handling() {
+        // "EURJPY" has no FX suffix, so it would be classified (and rejected) as a stock code.
+        assert!(validate_code("EURJPY").is_err());
+
+        let fetcher = MockFetcher::new().with_page(
+            "https://finance.yahoo.co.jp/quote/EURJPY=FX",
+            200,
+            include_str!("fixtures/fx_usdjpy.html"),
+        );
+        let config = ScraperConfig {
+            code_type: Some(CodeType::Fx),
+            ..Default::default()
+        };
+        let data = scrape_dynamically_with_config(&fetcher, "EURJPY", &config).await.unwrap();
+        assert_eq!(data.price, "149.8520");
+        assert_eq!(data.market, Some(Market::Fx));
+    }
+
+    #[test]
+    fn test_validate_code() {
+        assert!(validate_code("9432").is_ok());
+        assert!(validate_code("130A").is_ok());
+        assert!(validate_code("998407.O").is_ok());
+        assert!(validate_code("^DJI").is_ok());
+        assert!(validate_code("USDJPY=FX").is_ok());
+        assert!(validate_code("12").is_err());
+        assert!(validate_code("sony").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_dynamically_reports_unexpected_redirect() {
+        let url = "https://finance.yahoo.co.jp/quote/9999.T";
+        let fetcher = MockFetcher::new();
+        fetcher.push(
+            url,
+            Ok(fetcher::Response {
+                status: 302,
+                location: Some("https://finance.yahoo.co.jp/search/?query=9999".to_string()),
+                ..Default::default()
+            }),
+        );
+
+        let err = scrape_dynamically(&fetcher, "9999").await.unwrap_err();
+        let err = err.downcast_ref::<ScraperError>().unwrap();
+        assert!(matches!(err, ScraperError::UnexpectedRedirect(location) if location.contains("search")));
+    }
+
+    /// Serves a consent wall: `/quote/...` redirects to `/consent`, which answers with a page that
+    /// has a heading but no quote. Returns the base URL.
+    async fn serve_consent_wall() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 2048];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let response = if request.starts_with("GET /quote/") {
+                    "HTTP/1.1 302 Found\r\nLocation: /consent?sessionId=abc\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else {
+                    let body = "<html><body><h2>Yahoo! JAPANからのお知らせ</h2><button>同意する</button></body></html>";
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_scrape_reports_consent_redirect() {
+        let base = serve_consent_wall().await;
+        let url = format!("{}/quote/6758.T", base);
+        let err = scrape_url_with(&ReqwestFetcher::new(), &url, &ScraperConfig::default()).await.unwrap_err();
+        match err {
+            ScraperError::RedirectedAway(requested, final_url) => {
+                assert_eq!(requested, url);
+                assert_eq!(final_url, format!("{}/consent?sessionId=abc", base));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The same through `scrape_dynamically`, whose URL is built from the code; redirects between
+        // forms of the same quote page (the second response) are fine.
+        let quote_url = "https://finance.yahoo.co.jp/quote/6758.T";
+        let fetcher = MockFetcher::new();
+        fetcher.push(
+            quote_url,
+            Ok(fetcher::Response {
+                status: 200,
+                final_url: Some("https://consent.yahoo.co.jp/consent?sessionId=abc".to_string()),
+                body: include_str!("fixtures/stock_6758_name_only.html").to_string(),
+                ..Default::default()
+            }),
+        );
+        fetcher.push(
+            quote_url,
+            Ok(fetcher::Response {
+                status: 200,
+                final_url: Some("https://finance.yahoo.co.jp/quote/6758.T?ref=top".to_string()),
+                body: include_str!("fixtures/stock_6758.html").to_string(),
+                ..Default::default()
+            }),
+        );
+        let err = scrape_dynamically(&fetcher, "6758").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ScraperError>(), Some(ScraperError::RedirectedAway(_, _))));
+        assert_eq!(scrape_dynamically(&fetcher, "6758").await.unwrap().price, "3,456");
+    }
+
+    #[test]
+    fn test_parse_price() {
+        let data = StockData {
+            code: "6758".to_string(),
+            price: "3,456.5".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(parse_price(&data, ValueParser::STOCK).unwrap(), 3456.5);
+
+        let missing = StockData { code: "6758".to_string(), ..Default::default() };
+        assert!(parse_price(&missing, ValueParser::STOCK).is_err());
+
+        let garbage = StockData { price: "---".to_string(), ..Default::default() };
+        assert!(parse_price(&garbage, ValueParser::STOCK).is_err());
+    }
+}
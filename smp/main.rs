@@ -1,155 +1,1031 @@
-use reqwest;
-use scraper::{Html};
+use auto_selecter1::config::ScraperConfig;
+use auto_selecter1::cookies::load_cookie_file;
+use auto_selecter1::fetcher::{AdaptiveTimeout, CachingFetcher, Fetcher, ReqwestFetcher, Response, Validators};
+use auto_selecter1::models::{ScraperError, StockData};
+use auto_selecter1::output::{
+    read_completed_codes, read_failed_codes, write_failures, write_jsonl_line, write_table, BatchSummary,
+    ErrorRecord, NdjsonWriter, OutputFormat, OutputMeta,
+};
+#[cfg(feature = "arrow")]
+use auto_selecter1::output::write_parquet;
+use auto_selecter1::value_parser::DecimalPrecision;
+use auto_selecter1::{scrape_batch_with_progress, CodeType};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use serde::Deserialize;
 use serde_json::json;
-use std::error::Error;
+use std::collections::HashSet;
 use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Command-line options accepted by the scraper.
+#[derive(Debug, PartialEq, Parser)]
+#[command(
+    name = "smp",
+    version,
+    about = "Scrapes quotes from Yahoo! Finance Japan",
+    after_help = "Exit status:\n  0  success (including codes that failed without --strict or --fail-fast)\n  1  no codes were given\n  2  every code failed\n  3  a code failed under --strict or --fail-fast\n  4  invalid arguments\n  5  a config, codes, failures or cookie file, an AUTO_SELECTER_* variable, the proxy or the --cache-dir could not be used\n  6  any other error, such as an output file that could not be written"
+)]
+struct CliArgs {
+    /// Codes to scrape, e.g. 6758 7203 USDJPY=FX; an argument may hold several comma-separated codes.
+    #[arg(value_name = "CODE")]
+    codes: Vec<String>,
+    /// File with more codes, one or more per line (comma-separated); `#` starts a comment.
+    #[arg(long, value_name = "PATH")]
+    codes_file: Option<PathBuf>,
+    /// JSON file with default settings; flags given on the command line win over it.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Maximum number of codes to process; 0 means no cap.
+    #[arg(long, default_value_t = 0, value_name = "N")]
+    limit: usize,
+    /// Format of the records printed on stdout (json, jsonl or table), or parquet, written to the
+    /// --output-file instead (needs the `arrow` feature).
+    #[arg(long = "format", visible_alias = "output", default_value = "json", value_name = "FORMAT")]
+    output: OutputFormat,
+    /// NDJSON file each completed record is appended to as soon as it is scraped; with --format
+    /// parquet, the Parquet file written at the end instead.
+    #[arg(long = "output-file", visible_alias = "out", value_name = "PATH")]
+    output_file: Option<PathBuf>,
+    /// Skip codes already recorded in the --output-file.
+    #[arg(long, requires = "output_file")]
+    resume: bool,
+    /// JSON file the codes that failed are written to, with their errors, for --retry-failures.
+    #[arg(long, value_name = "PATH")]
+    failures_file: Option<PathBuf>,
+    /// Scrape the codes listed in a --failures-file of an earlier run (in addition to any given).
+    #[arg(long, value_name = "PATH")]
+    retry_failures: Option<PathBuf>,
+    /// fsync the --output-file after every record.
+    #[arg(long)]
+    fsync: bool,
+    /// Netscape-format cookie file loaded into the client before scraping.
+    #[arg(long, value_name = "PATH")]
+    cookies: Option<PathBuf>,
+    /// HTTP timeout of each request in seconds [env: AUTO_SELECTER_TIMEOUT_MS, in ms].
+    #[arg(long, value_parser = parse_seconds, value_name = "SECS")]
+    timeout: Option<Duration>,
+    /// Wall-clock budget for each code in seconds, covering all of its requests.
+    #[arg(long = "timeout-per-code", value_parser = parse_seconds, value_name = "SECS")]
+    timeout_per_code: Option<Duration>,
+    /// Bound each request by 4× the recent average latency (between 1 and 30 seconds).
+    #[arg(long)]
+    adaptive_timeout: bool,
+    /// Number of codes scraped at the same time.
+    #[arg(long, default_value_t = 1, value_parser = parse_concurrency, value_name = "N")]
+    concurrency: usize,
+    /// Forces the type every code is handled as (stock, etf, fx, dji or nikkei).
+    #[arg(long = "type", value_name = "TYPE")]
+    code_type: Option<CodeType>,
+    /// How many times a failed fetch is retried (network errors, 5xx and 429 only).
+    #[arg(long, default_value_t = 0, value_name = "N")]
+    retries: u32,
+    /// Proxy for all requests [env: AUTO_SELECTER_PROXY].
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+    /// User-Agent header sent with every request [env: AUTO_SELECTER_USER_AGENT].
+    #[arg(long, value_name = "UA")]
+    user_agent: Option<String>,
+    /// Directory fetched quote pages are saved to (one file per URL) and served from on later runs.
+    #[arg(long, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+    /// Maximum age in seconds of a --cache-dir page that is still served; older pages are fetched again.
+    #[arg(long, requires = "cache_dir", value_parser = parse_seconds, value_name = "SECS")]
+    cache_max_age: Option<Duration>,
+    /// Keep the change as shown, e.g. "+120(+1.52%)", in each record's changeRaw.
+    #[arg(long)]
+    include_raw_change: bool,
+    /// Collect the codes listed under 関連銘柄 (related stocks) into each record's relatedCodes.
+    #[arg(long)]
+    include_related: bool,
+    /// Fetch a stock's 信用残 page (/quote/<code>.T/margin) when its quote page lacks the margin balances.
+    #[arg(long)]
+    with_margin: bool,
+    /// Read the latest prints of a stock's 歩み値 (time and sales) table into recentTicks.
+    #[arg(long)]
+    include_ticks: bool,
+    /// How many 歩み値 rows --include-ticks keeps at most.
+    #[arg(long, requires = "include_ticks", value_name = "N", default_value_t = 10)]
+    max_ticks: usize,
+    /// Read a stock's PTS (after-hours) quote into ptsPrice and ptsChange, when the page shows one.
+    #[arg(long)]
+    with_pts: bool,
+    /// Format prices and changes with a fixed number of decimals per instrument type: FX rates to 3,
+    /// index levels and percentages to 2. Stock prices are left as scraped.
+    #[arg(long)]
+    normalize_decimals: bool,
+    /// When no price is found, guess it from the most price-like number on the page. The guess can
+    /// be wrong and is marked with priceConfidence "Guess".
+    #[arg(long)]
+    aggressive: bool,
+    /// Add each record's debugElements: the markup of the element every field was read from.
+    #[arg(long)]
+    debug_elements: bool,
+    /// Include failed codes in the JSON output as {"code", "error", "message"} objects: an
+    /// `errors` array next to the records (json), or lines of their own (jsonl).
+    #[arg(long)]
+    errors_in_output: bool,
+    /// Wrap the JSON output as {"meta": {"scraper_version", "schema"}, "results": [records]}
+    /// (jsonl: a {"meta": ...} first line), so stored output says which format it follows.
+    #[arg(long)]
+    with_meta: bool,
+    /// Stop at the first code that fails and exit with status 3.
+    #[arg(long, conflicts_with = "strict")]
+    fail_fast: bool,
+    /// Scrape every code, but exit with status 3 if any of them failed.
+    #[arg(long)]
+    strict: bool,
+    /// Suppress progress messages, notes and the closing summary line; warnings are still shown.
+    #[arg(short, long)]
+    quiet: bool,
+    /// The options given on the command line, as opposed to left at their defaults.
+    #[arg(skip)]
+    given: HashSet<String>,
+}
 
-mod models;
-mod scraper_logic;
+/// Prints the library's log records to stderr: "Warning: ..." and "Note: ..." for the warn and
+/// info levels, and "Debug: ..." (only shown with --debug-elements) for the rest.
+struct StderrLogger;
 
-use models::StockData;
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
 
-enum CodeType {
-    Stock,
-    Fx,
-    Dji,
-    Nikkei,
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let label = match record.level() {
+                log::Level::Error => "Error",
+                log::Level::Warn => "Warning",
+                log::Level::Info => "Note",
+                log::Level::Debug | log::Level::Trace => "Debug",
+            };
+            eprintln!("{}: {}", label, record.args());
+        }
+    }
+
+    fn flush(&self) {}
 }
 
-fn get_code_type(code: &str) -> CodeType {
-    let upper_code = code.to_uppercase();
-    if upper_code == "%5EDJI" || upper_code == "^DJI" || upper_code == "DJI" {
-        CodeType::Dji
-    } else if upper_code == "998407.O" || upper_code == ".N225" || upper_code == "%5EN225" {
-        CodeType::Nikkei
-    } else if code.ends_with("=FX") {
-        CodeType::Fx
+static LOGGER: StderrLogger = StderrLogger;
+
+/// The most detailed log level printed for the given flags.
+fn log_level(cli: &CliArgs) -> log::LevelFilter {
+    if cli.quiet {
+        log::LevelFilter::Warn
+    } else if cli.debug_elements {
+        log::LevelFilter::Debug
     } else {
-        CodeType::Stock
+        log::LevelFilter::Info
     }
 }
 
-/// Receives a stock code and returns a URL for Yahoo Finance.
-fn build_url_from_code(code: &str) -> String {
-    match get_code_type(code) {
-        CodeType::Dji => "https://finance.yahoo.co.jp/quote/%5EDJI".to_string(),
-        CodeType::Nikkei => "https://finance.yahoo.co.jp/quote/998407.O".to_string(),
-        CodeType::Fx => format!("https://finance.yahoo.co.jp/quote/{}", code),
-        CodeType::Stock => {
-            if code.ends_with(".O") {
-                format!("https://finance.yahoo.co.jp/quote/{}", code)
-            } else {
-                format!("https://finance.yahoo.co.jp/quote/{}.T", code)
-            }
-        }
+/// Settings read from the `--config` file. Each one applies only where the
+/// command line didn't give the corresponding flag.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    codes: Vec<String>,
+    format: Option<String>,
+    timeout: Option<f64>,
+    concurrency: Option<usize>,
+    retries: Option<u32>,
+    cookies: Option<PathBuf>,
+    #[serde(rename = "type")]
+    code_type: Option<String>,
+}
+
+/// Parses the raw arguments (without the program name).
+/// Positional arguments may contain several comma-separated codes.
+fn parse_args(args: Vec<String>) -> Result<CliArgs, clap::Error> {
+    let matches = CliArgs::command().try_get_matches_from(std::iter::once("smp".to_string()).chain(args))?;
+    let mut cli = CliArgs::from_arg_matches(&matches)?;
+    cli.codes = split_codes(cli.codes.iter().map(String::as_str));
+    cli.given = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect();
+    Ok(cli)
+}
+
+/// Splits comma-separated codes, dropping empty entries.
+fn split_codes<'a>(items: impl Iterator<Item = &'a str>) -> Vec<String> {
+    items
+        .flat_map(|item| item.split(','))
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads the codes of a `--codes-file`: comma- or line-separated, with `#` comments.
+fn parse_codes_file(text: &str) -> Vec<String> {
+    split_codes(text.lines().map(|line| line.split('#').next().unwrap_or_default()))
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && *s > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("expected a positive number of seconds, got {}", value))
+}
+
+fn parse_concurrency(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("expected a positive number, got {}", value)),
     }
 }
 
-/// Scrapes a single stock page dynamically without any prior knowledge of the stock's name.
-async fn scrape_dynamically(code: &str) -> Result<StockData, Box<dyn Error>> {
-    let url = build_url_from_code(code);
-    let code_type = get_code_type(code);
+/// Fills in the settings the command line didn't give from `file`, even where a flag was given
+/// with its default value.
+/// The file's codes are added after the ones given as arguments.
+fn apply_file_config(cli: &mut CliArgs, file: FileConfig) -> Result<(), String> {
+    cli.codes.extend(split_codes(file.codes.iter().map(String::as_str)));
+    let given = |id: &str| cli.given.contains(id);
+    if let (false, Some(format)) = (given("output"), file.format) {
+        cli.output = format.parse()?;
+    }
+    if let (None, Some(seconds)) = (cli.timeout, file.timeout) {
+        cli.timeout = Some(parse_seconds(&seconds.to_string())?);
+    }
+    if let (false, Some(concurrency)) = (given("concurrency"), file.concurrency) {
+        cli.concurrency = parse_concurrency(&concurrency.to_string())?;
+    }
+    if let (false, Some(retries)) = (given("retries"), file.retries) {
+        cli.retries = retries;
+    }
+    if cli.cookies.is_none() {
+        cli.cookies = file.cookies;
+    }
+    if let (None, Some(code_type)) = (cli.code_type, file.code_type) {
+        cli.code_type = Some(code_type.parse()?);
+    }
+    Ok(())
+}
 
-    let response = reqwest::get(&url).await?;
-    let body = response.text().await?;
-    let document = Html::parse_document(&body);
+fn load_file_config(path: &Path) -> Result<FileConfig, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
 
-    // 1. Find the name and its selector first.
-    let (_name_selector_opt, name_text) = scraper_logic::find_name_dynamically(&document).await?;
+/// Exit status when no codes were given.
+const EXIT_NO_CODES: i32 = 1;
+/// Exit status when every code failed, whatever the `FailurePolicy`.
+const EXIT_ALL_FAILED: i32 = 2;
+/// Exit status when a code failed under `FailurePolicy::FailFast` or `FailurePolicy::Strict`.
+const EXIT_SOME_FAILED: i32 = 3;
+/// Exit status for arguments clap rejects (clap's own default, 2, is taken by `EXIT_ALL_FAILED`).
+const EXIT_USAGE: i32 = 4;
+/// Exit status when a file, environment variable or setting the run depends on can't be used,
+/// e.g. an unreadable config file or an invalid proxy.
+const EXIT_SETUP: i32 = 5;
+/// Exit status for any other error, e.g. an output file that can't be written.
+const EXIT_ERROR: i32 = 6;
 
-    if name_text.is_empty() {
-        return Err(Box::new(models::ScraperError(
-            "Could not dynamically find a valid name.".to_string(),
-        )));
+/// The exit status for a `parse_args` error: 0 for `--help` and `--version`, `EXIT_USAGE` otherwise.
+fn usage_exit_code(error: &clap::Error) -> i32 {
+    if error.use_stderr() {
+        EXIT_USAGE
+    } else {
+        0
     }
+}
 
-    // 2. Use the found name as an anchor to find everything else.
-    let anchor_name = &name_text;
+/// What a failed code means for the batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailurePolicy {
+    /// Report the failure and carry on; the exit status is 0 unless every code failed (the default).
+    Lenient,
+    /// Stop at the first failure; the exit status is `EXIT_SOME_FAILED`.
+    FailFast,
+    /// Scrape every code; the exit status is `EXIT_SOME_FAILED` if any failed.
+    Strict,
+}
 
-    let code_selector_opt = scraper_logic::find_text_pattern_selector_near_anchor(&document, anchor_name, "code").await?;
-    
-    let price_selector_opt;
-    let change_selector_opt;
-    let change_percent_selector_opt;
-    let update_time_selector_opt;
+impl FailurePolicy {
+    fn from_cli(cli: &CliArgs) -> Self {
+        if cli.fail_fast {
+            FailurePolicy::FailFast
+        } else if cli.strict {
+            FailurePolicy::Strict
+        } else {
+            FailurePolicy::Lenient
+        }
+    }
 
-    match code_type {
-        CodeType::Fx => {
-            // FX-specific logic
-            price_selector_opt = scraper_logic::find_fx_price_selector(&document).await?;
-            change_selector_opt = scraper_logic::find_fx_change_selector(&document).await?;
-            change_percent_selector_opt = None; // User requested to not scrape change_percent for FX
-            update_time_selector_opt = scraper_logic::find_fx_update_time_selector(&document).await?;
+    /// The exit status for a finished (or stopped) batch of `codes` codes; see the `after_help` of
+    /// `CliArgs`. A batch that `--fail-fast` stopped before every code was tried hasn't "all failed".
+    fn exit_code(self, summary: &BatchSummary, codes: usize) -> i32 {
+        if summary.failed > 0 && summary.failed == codes {
+            EXIT_ALL_FAILED
+        } else if self != FailurePolicy::Lenient && summary.failed > 0 {
+            EXIT_SOME_FAILED
+        } else {
+            0
         }
-        CodeType::Dji => { // DJI-specific logic
-            price_selector_opt = scraper_logic::find_stock_price_selector(&document, anchor_name, code).await?;
-            change_selector_opt = scraper_logic::find_stock_change_selector(&document, anchor_name).await?;
-            change_percent_selector_opt = scraper_logic::find_stock_change_percent_selector(&document, anchor_name).await?;
-            update_time_selector_opt = scraper_logic::find_dji_update_time_selector(&document).await?;
+    }
+}
+
+/// The fetcher the CLI scrapes with: straight to the site, or through the `--cache-dir` page cache.
+enum CliFetcher {
+    Direct(ReqwestFetcher),
+    Cached(CachingFetcher<ReqwestFetcher>),
+}
+
+impl Fetcher for CliFetcher {
+    async fn get(&self, url: &str) -> Result<Response, ScraperError> {
+        match self {
+            CliFetcher::Direct(fetcher) => fetcher.get(url).await,
+            CliFetcher::Cached(fetcher) => fetcher.get(url).await,
         }
-        CodeType::Nikkei => { // Nikkei-specific logic
-            price_selector_opt = scraper_logic::find_stock_price_selector(&document, anchor_name, code).await?;
-            change_selector_opt = scraper_logic::find_stock_change_selector(&document, anchor_name).await?;
-            change_percent_selector_opt = scraper_logic::find_stock_change_percent_selector(&document, anchor_name).await?;
-            update_time_selector_opt = scraper_logic::find_nikkei_update_time_selector(&document).await?;
+    }
+
+    async fn get_conditional(&self, url: &str, validators: &Validators) -> Result<Response, ScraperError> {
+        match self {
+            CliFetcher::Direct(fetcher) => fetcher.get_conditional(url, validators).await,
+            CliFetcher::Cached(fetcher) => fetcher.get_conditional(url, validators).await,
         }
-        CodeType::Stock => {
-            // Stock-specific logic
-            let zenjitsuhi_anchor = "前日比";
-            price_selector_opt = scraper_logic::find_stock_price_selector(&document, anchor_name, code).await?;
-            change_selector_opt = scraper_logic::find_stock_change_selector(&document, zenjitsuhi_anchor).await?;
-            change_percent_selector_opt = scraper_logic::find_stock_change_percent_selector(&document, zenjitsuhi_anchor).await?;
-            update_time_selector_opt = scraper_logic::find_stock_update_time_selector(&document).await?;
+    }
+
+    async fn get_fresh(&self, url: &str) -> Result<Response, ScraperError> {
+        match self {
+            CliFetcher::Direct(fetcher) => fetcher.get_fresh(url).await,
+            CliFetcher::Cached(fetcher) => fetcher.get_fresh(url).await,
         }
     }
+}
 
-    // 3. Scrape data using the found selectors.
-    let mut scraped_data = StockData::default();
-    scraped_data.name = name_text;
-    scraped_data.code = scraper_logic::scrape_field(&document, &code_selector_opt, "code");
-    scraped_data.price = scraper_logic::scrape_field(&document, &price_selector_opt, "price");
-    scraped_data.change = scraper_logic::scrape_field(&document, &change_selector_opt, "change");
-    scraped_data.change_percent = scraper_logic::scrape_field(&document, &change_percent_selector_opt, "change_percent");
-    scraped_data.update_time = scraper_logic::scrape_field(&document, &update_time_selector_opt, "update_time");
+/// The fetcher for `config`, behind the `--cache-dir` cache when one is given. An error (such as an
+/// invalid proxy, or a cache directory that can't be created) is a setup failure, see `EXIT_SETUP`.
+fn build_fetcher(cli: &CliArgs, config: &ScraperConfig) -> Result<CliFetcher, String> {
+    let fetcher = ReqwestFetcher::with_config(config).map_err(|e| e.to_string())?;
+    match &cli.cache_dir {
+        Some(dir) => CachingFetcher::new(fetcher, dir, cli.cache_max_age)
+            .map(CliFetcher::Cached)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e)),
+        None => Ok(CliFetcher::Direct(fetcher)),
+    }
+}
 
-    // 4. Fill in missing data
-    if scraped_data.code.is_empty() {
-        scraped_data.code = code.to_string();
+/// Scrapes `codes` as one batch, so the concurrency and rate limits and the sharing of aliased
+/// codes (see `scrape_batch_with_progress`) span all of them, and hands each result to `handle`
+/// as soon as it arrives, in the order the codes finish. Failures are also reported on stderr and
+/// counted in the returned summary; under `FailurePolicy::FailFast` the batch stops at the first
+/// one, dropping the codes still in flight (those already finished are still handled).
+async fn run_batch(
+    fetcher: &impl Fetcher,
+    codes: &[String],
+    config: &ScraperConfig,
+    policy: FailurePolicy,
+    progress: &dyn Fn(String),
+    mut handle: impl FnMut(Result<StockData, ErrorRecord>) -> io::Result<()>,
+) -> io::Result<BatchSummary> {
+    let mut summary = BatchSummary::default();
+    let mut reported: HashSet<String> = HashSet::new();
+    // Handles one result (once per code); true when the batch should stop there.
+    let mut report = |code: String, result: Result<StockData, ScraperError>| -> io::Result<bool> {
+        if !reported.insert(code.clone()) {
+            return Ok(false);
+        }
+        match result {
+            Ok(data) => {
+                progress(format!("  -> {} done in {} ms", code, data.scrape_ms));
+                summary.record_success(&data);
+                handle(Ok(data))?;
+                Ok(false)
+            }
+            Err(e) => {
+                eprintln!("  -> Error scraping {}: {}", code, e);
+                summary.record_failure(&code);
+                handle(Err(ErrorRecord::new(&code, &e)))?;
+                Ok(policy == FailurePolicy::FailFast)
+            }
+        }
+    };
+
+    for code in codes {
+        progress(format!("Scraping code: {}", code));
     }
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let batch = scrape_batch_with_progress(fetcher, codes, config, |_, _, code, result| {
+        let _ = sender.send((code.to_string(), result.clone()));
+    });
+    tokio::pin!(batch);
+    let mut stop = false;
+    let results = loop {
+        tokio::select! {
+            biased;
+            Some((code, result)) = receiver.recv() => {
+                if report(code, result)? {
+                    stop = true;
+                    break Vec::new();
+                }
+            }
+            results = &mut batch => break results,
+        }
+    };
+    // What finished alongside the last result handled, then the codes that shared another's page.
+    while let Ok((code, result)) = receiver.try_recv() {
+        stop |= report(code, result)?;
+    }
+    for (code, result) in results {
+        if !stop {
+            stop |= report(code, result)?;
+        }
+    }
+    if stop {
+        eprintln!("Stopping after the first failure (--fail-fast)");
+    }
+    Ok(summary)
+}
 
-    Ok(scraped_data)
+/// The document printed in JSON mode: the bare array of records, or an object holding them under
+/// `results`, with `errors` for `--errors-in-output` and `meta` for `--with-meta`.
+fn json_output(records: &[StockData], errors: Option<&[ErrorRecord]>, with_meta: bool) -> serde_json::Value {
+    match (errors, with_meta) {
+        (None, false) => json!(records),
+        (Some(errors), false) => json!({ "results": records, "errors": errors }),
+        (None, true) => json!({ "meta": OutputMeta::current(), "results": records }),
+        (Some(errors), true) => json!({ "meta": OutputMeta::current(), "results": records, "errors": errors }),
+    }
+}
+
+/// Builds the list of codes that will actually be scraped.
+///
+/// The order of operations is: collect codes from the arguments,
+/// de-duplicate them (the first occurrence wins), then truncate the
+/// result to `limit` entries. A `limit` of 0 means no cap.
+fn effective_codes(codes: &[String], limit: usize) -> Vec<String> {
+    let mut unique_codes: Vec<String> = Vec::new();
+    for code in codes {
+        if !unique_codes.contains(code) {
+            unique_codes.push(code.clone());
+        }
+    }
+    if limit > 0 {
+        unique_codes.truncate(limit);
+    }
+    unique_codes
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", e);
+        std::process::exit(EXIT_ERROR);
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().skip(1).collect();
-    let mut stock_codes: Vec<String> = Vec::new();
-    for arg in args {
-        for code in arg.split(',') {
-            stock_codes.push(code.to_string());
+    let mut cli = match parse_args(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(usage_exit_code(&e));
+        }
+    };
+    if let Some(path) = cli.config.clone() {
+        if let Err(e) = load_file_config(&path).and_then(|file| apply_file_config(&mut cli, file)) {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_SETUP);
+        }
+    }
+    if let Some(path) = &cli.codes_file {
+        match fs::read_to_string(path) {
+            Ok(text) => cli.codes.extend(parse_codes_file(&text)),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                std::process::exit(EXIT_SETUP);
+            }
+        }
+    }
+    if let Some(path) = &cli.retry_failures {
+        match read_failed_codes(path) {
+            Ok(codes) => cli.codes.extend(codes),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                std::process::exit(EXIT_SETUP);
+            }
         }
     }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log_level(&cli));
+    }
+    if cli.output == OutputFormat::Parquet && (cli.output_file.is_none() || cli.resume) {
+        eprintln!("--format parquet needs an --output-file to write to, and can't --resume");
+        std::process::exit(EXIT_USAGE);
+    }
+    let stock_codes = effective_codes(&cli.codes, cli.limit);
 
+    // A scripted caller can tell "nothing to do" from success by the exit status.
     if stock_codes.is_empty() {
-        eprintln!("Usage: auto_selecter1 <stock_code_1> <stock_code_2> ...");
-        eprintln!("Example: auto_selecter1 6758 7203 USDJPY=FX");
-        return Ok(());
+        eprintln!("No codes given.\n");
+        eprintln!("{}", CliArgs::command().render_help());
+        eprintln!("Example: smp 6758 7203 USDJPY=FX");
+        std::process::exit(EXIT_NO_CODES);
     }
 
+    // Codes already present in the output file are skipped when resuming.
+    let completed_codes = match (&cli.output_file, cli.resume) {
+        (Some(path), true) => read_completed_codes(path)?,
+        _ => HashSet::new(),
+    };
+    let mut writer = match &cli.output_file {
+        Some(path) if cli.output != OutputFormat::Parquet => Some(NdjsonWriter::open(path, cli.fsync)?),
+        _ => None,
+    };
+
+    // Flags win over the AUTO_SELECTER_* environment variables, which win over the defaults.
+    let mut config = match ScraperConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_SETUP);
+        }
+    };
+    config.max_concurrent = cli.concurrency;
+    config.code_type = cli.code_type;
+    config.max_retries = cli.retries;
+    config.include_raw_change = cli.include_raw_change;
+    config.include_related = cli.include_related;
+    config.with_margin = cli.with_margin;
+    config.include_ticks = cli.include_ticks;
+    config.max_ticks = cli.max_ticks;
+    config.with_pts = cli.with_pts;
+    config.aggressive = cli.aggressive;
+    config.decimal_precision = cli.normalize_decimals.then_some(DecimalPrecision::DISPLAY);
+    config.debug_elements = cli.debug_elements;
+    if cli.timeout.is_some() {
+        config.timeout = cli.timeout;
+    }
+    if cli.timeout_per_code.is_some() {
+        config.timeout_per_code = cli.timeout_per_code;
+    }
+    if cli.adaptive_timeout {
+        config.adaptive_timeout = Some(Arc::new(AdaptiveTimeout::default()));
+    }
+    if cli.proxy.is_some() {
+        config.proxy = cli.proxy.clone();
+    }
+    if cli.user_agent.is_some() {
+        config.user_agent = cli.user_agent.clone();
+    }
+    if let Some(path) = &cli.cookies {
+        match load_cookie_file(path) {
+            Ok(cookies) => config.cookies = cookies,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(EXIT_SETUP);
+            }
+        }
+    }
+    let fetcher = match build_fetcher(&cli, &config) {
+        Ok(fetcher) => fetcher,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_SETUP);
+        }
+    };
     let mut all_stock_data: Vec<StockData> = Vec::new();
 
-    println!("--- Running Dynamic Scraper ---");
+    // In JSON Lines and table mode stdout carries only records, so progress goes to stderr.
+    let progress = |message: String| match cli.output {
+        _ if cli.quiet => {}
+        OutputFormat::Json | OutputFormat::Parquet => println!("{}", message),
+        OutputFormat::Jsonl | OutputFormat::Table => eprintln!("{}", message),
+    };
+    progress("--- Running Dynamic Scraper ---".to_string());
+    let mut pending: Vec<String> = Vec::new();
     for code in &stock_codes {
-        println!("Scraping code: {}", code);
-        match scrape_dynamically(code).await {
-            Ok(data) => all_stock_data.push(data),
-            Err(e) => eprintln!("  -> Error scraping {}: {}", code, e),
+        if completed_codes.contains(code) {
+            progress(format!("Skipping already scraped code: {}", code));
+        } else {
+            pending.push(code.clone());
         }
     }
+    if cli.with_meta && cli.output == OutputFormat::Jsonl {
+        write_jsonl_line(&mut io::stdout().lock(), &json!({ "meta": OutputMeta::current() }))?;
+    }
+    let policy = FailurePolicy::from_cli(&cli);
+    let mut errors: Vec<ErrorRecord> = Vec::new();
+    let summary = run_batch(&fetcher, &pending, &config, policy, &progress, |result| {
+        match result {
+            Ok(data) => {
+                if let Some(writer) = writer.as_mut() {
+                    writer.write_record(&data)?;
+                }
+                if cli.output == OutputFormat::Jsonl {
+                    write_jsonl_line(&mut io::stdout().lock(), &data)?;
+                }
+                all_stock_data.push(data);
+            }
+            Err(error) if cli.errors_in_output || cli.failures_file.is_some() => {
+                if cli.errors_in_output && cli.output == OutputFormat::Jsonl {
+                    write_jsonl_line(&mut io::stdout().lock(), &error)?;
+                }
+                errors.push(error);
+            }
+            Err(_) => {}
+        }
+        Ok(())
+    })
+    .await?;
+    if !cli.quiet {
+        eprintln!("{}", summary);
+    }
+    if let Some(path) = &cli.failures_file {
+        write_failures(path, &errors)?;
+    }
+
+    if cli.output == OutputFormat::Json {
+        println!("\n--- Scraped Data ---");
+        let scraped_data_json = json_output(&all_stock_data, cli.errors_in_output.then_some(errors.as_slice()), cli.with_meta);
+        println!("{}", serde_json::to_string_pretty(&scraped_data_json)?);
+    }
+    if cli.output == OutputFormat::Table {
+        write_table(&mut io::stdout().lock(), &all_stock_data)?;
+    }
+    #[cfg(feature = "arrow")]
+    if let (OutputFormat::Parquet, Some(path)) = (cli.output, &cli.output_file) {
+        write_parquet(path, &all_stock_data)?;
+    }
 
-    println!("\n--- Scraped Data ---");
-    let scraped_data_json = json!(all_stock_data);
-    println!("{}", serde_json::to_string_pretty(&scraped_data_json)?);
+    let exit_code = policy.exit_code(&summary, pending.len());
+    if exit_code != 0 {
+        eprintln!("Exiting with status {}; failed codes: {}", exit_code, summary.failed_codes.join(", "));
+        std::process::exit(exit_code);
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auto_selecter1::output::SCHEMA_VERSION;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args_limit() {
+        let cli = parse_args(args(&["6758,7203", "--limit", "1", "8729"])).unwrap();
+        assert_eq!(cli.codes, args(&["6758", "7203", "8729"]));
+        assert_eq!(cli.limit, 1);
+
+        assert!(parse_args(args(&["--limit"])).is_err());
+        assert!(parse_args(args(&["--limit=abc"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_output_file_and_resume() {
+        let cli = parse_args(args(&["--output-file", "out.ndjson", "--resume", "--fsync", "USDJPY=FX"])).unwrap();
+        assert_eq!(cli.output_file, Some(PathBuf::from("out.ndjson")));
+        assert!(cli.resume);
+        assert!(cli.fsync);
+        assert_eq!(cli.codes, args(&["USDJPY=FX"]));
+
+        assert!(parse_args(args(&["--resume", "6758"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_output_format() {
+        let cli = parse_args(args(&["--output", "jsonl", "--out", "log.jsonl", "6758"])).unwrap();
+        assert_eq!(cli.output, OutputFormat::Jsonl);
+        assert_eq!(cli.output_file, Some(PathBuf::from("log.jsonl")));
+        assert_eq!(parse_args(args(&["6758"])).unwrap().output, OutputFormat::Json);
+        assert_eq!(parse_args(args(&["--format=table", "6758"])).unwrap().output, OutputFormat::Table);
+        assert!(parse_args(args(&["--output=csv", "6758"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_type_override() {
+        let cli = parse_args(args(&["--type", "fx", "EURJPY"])).unwrap();
+        assert_eq!(cli.code_type, Some(CodeType::Fx));
+        assert_eq!(parse_args(args(&["6758"])).unwrap().code_type, None);
+        assert!(parse_args(args(&["--type=bond", "6758"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_retries() {
+        assert_eq!(parse_args(args(&["--retries", "3", "6758"])).unwrap().retries, 3);
+        assert_eq!(parse_args(args(&["6758"])).unwrap().retries, 0);
+        assert!(parse_args(args(&["--retries=-1", "6758"])).is_err());
+        assert!(parse_args(args(&["-q", "6758"])).unwrap().quiet);
+    }
+
+    #[test]
+    fn test_parse_args_cookies() {
+        let cli = parse_args(args(&["--cookies=cookies.txt", "6758"])).unwrap();
+        assert_eq!(cli.cookies, Some(PathBuf::from("cookies.txt")));
+        assert!(parse_args(args(&["6758", "--cookies"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_timeout_per_code() {
+        let cli = parse_args(args(&["--timeout-per-code", "2.5", "6758"])).unwrap();
+        assert_eq!(cli.timeout_per_code, Some(Duration::from_millis(2500)));
+        assert!(parse_args(args(&["--timeout-per-code=0"])).is_err());
+        assert!(parse_args(args(&["--timeout-per-code=soon"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_clap_flags() {
+        let cli = parse_args(args(&[
+            "--format", "jsonl", "--timeout", "3", "--concurrency", "4", "--codes-file", "codes.txt",
+            "--config", "smp.json", "-q", "--include-raw-change", "--include-related", "--with-margin", "--debug-elements",
+            "--include-ticks", "--max-ticks", "5", "--with-pts", "--aggressive", "--normalize-decimals", "6758",
+        ]))
+        .unwrap();
+        assert_eq!(cli.output, OutputFormat::Jsonl);
+        assert_eq!(cli.timeout, Some(Duration::from_secs(3)));
+        assert_eq!(cli.timeout_per_code, None);
+        assert_eq!(cli.concurrency, 4);
+        assert_eq!(cli.codes_file, Some(PathBuf::from("codes.txt")));
+        assert_eq!(cli.config, Some(PathBuf::from("smp.json")));
+        assert!(cli.quiet);
+        assert!(cli.include_raw_change);
+        assert!(cli.include_related);
+        assert!(cli.with_margin);
+        assert!(cli.debug_elements);
+        assert!(cli.include_ticks);
+        assert_eq!(cli.max_ticks, 5);
+        assert!(cli.with_pts);
+        assert!(cli.aggressive);
+        assert!(cli.normalize_decimals);
+
+        assert_eq!(parse_args(args(&["6758"])).unwrap().concurrency, 1);
+        assert!(!parse_args(args(&["6758"])).unwrap().include_raw_change);
+        assert_eq!(parse_args(args(&["6758"])).unwrap().max_ticks, 10);
+        assert!(parse_args(args(&["--max-ticks", "5", "6758"])).is_err());
+        assert!(parse_args(args(&["--concurrency=0", "6758"])).is_err());
+        assert!(parse_args(args(&["--bogus", "6758"])).is_err());
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level(&parse_args(args(&["6758"])).unwrap()), log::LevelFilter::Info);
+        assert_eq!(log_level(&parse_args(args(&["-q", "--debug-elements", "6758"])).unwrap()), log::LevelFilter::Warn);
+        assert_eq!(log_level(&parse_args(args(&["--debug-elements", "6758"])).unwrap()), log::LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_parse_args_cache_dir() {
+        let cli = parse_args(args(&["--cache-dir", "cache", "--cache-max-age", "600", "6758"])).unwrap();
+        assert_eq!(cli.cache_dir, Some(PathBuf::from("cache")));
+        assert_eq!(cli.cache_max_age, Some(Duration::from_secs(600)));
+        assert!(parse_args(args(&["--cache-max-age", "600", "6758"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_codes_file() {
+        let text = "# watch list\n6758, 7203\n\nUSDJPY=FX  # yen\n";
+        assert_eq!(parse_codes_file(text), args(&["6758", "7203", "USDJPY=FX"]));
+    }
+
+    #[test]
+    fn test_apply_file_config() {
+        let file: FileConfig = serde_json::from_str(
+            r#"{"codes": ["8729,9432"], "format": "jsonl", "timeout": 5, "concurrency": 2, "retries": 1, "type": "stock"}"#,
+        )
+        .unwrap();
+        let mut cli = parse_args(args(&["--retries", "3", "6758"])).unwrap();
+        apply_file_config(&mut cli, file).unwrap();
+        assert_eq!(cli.codes, args(&["6758", "8729", "9432"]));
+        assert_eq!(cli.output, OutputFormat::Jsonl);
+        assert_eq!(cli.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(cli.concurrency, 2);
+        // Given on the command line, so the file's value is ignored.
+        assert_eq!(cli.retries, 3);
+        assert_eq!(cli.code_type, "stock".parse().ok());
+
+        // Flags given with their default values still win over the file.
+        let file: FileConfig = serde_json::from_str(r#"{"format": "jsonl", "concurrency": 4, "retries": 2}"#).unwrap();
+        let mut cli = parse_args(args(&["--format", "json", "--concurrency", "1", "--retries", "0", "6758"])).unwrap();
+        apply_file_config(&mut cli, file).unwrap();
+        assert_eq!((cli.output, cli.concurrency, cli.retries), (OutputFormat::Json, 1, 0));
+
+        let bad: FileConfig = serde_json::from_str(r#"{"format": "csv"}"#).unwrap();
+        assert!(apply_file_config(&mut parse_args(args(&["6758"])).unwrap(), bad).is_err());
+    }
+
+    #[test]
+    fn test_effective_codes_dedup_then_limit() {
+        let codes = args(&["6758", "6758", "7203", "8729"]);
+        assert_eq!(effective_codes(&codes, 2), args(&["6758", "7203"]));
+        assert_eq!(effective_codes(&codes, 0), args(&["6758", "7203", "8729"]));
+        assert_eq!(effective_codes(&codes, 10), args(&["6758", "7203", "8729"]));
+    }
+
+    #[test]
+    fn test_parse_args_failure_policy() {
+        let policy = |list: &[&str]| FailurePolicy::from_cli(&parse_args(args(list)).unwrap());
+        assert_eq!(policy(&["6758"]), FailurePolicy::Lenient);
+        assert_eq!(policy(&["--fail-fast", "6758"]), FailurePolicy::FailFast);
+        assert_eq!(policy(&["--strict", "6758"]), FailurePolicy::Strict);
+        assert!(parse_args(args(&["--fail-fast", "--strict", "6758"])).is_err());
+    }
+
+    /// Serves the 6758 and 7203 fixtures and answers 404 for every other page, recording the requested URLs.
+    #[derive(Default)]
+    struct FixtureFetcher {
+        requested: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Fetcher for FixtureFetcher {
+        async fn get(&self, url: &str) -> Result<Response, ScraperError> {
+            // Like a real request, give the other codes of the batch a turn before it is made, so
+            // a code still waiting here when `--fail-fast` stops the batch is never fetched.
+            tokio::task::yield_now().await;
+            self.requested.lock().unwrap().push(url.to_string());
+            let body = match url {
+                "https://finance.yahoo.co.jp/quote/6758.T" => include_str!("fixtures/stock_6758.html"),
+                "https://finance.yahoo.co.jp/quote/7203.T" => include_str!("fixtures/stock_7203.html"),
+                _ => return Ok(Response { status: 404, ..Default::default() }),
+            };
+            Ok(Response { status: 200, body: body.to_string(), ..Default::default() })
+        }
+    }
+
+    async fn run_mixed_batch(policy: FailurePolicy) -> (BatchSummary, Vec<String>, usize) {
+        let (summary, results, requests) = run_mixed_batch_results(policy).await;
+        let scraped = results.into_iter().filter_map(Result::ok).map(|data| data.code).collect();
+        (summary, scraped, requests)
+    }
+
+    async fn run_mixed_batch_results(policy: FailurePolicy) -> (BatchSummary, Vec<Result<StockData, ErrorRecord>>, usize) {
+        run_batch_of(&["6758", "9999", "7203"], 1, policy).await
+    }
+
+    async fn run_batch_of(codes: &[&str], concurrency: usize, policy: FailurePolicy) -> (BatchSummary, Vec<Result<StockData, ErrorRecord>>, usize) {
+        let fetcher = FixtureFetcher::default();
+        let config = ScraperConfig {
+            max_concurrent: concurrency,
+            ..Default::default()
+        };
+        let mut results = Vec::new();
+        let summary = run_batch(&fetcher, &args(codes), &config, policy, &|_| {}, |result| {
+            results.push(result);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let requests = fetcher.requested.lock().unwrap().len();
+        (summary, results, requests)
+    }
+
+    #[tokio::test]
+    async fn test_retry_failures_round_trip() {
+        let path = env::temp_dir().join(format!("smp_failures_{}.json", std::process::id()));
+        let path_arg = path.to_str().unwrap();
+        let first = parse_args(args(&["--failures-file", path_arg, "6758", "9999", "7203"])).unwrap();
+        let (_, results, _) = run_mixed_batch_results(FailurePolicy::Lenient).await;
+        let errors: Vec<ErrorRecord> = results.into_iter().filter_map(Result::err).collect();
+        write_failures(first.failures_file.as_deref().unwrap(), &errors).unwrap();
+
+        // The second run scrapes only what failed the first time.
+        let retry = parse_args(args(&["--retry-failures", path_arg])).unwrap();
+        let codes = read_failed_codes(retry.retry_failures.as_deref().unwrap()).unwrap();
+        assert_eq!(codes, ["9999"]);
+        let fetcher = FixtureFetcher::default();
+        let summary = run_batch(&fetcher, &codes, &ScraperConfig::default(), FailurePolicy::Lenient, &|_| {}, |_| Ok(()))
+            .await
+            .unwrap();
+        assert_eq!(summary.failed_codes, ["9999"]);
+        assert_eq!(*fetcher.requested.lock().unwrap(), ["https://finance.yahoo.co.jp/quote/9999.T"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_errors_in_json_output() {
+        let (_, results, _) = run_mixed_batch_results(FailurePolicy::Lenient).await;
+        let (records, errors): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+        let records: Vec<StockData> = records.into_iter().map(Result::unwrap).collect();
+        let errors: Vec<ErrorRecord> = errors.into_iter().map(|e| e.unwrap_err()).collect();
+
+        let output = json_output(&records, Some(&errors), false);
+        assert_eq!(output["results"].as_array().unwrap().len(), 2);
+        assert_eq!(output["results"][1]["code"], "7203");
+        assert_eq!(output["errors"][0]["code"], "9999");
+        assert_eq!(output["errors"][0]["error"], "not_found");
+        assert_eq!(
+            output["errors"][0]["message"],
+            "Not found (HTTP 404): https://finance.yahoo.co.jp/quote/9999.T"
+        );
+
+        // Without the flag the output stays a bare array of records.
+        assert_eq!(json_output(&records, None, false).as_array().unwrap().len(), 2);
+
+        let output = json_output(&records, None, true);
+        assert_eq!(output["meta"]["scraper_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(output["meta"]["schema"], SCHEMA_VERSION);
+        assert_eq!(output["results"][1]["code"], "7203");
+        assert!(output.get("errors").is_none());
+        // The records sit under the same key whether or not the errors are included.
+        let output = json_output(&records, Some(&errors), true);
+        assert_eq!(output["results"][1]["code"], "7203");
+        assert_eq!(output["errors"][0]["code"], "9999");
+
+        let mut line = Vec::new();
+        write_jsonl_line(&mut line, &errors[0]).unwrap();
+        assert!(String::from_utf8(line).unwrap().starts_with(r#"{"code":"9999","error":"not_found","#));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_batch_limits_span_the_whole_batch() {
+        let fetcher = FixtureFetcher::default();
+        let config = ScraperConfig {
+            max_concurrent: 1,
+            requests_per_second: Some(2.0),
+            ..Default::default()
+        };
+        let started = tokio::time::Instant::now();
+        let mut handled = 0;
+        let codes = args(&["usdjpy=fx", "6758", "7203", "USDJPY=X"]);
+        let summary = run_batch(&fetcher, &codes, &config, FailurePolicy::Lenient, &|_| {}, |_| {
+            handled += 1;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        // The two spellings of the pair share one fetch, and the three pages are dispatched half a
+        // second apart rather than each starting a rate limiter of its own.
+        assert_eq!(fetcher.requested.lock().unwrap().len(), 3);
+        assert_eq!((handled, summary.total()), (4, 4));
+        assert_eq!(started.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_failure_policies() {
+        let (summary, scraped, requests) = run_mixed_batch(FailurePolicy::Lenient).await;
+        assert_eq!(scraped, args(&["6758", "7203"]));
+        assert_eq!(summary.failed_codes, args(&["9999"]));
+        assert_eq!(requests, 3);
+        assert_eq!(FailurePolicy::Lenient.exit_code(&summary, 3), 0);
+        let mut all_failed = BatchSummary::default();
+        all_failed.record_failure("9999");
+        all_failed.record_failure("9998");
+        assert_eq!(FailurePolicy::Lenient.exit_code(&all_failed, 2), EXIT_ALL_FAILED);
+        assert_eq!(FailurePolicy::Strict.exit_code(&all_failed, 2), EXIT_ALL_FAILED);
+        assert_eq!(FailurePolicy::FailFast.exit_code(&all_failed, 2), EXIT_ALL_FAILED);
+
+        let (summary, scraped, requests) = run_mixed_batch(FailurePolicy::Strict).await;
+        assert_eq!(scraped, args(&["6758", "7203"]));
+        assert_eq!(summary.failed_codes, args(&["9999"]));
+        assert_eq!(requests, 3);
+        assert_eq!(FailurePolicy::Strict.exit_code(&summary, 3), EXIT_SOME_FAILED);
+
+        // 7203 is never fetched once 9999 has failed.
+        let (summary, scraped, requests) = run_mixed_batch(FailurePolicy::FailFast).await;
+        assert_eq!(scraped, args(&["6758"]));
+        assert_eq!(summary.failed_codes, args(&["9999"]));
+        assert_eq!(requests, 2);
+        assert_eq!(FailurePolicy::FailFast.exit_code(&summary, 3), EXIT_SOME_FAILED);
+
+        // Stopping at a failing first code leaves the others untried, so not every code failed.
+        let (summary, _, requests) = run_batch_of(&["9999", "6758"], 1, FailurePolicy::FailFast).await;
+        assert_eq!(summary.total(), 1);
+        assert_eq!(requests, 1);
+        assert_eq!(FailurePolicy::FailFast.exit_code(&summary, 2), EXIT_SOME_FAILED);
+
+        // Codes scraped alongside the failing one are still reported.
+        let (summary, results, _) = run_batch_of(&["9999", "6758", "7203"], 2, FailurePolicy::FailFast).await;
+        assert_eq!(summary.failed_codes, args(&["9999"]));
+        assert_eq!(summary.total(), 2);
+        assert_eq!(results.len(), 2);
+        assert_eq!(FailurePolicy::FailFast.exit_code(&summary, 3), EXIT_SOME_FAILED);
+
+        assert_eq!(FailurePolicy::Strict.exit_code(&BatchSummary::default(), 0), 0);
+    }
+
+    #[test]
+    fn test_build_fetcher_rejects_an_invalid_proxy() {
+        let cli = parse_args(args(&["6758"])).unwrap();
+        let config = ScraperConfig {
+            proxy: Some("not a proxy url".to_string()),
+            ..Default::default()
+        };
+        assert!(build_fetcher(&cli, &config).err().unwrap().contains("Invalid proxy"));
+        assert!(build_fetcher(&cli, &ScraperConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_usage_exit_code() {
+        assert_eq!(usage_exit_code(&parse_args(args(&["--concurrency", "0"])).unwrap_err()), EXIT_USAGE);
+        assert_eq!(usage_exit_code(&parse_args(args(&["--no-such-flag"])).unwrap_err()), EXIT_USAGE);
+        assert_eq!(usage_exit_code(&parse_args(args(&["--help"])).unwrap_err()), 0);
+        assert_eq!(usage_exit_code(&parse_args(args(&["--version"])).unwrap_err()), 0);
+    }
+}
@@ -1,23 +1,633 @@
+use crate::scraper_logic::normalize_minus;
+use crate::value_parser::{format_decimal, ValueParser};
+use crate::{get_code_type, CodeType};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::time::Duration;
 
-#[derive(Debug)]
-pub struct ScraperError(pub String);
+#[derive(Debug, Clone)]
+pub enum ScraperError {
+    /// The server answered with a redirect (to the given location) while redirects are disabled.
+    UnexpectedRedirect(String),
+    /// Redirects for the requested URL (first) ended on a page (second) that isn't its quote page,
+    /// typically a consent wall.
+    RedirectedAway(String, String),
+    /// A CSS selector failed to parse. Selectors are mostly literals, so this points to a bug,
+    /// but it is reported as an error so a batch can carry on with the remaining codes.
+    SelectorParse(String),
+    /// A cookie file could not be read or contains a malformed entry.
+    InvalidCookieFile(String),
+    /// Scraping the given code took longer than the per-code time budget.
+    CodeTimeout(String, Duration),
+    /// The request failed in a way that may pass on another attempt: a timeout, a refused or reset
+    /// connection, or an interrupted response or body.
+    Network(String),
+    /// The request failed in a way that another attempt won't fix: DNS, TLS, an invalid URL, ...
+    Request(String),
+    /// The page (the given URL) answered 404, e.g. for a delisted code.
+    NotFound(String),
+    /// The server answered with an error status other than 404; holds the status and the URL.
+    HttpStatus(u16, String),
+    /// The code can't be turned into a quote URL.
+    InvalidCode(String),
+    /// A setting (e.g. an environment variable) has a value that can't be used.
+    InvalidConfig(String),
+    /// A name search (the given query) returned no stock.
+    NoSearchResult(String),
+    /// A name search matched several stocks; holds the query and the "code name" candidates.
+    AmbiguousName(String, Vec<String>),
+    Other(String),
+}
 
 impl std::fmt::Display for ScraperError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            ScraperError::UnexpectedRedirect(location) => write!(f, "Unexpected redirect to {}", location),
+            ScraperError::RedirectedAway(url, final_url) => {
+                write!(f, "{} was redirected to {}, which is not its quote page (consent wall?)", url, final_url)
+            }
+            ScraperError::SelectorParse(message) => write!(f, "Invalid selector: {}", message),
+            ScraperError::InvalidCookieFile(message) => write!(f, "Invalid cookie file: {}", message),
+            ScraperError::CodeTimeout(code, limit) => {
+                write!(f, "Scraping {} did not finish within {:?}", code, limit)
+            }
+            ScraperError::Network(message) => write!(f, "Network error: {}", message),
+            ScraperError::Request(message) => write!(f, "Request error: {}", message),
+            ScraperError::NotFound(url) => write!(f, "Not found (HTTP 404): {}", url),
+            ScraperError::HttpStatus(status, url) => write!(f, "Unexpected HTTP status {} for {}", status, url),
+            ScraperError::InvalidCode(code) => write!(f, "Invalid code: {}", code),
+            ScraperError::InvalidConfig(message) => write!(f, "Invalid configuration: {}", message),
+            ScraperError::NoSearchResult(query) => write!(f, "No stock found for {:?}", query),
+            ScraperError::AmbiguousName(query, candidates) => {
+                write!(f, "{:?} matches several stocks: {}", query, candidates.join(", "))
+            }
+            ScraperError::Other(message) => write!(f, "{}", message),
+        }
     }
 }
 
 impl Error for ScraperError {}
 
+impl ScraperError {
+    /// The error for a response with a non-success status.
+    pub fn from_status(status: u16, url: &str) -> ScraperError {
+        match status {
+            404 => ScraperError::NotFound(url.to_string()),
+            _ => ScraperError::HttpStatus(status, url.to_string()),
+        }
+    }
+
+    /// A short, stable name for the kind of error (e.g. "timeout", "not_found"), for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ScraperError::UnexpectedRedirect(_) => "unexpected_redirect",
+            ScraperError::RedirectedAway(_, _) => "redirected_away",
+            ScraperError::SelectorParse(_) => "selector_parse",
+            ScraperError::InvalidCookieFile(_) => "invalid_cookie_file",
+            ScraperError::CodeTimeout(_, _) => "timeout",
+            ScraperError::Network(_) => "network",
+            ScraperError::Request(_) => "request",
+            ScraperError::NotFound(_) => "not_found",
+            ScraperError::HttpStatus(_, _) => "http_status",
+            ScraperError::InvalidCode(_) => "invalid_code",
+            ScraperError::InvalidConfig(_) => "invalid_config",
+            ScraperError::NoSearchResult(_) => "no_search_result",
+            ScraperError::AmbiguousName(_, _) => "ambiguous_name",
+            ScraperError::Other(_) => "other",
+        }
+    }
+
+    /// Whether trying the same request again may succeed: network errors, timeouts,
+    /// 5xx responses and 429 (rate limited). A 404, an invalid code or a redirect to a
+    /// consent page will fail the same way again, so those aren't retried.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ScraperError::Network(_) | ScraperError::CodeTimeout(_, _) => true,
+            ScraperError::HttpStatus(status, _) => *status == 429 || (500..600).contains(status),
+            _ => false,
+        }
+    }
+}
+
+/// The market an instrument belongs to, derived from its code.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Market {
+    /// A stock or ETF listed in Tokyo.
+    TokyoStock,
+    /// The Dow Jones Industrial Average.
+    Dji,
+    /// The Nikkei 225.
+    Nikkei,
+    /// A currency pair (`=FX` or `=X`).
+    Fx,
+}
+
+impl Market {
+    /// Classifies a code the same way the scraper does.
+    pub fn from_code(code: &str) -> Market {
+        Market::from_code_type(&get_code_type(code))
+    }
+
+    pub fn from_code_type(code_type: &CodeType) -> Market {
+        match code_type {
+            CodeType::Stock(_) => Market::TokyoStock,
+            CodeType::Dji => Market::Dji,
+            CodeType::Nikkei => Market::Nikkei,
+            CodeType::Fx => Market::Fx,
+        }
+    }
+}
+
+/// Trading state shown next to the price.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradingState {
+    /// Regular trading; no marker is shown.
+    Normal,
+    /// 特別気配: a special (indicative) quote instead of a trade price.
+    SpecialQuote,
+    /// ストップ高: the price hit the daily upper limit.
+    LimitUp,
+    /// ストップ安: the price hit the daily lower limit.
+    LimitDown,
+}
+
+/// Trading session phase, normalized from the status marker in the price board
+/// (e.g. 寄付前, ザラ場, 前引け, 引け後).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionPhase {
+    /// Before the open (寄付前).
+    PreOpen,
+    /// Continuous trading (ザラ場, 前場, 後場).
+    Open,
+    /// Lunch break between the morning and afternoon sessions (前引け, 昼休み).
+    Break,
+    /// After the close (引け後, 大引け).
+    Closed,
+}
+
+impl SessionPhase {
+    /// Normalizes a status marker text; `None` when it isn't a known marker.
+    /// The break is checked first, since 前場引け mentions both 前場 and 引け.
+    pub fn from_status(text: &str) -> Option<Self> {
+        const PHASES: [(SessionPhase, &[&str]); 4] = [
+            (SessionPhase::Break, &["前引け", "前場引け", "昼休み"]),
+            (SessionPhase::PreOpen, &["寄付前", "寄り前", "取引開始前"]),
+            (SessionPhase::Closed, &["引け", "取引終了"]),
+            (SessionPhase::Open, &["ザラ場", "取引中", "前場", "後場"]),
+        ];
+        PHASES
+            .iter()
+            .find(|(_, markers)| markers.iter().any(|marker| text.contains(marker)))
+            .map(|(phase, _)| *phase)
+    }
+}
+
+/// Direction of the move since the previous close, as colored by the page.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Flat,
+}
+
+/// How far a scraped value can be trusted, going by where it came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    /// JSON-LD data embedded for machines that names the code, or a user-supplied selector.
+    High,
+    /// A hardcoded selector, one discovered on an earlier page of the same layout, or a price `<meta>` tag.
+    Medium,
+    /// Found by the heuristic DOM walk on this page.
+    Low,
+    /// The most price-like number anywhere on the page, taken only with `ScraperConfig::aggressive`
+    /// when nothing else found a price. Often wrong; consumers that need a real price should reject it.
+    Guess,
+}
+
+/// A scraped quote.
+///
+/// JSON keys are camelCase to match JS consumers (`changeAbs`, `changePercent`, `updateTime`, ...).
+/// Compatibility: output written before the rename used snake_case keys (`change`, `change_percent`,
+/// `update_time`, ...); those are still accepted when deserializing, e.g. when resuming from an old NDJSON file.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct StockData {
     pub code: String,
     pub name: String,
     pub price: String,
+    #[serde(rename = "changeAbs", alias = "change")]
     pub change: String,
+    /// Percent change in the canonical form "-1.17%" (see `normalize_change_percent`).
+    #[serde(alias = "change_percent")]
     pub change_percent: String,
+    /// The change as shown before being split into `change` and `change_percent`, e.g. "+120(+1.52%)".
+    /// Only filled in with `ScraperConfig::include_raw_change`.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "change_raw")]
+    pub change_raw: Option<String>,
+    #[serde(alias = "update_time")]
     pub update_time: String,
+    /// Unit printed inside the price, e.g. "円" for "149.52円" or "pt" for "1,234 pt"; `price` keeps it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Where `price` came from (see `Confidence`); `None` when no price was found.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "price_confidence")]
+    pub price_confidence: Option<Confidence>,
+    /// Special quote / limit state. When it isn't `Normal`, `price` is the indicative quote.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "trading_state")]
+    pub trading_state: Option<TradingState>,
+    /// Session status marker as shown near the price board, e.g. "ザラ場" or "引け後".
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "session_status")]
+    pub session_status: Option<String>,
+    /// `session_status` normalized, when it is a known marker.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "session_phase")]
+    pub session_phase: Option<SessionPhase>,
+    /// Direction taken from the change label's modifier class rather than the sign of `change`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// Market the code was classified as.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub market: Option<Market>,
+    /// True when the page was fetched a second time because the first one had a name but no price.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refetched: bool,
+    /// Time spent fetching and parsing this code, in milliseconds.
+    #[serde(default, alias = "scrape_ms")]
+    pub scrape_ms: u64,
+    /// English (romanized) company name, when the page shows one.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "name_en")]
+    pub name_en: Option<String>,
+    /// 基準価額 (net asset value), only present for ETFs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nav: Option<String>,
+    /// 始値 (today's opening price) as shown in the reference panel; stocks only. A time the panel adds,
+    /// as in "3,480(09:00)", is kept here and ignored when parsing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open: Option<String>,
+    /// 前日終値 (previous close) as shown in the reference panel; stocks only.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "previous_close")]
+    pub previous_close: Option<String>,
+    /// 信用買残 (margin buy balance, the long side) as shown, e.g. "2,345,600株"; stocks only.
+    /// Taken from the quote page, or from the 信用残 page with `ScraperConfig::with_margin`.
+    /// This is the "margin long" balance as well: there is no separate `margin_long` field, and
+    /// `None` rather than an empty string stands for a missing balance. `marginLong` and
+    /// `margin_long` are accepted as keys when deserializing.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "margin_buy", alias = "marginLong", alias = "margin_long")]
+    pub margin_buy: Option<String>,
+    /// 信用売残 (margin sell balance, the short side) as shown; stocks only. Also the "margin short"
+    /// balance (`marginShort` / `margin_short` when deserializing), like `margin_buy`.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "margin_sell", alias = "marginShort", alias = "margin_short")]
+    pub margin_sell: Option<String>,
+    /// High of the range the chart shows, when the chart annotates it. Unlike the 52-week high this
+    /// follows the chart's visible period.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "chart_range_high")]
+    pub chart_range_high: Option<String>,
+    /// Low of the range the chart shows, when the chart annotates it.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "chart_range_low")]
+    pub chart_range_low: Option<String>,
+    /// Price on the PTS (proprietary trading system) after hours, with `ScraperConfig::with_pts`; stocks
+    /// only. Empty when the page shows no PTS quote, as is usual while the market is open.
+    #[serde(default, skip_serializing_if = "String::is_empty", alias = "pts_price")]
+    pub pts_price: String,
+    /// Change of the PTS price from the day's close, e.g. "+5.5"; empty along with `pts_price`.
+    #[serde(default, skip_serializing_if = "String::is_empty", alias = "pts_change")]
+    pub pts_change: String,
+    /// Codes of the stocks listed under 関連銘柄, with `ScraperConfig::include_related`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", alias = "related_codes")]
+    pub related_codes: Vec<String>,
+    /// Latest prints of the 歩み値 table as (time, price), newest first, with `ScraperConfig::include_ticks`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", alias = "recent_ticks")]
+    pub recent_ticks: Vec<(String, String)>,
+    /// Markup of the element each field was read from, by field name, with `ScraperConfig::debug_elements`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty", alias = "debug_elements")]
+    pub debug_elements: BTreeMap<String, String>,
+    /// How many elements the selector behind each entry of `debug_elements` matches on the page.
+    /// More than one means the selector is ambiguous and only its first match was read.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty", alias = "debug_match_counts")]
+    pub debug_match_counts: BTreeMap<String, usize>,
+    /// Company logo or chart thumbnail shown in the price board, resolved against the page URL.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "image_url")]
+    pub image_url: Option<String>,
+    /// The page's `<title>`, kept to check that the fetch landed on the requested quote page.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "page_title")]
+    pub page_title: Option<String>,
+    /// Name of the index an ETF tracks (連動対象), only present for ETFs.
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "tracking_index")]
+    pub tracking_index: Option<String>,
+}
+
+impl StockData {
+    /// Names of the quote fields that came back empty. FX pages show no percent change,
+    /// so `change_percent` isn't expected for FX codes.
+    pub fn missing_fields(&self) -> Vec<&'static str> {
+        let is_fx = self.market == Some(Market::Fx);
+        [
+            ("code", &self.code),
+            ("name", &self.name),
+            ("price", &self.price),
+            ("change", &self.change),
+            ("change_percent", &self.change_percent),
+            ("update_time", &self.update_time),
+        ]
+        .into_iter()
+        .filter(|(field, value)| value.is_empty() && !(is_fx && *field == "change_percent"))
+        .map(|(field, _)| field)
+        .collect()
+    }
+
+    /// True when every expected quote field was found.
+    pub fn is_complete(&self) -> bool {
+        self.missing_fields().is_empty()
+    }
+}
+
+/// One trading day from the history page (see `history::scrape_history`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DailyBar {
+    /// The trading day, e.g. "2024-12-27".
+    pub date: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Shares traded; `None` for indices, which show no volume, and for days without trades.
+    pub volume: Option<u64>,
+}
+
+/// Numeric companion of a `StockData`, produced by `ValueParser`.
+/// Each field is `None` when the raw string is missing or doesn't match the expected format.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedStockData {
+    pub price: Option<f64>,
+    pub change: Option<f64>,
+    /// Percent change as a number, e.g. -1.17 for "(-1.17%)", signed from `change` when the page
+    /// shows the percentage without one (the same value as `percent`).
+    pub change_percent: Option<f64>,
+    /// `change_percent` as a `Percent`.
+    pub percent: Option<Percent>,
+    pub open: Option<f64>,
+    pub previous_close: Option<f64>,
+    /// Gap at the open, `open - previous_close`; `None` unless both were parsed.
+    pub gap: Option<f64>,
+    /// `gap` as a percentage of `previous_close`, e.g. -0.49 for 3,480 against 3,497.
+    pub gap_percent: Option<f64>,
+    /// Margin buy balance in shares, with units such as 万 expanded.
+    pub margin_buy: Option<f64>,
+    /// Margin sell balance in shares, with units such as 万 expanded.
+    pub margin_sell: Option<f64>,
+    /// `update_time` split into its date and time-of-day.
+    pub update_time: UpdateTimeParts,
+}
+
+/// A percent change, always signed: -1.23 for "(-1.23%)", "-1.23%" or "−1.23", and for an unsigned
+/// "(1.23%)" next to a falling change. Serialized as the bare number; displayed as "-1.23%".
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(transparent)]
+pub struct Percent(pub f64);
+
+impl Percent {
+    /// Parses a percent change in any of the forms the pages use (see `normalize_change_percent`).
+    /// An unsigned value takes its sign from `change`, the absolute change shown next to it, when
+    /// that is negative. `None` when `raw` isn't a number, e.g. "---".
+    pub fn parse(raw: &str, change: Option<f64>) -> Option<Percent> {
+        let normalized = normalize_change_percent(raw);
+        let value = ValueParser::parse_percent(&normalized)?;
+        let unsigned = !normalized.starts_with(['+', '-']);
+        Some(Percent(if unsigned && change.is_some_and(|change| change < 0.0) { -value } else { value }))
+    }
+}
+
+impl std::fmt::Display for Percent {
+    /// Two decimals with the sign, e.g. "-1.23%" or "+0.46%"; zero is "0.00%".
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}%", format_decimal(self.0, 2, false, true))
+    }
+}
+
+/// The pieces of a raw `update_time` such as "15:00", "12/27 15:00" or "12/27", as shown on the page.
+/// A lighter alternative to full date-time parsing for consumers that only display them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct UpdateTimeParts {
+    /// Month and day, e.g. "12/27".
+    pub date: Option<String>,
+    /// Time of day, e.g. "15:00".
+    pub time: Option<String>,
+}
+
+/// Splits a combined change string such as `"+120(+1.52%)"` into the absolute
+/// change and the percent change (without parentheses).
+/// The percent part is empty when the string has no parenthesised section.
+pub fn parse_change_string(combined: &str) -> (String, String) {
+    if let Some(paren_index) = combined.find('(') {
+        let abs = combined[..paren_index].trim().to_string();
+        let pct_part = &combined[paren_index + 1..];
+        let pct = if let Some(end_paren_index) = pct_part.find(')') {
+            pct_part[..end_paren_index].trim().to_string()
+        } else {
+            "".to_string()
+        };
+        (abs, pct)
+    } else {
+        (combined.trim().to_string(), "".to_string())
+    }
+}
+
+/// Brings a percent change into the canonical form "-1.23%": parentheses and spaces are dropped,
+/// the minus sign is ASCII and the value ends in exactly one '%', which is added when missing.
+/// The sign is kept as shown, so an unsigned value stays unsigned. Empty input stays empty, and
+/// text that isn't a number (e.g. "---") is returned trimmed but otherwise unchanged.
+pub fn normalize_change_percent(raw: &str) -> String {
+    let trimmed = normalize_minus(raw.trim());
+    let inner = trimmed
+        .strip_prefix(['(', '（'])
+        .and_then(|s| s.strip_suffix([')', '）']))
+        .unwrap_or(&trimmed);
+    let number: String = inner
+        .trim()
+        .trim_end_matches(['%', '％'])
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let unsigned = number.strip_prefix(['+', '-']).unwrap_or(&number);
+    if unsigned.replace(',', "").parse::<f64>().is_ok() && unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("{}%", number)
+    } else {
+        trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_change_percent() {
+        // Dynamic finder: the secondary StyledNumber keeps its parentheses.
+        assert_eq!(normalize_change_percent("(-1.23%)"), "-1.23%");
+        assert_eq!(normalize_change_percent("(+0.79%)"), "+0.79%");
+        // Combined element split by `parse_change_string`, and a Unicode minus.
+        assert_eq!(normalize_change_percent("−1.23%"), "-1.23%");
+        assert_eq!(normalize_change_percent("（－1.23％）"), "-1.23%");
+        // Static scraper: a bare number without '%'.
+        assert_eq!(normalize_change_percent("-1.23"), "-1.23%");
+        assert_eq!(normalize_change_percent("0.00"), "0.00%");
+        // Stray spaces and doubled signs.
+        assert_eq!(normalize_change_percent(" ( +0.46 % ) "), "+0.46%");
+        assert_eq!(normalize_change_percent("+0.46%%"), "+0.46%");
+        // Nothing to normalize.
+        assert_eq!(normalize_change_percent(""), "");
+        assert_eq!(normalize_change_percent("  "), "");
+        assert_eq!(normalize_change_percent("---"), "---");
+        assert_eq!(normalize_change_percent("+-1.2"), "+-1.2");
+    }
+
+    #[test]
+    fn test_percent_parse_and_display() {
+        let parse = |raw: &str, change: Option<f64>| Percent::parse(raw, change);
+        assert_eq!(parse("(-1.23%)", None), Some(Percent(-1.23)));
+        assert_eq!(parse("-1.23%", None), Some(Percent(-1.23)));
+        assert_eq!(parse("−1.23", None), Some(Percent(-1.23)));
+        assert_eq!(parse("（－1.23％）", None), Some(Percent(-1.23)));
+        assert_eq!(parse("(+0.46%)", None), Some(Percent(0.46)));
+        assert_eq!(parse("+0.46%", Some(-3.0)), Some(Percent(0.46)));
+        // An unsigned percentage takes the sign of the absolute change next to it.
+        assert_eq!(parse("(1.23%)", Some(-41.0)), Some(Percent(-1.23)));
+        assert_eq!(parse("1.23%", Some(41.0)), Some(Percent(1.23)));
+        assert_eq!(parse("1.23%", None), Some(Percent(1.23)));
+        assert_eq!(parse("0.00%", Some(0.0)), Some(Percent(0.0)));
+        assert_eq!(parse("---", Some(-41.0)), None);
+        assert_eq!(parse("", None), None);
+
+        assert_eq!(Percent(-1.23).to_string(), "-1.23%");
+        assert_eq!(Percent(0.46).to_string(), "+0.46%");
+        assert_eq!(Percent(1.5).to_string(), "+1.50%");
+        assert_eq!(Percent(0.0).to_string(), "0.00%");
+        assert_eq!(serde_json::to_value(Percent(-1.23)).unwrap(), serde_json::json!(-1.23));
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let mut data = StockData {
+            code: "6758".to_string(),
+            name: "ソニーグループ(株)".to_string(),
+            price: "3,456".to_string(),
+            change: "-41".to_string(),
+            change_percent: "(-1.17%)".to_string(),
+            update_time: "15:00".to_string(),
+            ..Default::default()
+        };
+        assert!(data.is_complete());
+
+        data.update_time.clear();
+        assert_eq!(data.missing_fields(), vec!["update_time"]);
+
+        let fx = StockData {
+            code: "USDJPY=FX".to_string(),
+            name: "米ドル/円".to_string(),
+            price: "149.852".to_string(),
+            change: "+0.213".to_string(),
+            update_time: "10:15".to_string(),
+            market: Some(Market::Fx),
+            ..Default::default()
+        };
+        assert!(fx.is_complete());
+    }
+
+    #[test]
+    fn test_session_phase_from_status() {
+        assert_eq!(SessionPhase::from_status("寄付前"), Some(SessionPhase::PreOpen));
+        assert_eq!(SessionPhase::from_status("ザラ場"), Some(SessionPhase::Open));
+        assert_eq!(SessionPhase::from_status("前場引け"), Some(SessionPhase::Break));
+        assert_eq!(SessionPhase::from_status("引け後"), Some(SessionPhase::Closed));
+        assert_eq!(SessionPhase::from_status("リアルタイム株価"), None);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let url = "https://finance.yahoo.co.jp/quote/6758.T";
+        assert!(ScraperError::Network("connection reset".to_string()).is_retryable());
+        assert!(ScraperError::from_status(503, url).is_retryable());
+        assert!(ScraperError::from_status(429, url).is_retryable());
+        assert!(ScraperError::CodeTimeout("6758".to_string(), Duration::from_secs(5)).is_retryable());
+
+        assert!(matches!(ScraperError::from_status(404, url), ScraperError::NotFound(_)));
+        assert!(!ScraperError::from_status(404, url).is_retryable());
+        assert!(!ScraperError::from_status(403, url).is_retryable());
+        assert!(!ScraperError::InvalidCode("ABCDE".to_string()).is_retryable());
+        assert!(!ScraperError::Request("dns error".to_string()).is_retryable());
+        assert!(!ScraperError::UnexpectedRedirect("https://consent.yahoo.co.jp/".to_string()).is_retryable());
+    }
+
+    #[test]
+    fn test_stock_data_json_keys_round_trip() {
+        let data = StockData {
+            code: "6758".to_string(),
+            name: "ソニーグループ(株)".to_string(),
+            price: "3,456".to_string(),
+            change: "-41".to_string(),
+            change_percent: "(-1.17%)".to_string(),
+            update_time: "15:00".to_string(),
+            scrape_ms: 12,
+            tracking_index: Some("TOPIX".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["changeAbs"], "-41");
+        assert_eq!(json["changePercent"], "(-1.17%)");
+        assert_eq!(json["updateTime"], "15:00");
+        assert_eq!(json["scrapeMs"], 12);
+        assert_eq!(json["trackingIndex"], "TOPIX");
+        assert!(json.get("change").is_none());
+        assert!(json.get("change_percent").is_none());
+
+        let parsed: StockData = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.change, "-41");
+        assert_eq!(parsed.change_percent, "(-1.17%)");
+        assert_eq!(parsed.update_time, "15:00");
+        assert_eq!(parsed.tracking_index.as_deref(), Some("TOPIX"));
+    }
+
+    #[test]
+    fn test_stock_data_accepts_legacy_snake_case_keys() {
+        let legacy = r#"{"code":"6758","name":"ソニーグループ(株)","price":"3,456","change":"-41","change_percent":"(-1.17%)","update_time":"15:00","name_en":"SONY GROUP"}"#;
+        let parsed: StockData = serde_json::from_str(legacy).unwrap();
+        assert_eq!(parsed.change, "-41");
+        assert_eq!(parsed.change_percent, "(-1.17%)");
+        assert_eq!(parsed.update_time, "15:00");
+        assert_eq!(parsed.name_en.as_deref(), Some("SONY GROUP"));
+
+        let long_short: StockData = serde_json::from_str(
+            r#"{"code":"6758","name":"","price":"","changeAbs":"","changePercent":"","updateTime":"","marginLong":"2,345,600株","margin_short":"512,300株"}"#,
+        )
+        .unwrap();
+        assert_eq!(long_short.margin_buy.as_deref(), Some("2,345,600株"));
+        assert_eq!(long_short.margin_sell.as_deref(), Some("512,300株"));
+    }
+
+    #[test]
+    fn test_market_from_code() {
+        assert_eq!(Market::from_code("6758"), Market::TokyoStock);
+        assert_eq!(Market::from_code("130A"), Market::TokyoStock);
+        assert_eq!(Market::from_code("USDJPY=FX"), Market::Fx);
+        assert_eq!(Market::from_code("USDJPY=X"), Market::Fx);
+        assert_eq!(Market::from_code("^DJI"), Market::Dji);
+        assert_eq!(Market::from_code("%5EDJI"), Market::Dji);
+        assert_eq!(Market::from_code("998407.O"), Market::Nikkei);
+        assert_eq!(Market::from_code("%5EN225"), Market::Nikkei);
+
+        let data = StockData {
+            market: Some(Market::TokyoStock),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(&data).unwrap()["market"], "TokyoStock");
+    }
+
+    #[test]
+    fn test_parse_change_string() {
+        assert_eq!(parse_change_string("+120(+1.52%)"), ("+120".to_string(), "+1.52%".to_string()));
+        assert_eq!(parse_change_string(" -41 ( -1.17% ) "), ("-41".to_string(), "-1.17%".to_string()));
+        assert_eq!(parse_change_string("+0.12"), ("+0.12".to_string(), "".to_string()));
+        assert_eq!(parse_change_string("+5(+0.1%"), ("+5".to_string(), "".to_string()));
+    }
 }
@@ -0,0 +1,561 @@
+use crate::models::{ScraperError, StockData};
+#[cfg(feature = "arrow")]
+use crate::{models::UpdateTimeParts, value_parser::ValueParser};
+#[cfg(feature = "arrow")]
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+#[cfg(feature = "arrow")]
+use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
+#[cfg(feature = "arrow")]
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveTime, TimeDelta, Utc};
+#[cfg(feature = "arrow")]
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+#[cfg(feature = "arrow")]
+use std::sync::Arc;
+use unicode_width::UnicodeWidthStr;
+
+/// How the CLI prints the scraped records on stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    /// A single pretty-printed JSON array once every code is done.
+    #[default]
+    Json,
+    /// JSON Lines: one compact object per line, printed as soon as each code is scraped.
+    Jsonl,
+    /// An aligned table for reading in a terminal, printed once every code is done.
+    Table,
+    /// A Parquet file at `--output-file` with typed columns (see `write_parquet`), written once every
+    /// code is done. Only available with the `arrow` feature.
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            "table" => Ok(OutputFormat::Table),
+            "parquet" if cfg!(feature = "arrow") => Ok(OutputFormat::Parquet),
+            "parquet" => Err("Parquet output needs the `arrow` feature (cargo build --features arrow)".to_string()),
+            _ => Err(format!("Unknown output format: {} (expected json, jsonl, table or parquet)", s)),
+        }
+    }
+}
+
+/// Counts of how a batch went, printed as a one-line verdict after the run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BatchSummary {
+    /// Scraped with every expected field (see `StockData::is_complete`).
+    pub complete: usize,
+    /// Scraped, but with some fields missing.
+    pub partial: usize,
+    pub failed: usize,
+    /// The codes that failed, in the order they were reported.
+    pub failed_codes: Vec<String>,
+}
+
+impl BatchSummary {
+    pub fn record_success(&mut self, data: &StockData) {
+        if data.is_complete() {
+            self.complete += 1;
+        } else {
+            self.partial += 1;
+        }
+    }
+
+    pub fn record_failure(&mut self, code: &str) {
+        self.failed += 1;
+        self.failed_codes.push(code.to_string());
+    }
+
+    pub fn total(&self) -> usize {
+        self.complete + self.partial + self.failed
+    }
+}
+
+impl std::fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Scraped {} codes: {} complete, {} partial, {} failed",
+            self.total(),
+            self.complete,
+            self.partial,
+            self.failed
+        )
+    }
+}
+
+/// A failed code in the JSON output (`--errors-in-output`), e.g.
+/// `{"code":"6758","error":"timeout","message":"Scraping 6758 did not finish within 5s"}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorRecord {
+    pub code: String,
+    /// `ScraperError::kind`.
+    pub error: &'static str,
+    /// The error as printed on stderr.
+    pub message: String,
+}
+
+impl ErrorRecord {
+    pub fn new(code: &str, error: &ScraperError) -> Self {
+        Self {
+            code: code.to_string(),
+            error: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Version of the record format (`StockData`'s JSON keys and their meaning). Bump it when a
+/// key is renamed or changes meaning, so stored output can be migrated by version.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Which build wrote the output and which record format it follows (`--with-meta`), e.g.
+/// `{"scraper_version":"0.1.0","schema":2}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutputMeta {
+    pub scraper_version: &'static str,
+    pub schema: u32,
+}
+
+impl OutputMeta {
+    pub fn current() -> Self {
+        Self {
+            scraper_version: env!("CARGO_PKG_VERSION"),
+            schema: SCHEMA_VERSION,
+        }
+    }
+}
+
+/// Writes one record as a compact JSON line and flushes, so readers never see half a record.
+pub fn write_jsonl_line<W: Write, T: Serialize>(writer: &mut W, data: &T) -> io::Result<()> {
+    let line = serde_json::to_string(data)?;
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Writes the records as a table with the columns code, name, price, change, % and time.
+/// Widths are measured in terminal columns, so full-width (e.g. Japanese) text lines up;
+/// the numeric columns are right-aligned.
+pub fn write_table<W: Write>(writer: &mut W, records: &[StockData]) -> io::Result<()> {
+    const HEADERS: [&str; 6] = ["Code", "Name", "Price", "Change", "%", "Time"];
+    const RIGHT_ALIGNED: [bool; 6] = [false, false, true, true, true, false];
+
+    let rows: Vec<[&str; 6]> = records
+        .iter()
+        .map(|data| {
+            [
+                data.code.as_str(),
+                data.name.as_str(),
+                data.price.as_str(),
+                data.change.as_str(),
+                data.change_percent.as_str(),
+                data.update_time.as_str(),
+            ]
+        })
+        .collect();
+    let mut widths = HEADERS.map(UnicodeWidthStr::width);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+
+    for row in std::iter::once(&HEADERS).chain(&rows) {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            let padding = " ".repeat(widths[i] - cell.width());
+            if i > 0 {
+                line.push_str("  ");
+            }
+            if RIGHT_ALIGNED[i] {
+                line.push_str(&padding);
+                line.push_str(cell);
+            } else {
+                line.push_str(cell);
+                line.push_str(&padding);
+            }
+        }
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+    writer.flush()
+}
+
+/// Appends records to a file as NDJSON (one JSON object per line).
+/// Every line is flushed as soon as it is written, and optionally fsync'd,
+/// so an interrupted run leaves only complete records behind.
+pub struct NdjsonWriter {
+    file: File,
+    fsync: bool,
+}
+
+impl NdjsonWriter {
+    /// Opens `path` for appending, creating it if needed.
+    /// If a previous run died mid-line, the fragment is terminated so new records start on a fresh line.
+    pub fn open(path: &Path, fsync: bool) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        if len > 0 {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(Self { file, fsync })
+    }
+
+    pub fn write_record(&mut self, data: &StockData) -> io::Result<()> {
+        write_jsonl_line(&mut self.file, data)?;
+        if self.fsync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the failed codes of a run as a JSON array of `ErrorRecord`s (`--failures-file`),
+/// replacing the file; a run without failures leaves an empty array.
+pub fn write_failures(path: &Path, errors: &[ErrorRecord]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    serde_json::to_writer_pretty(&mut file, errors)?;
+    file.write_all(b"\n")?;
+    file.sync_data()
+}
+
+/// Reads the codes of a failures file written by `write_failures`, in the order they failed.
+pub fn read_failed_codes(path: &Path) -> io::Result<Vec<String>> {
+    #[derive(Deserialize)]
+    struct FailedCode {
+        code: String,
+    }
+    let failures: Vec<FailedCode> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(failures.into_iter().map(|failure| failure.code).collect())
+}
+
+/// Reads an NDJSON output file and returns the codes of every complete record.
+/// Lines that don't parse (e.g. a partially written last line) are ignored,
+/// and a missing file yields an empty set.
+pub fn read_completed_codes(path: &Path) -> io::Result<HashSet<String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e),
+    };
+    let mut codes = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Ok(data) = serde_json::from_str::<StockData>(&line) {
+            codes.insert(data.code);
+        }
+    }
+    Ok(codes)
+}
+
+/// The offset of the times the quote pages show (日本時間).
+#[cfg(feature = "arrow")]
+const JST_OFFSET_SECS: i32 = 9 * 3600;
+
+/// Resolves the parts of an `update_time`, which carry no year and often no date, to the latest
+/// matching moment up to `now`: "12/27" read on January 5th is last year's, and a bare "23:55" read
+/// just after midnight is yesterday's. A date without a time is taken as midnight.
+#[cfg(feature = "arrow")]
+fn update_timestamp(parts: &UpdateTimeParts, now: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+    let time = match &parts.time {
+        Some(time) => NaiveTime::parse_from_str(time, "%H:%M").ok()?,
+        None => NaiveTime::MIN,
+    };
+    let today = now.date_naive();
+    let date = match &parts.date {
+        Some(date) => {
+            let (month, day) = date.split_once('/')?;
+            let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+            [today.year(), today.year() - 1]
+                .into_iter()
+                .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+                .find(|date| *date <= today)?
+        }
+        // Allow for a page clock slightly ahead of ours before taking the time as yesterday's.
+        None if parts.time.is_some() && today.and_time(time) > now.naive_local() + TimeDelta::hours(1) => today.pred_opt()?,
+        None if parts.time.is_some() => today,
+        None => return None,
+    };
+    date.and_time(time).and_local_timezone(*now.offset()).single()
+}
+
+/// Builds the columns written by `write_parquet`, resolving update times against `now`.
+#[cfg(feature = "arrow")]
+fn parquet_batch(records: &[StockData], now: DateTime<FixedOffset>) -> Result<RecordBatch, ArrowError> {
+    let timezone = now.offset().to_string();
+    let schema = Schema::new(vec![
+        Field::new("code", DataType::Utf8, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, true),
+        Field::new("change", DataType::Float64, true),
+        Field::new("change_percent", DataType::Float64, true),
+        Field::new("update_time", DataType::Timestamp(TimeUnit::Millisecond, Some(timezone.as_str().into())), true),
+    ]);
+    let parsed: Vec<_> = records
+        .iter()
+        .map(|data| ValueParser::for_code(&data.code).parse_stock_data(data))
+        .collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(records.iter().map(|data| data.code.as_str()))),
+        Arc::new(StringArray::from_iter_values(records.iter().map(|data| data.name.as_str()))),
+        Arc::new(Float64Array::from_iter(parsed.iter().map(|parsed| parsed.price))),
+        Arc::new(Float64Array::from_iter(parsed.iter().map(|parsed| parsed.change))),
+        Arc::new(Float64Array::from_iter(parsed.iter().map(|parsed| parsed.change_percent))),
+        Arc::new(
+            TimestampMillisecondArray::from_iter(
+                parsed
+                    .iter()
+                    .map(|parsed| update_timestamp(&parsed.update_time, now).map(|time| time.timestamp_millis())),
+            )
+            .with_timezone(timezone),
+        ),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns)
+}
+
+/// Writes the records to `path` as a Parquet file (`--format parquet`), replacing it. The columns
+/// are `code` and `name` as strings, `price`, `change` and `change_percent` as the floats
+/// `ValueParser` reads from them (null where they don't parse), and `update_time` as a timestamp in
+/// Japan time (null where the page showed none).
+#[cfg(feature = "arrow")]
+pub fn write_parquet(path: &Path, records: &[StockData]) -> io::Result<()> {
+    let jst = FixedOffset::east_opt(JST_OFFSET_SECS).expect("valid offset");
+    let batch = parquet_batch(records, Utc::now().with_timezone(&jst)).map_err(io::Error::other)?;
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), None).map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "arrow")]
+    use chrono::{TimeZone, Timelike};
+    use std::fs;
+
+    #[test]
+    fn test_batch_summary() {
+        let complete = StockData {
+            code: "6758".to_string(),
+            name: "ソニーグループ(株)".to_string(),
+            price: "3,456".to_string(),
+            change: "-41".to_string(),
+            change_percent: "(-1.17%)".to_string(),
+            update_time: "15:00".to_string(),
+            ..Default::default()
+        };
+        let partial = StockData {
+            price: String::new(),
+            ..complete.clone()
+        };
+
+        let mut summary = BatchSummary::default();
+        summary.record_success(&complete);
+        summary.record_success(&complete);
+        summary.record_success(&partial);
+        summary.record_failure("9999");
+        assert_eq!(summary.to_string(), "Scraped 4 codes: 2 complete, 1 partial, 1 failed");
+        assert_eq!(summary.failed_codes, ["9999"]);
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("auto_selecter1_{}_{}.ndjson", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_resume_from_partially_written_file() {
+        let path = temp_path("resume");
+        fs::write(
+            &path,
+            "{\"code\":\"6758\",\"name\":\"ソニーグループ(株)\",\"price\":\"3,456\",\"change\":\"-41\",\"change_percent\":\"(-1.17%)\",\"update_time\":\"15:00\"}\n{\"code\":\"7203\",\"na",
+        )
+        .unwrap();
+
+        let completed = read_completed_codes(&path).unwrap();
+        assert_eq!(completed, HashSet::from(["6758".to_string()]));
+
+        let mut writer = NdjsonWriter::open(&path, true).unwrap();
+        writer
+            .write_record(&StockData {
+                code: "7203".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let completed = read_completed_codes(&path).unwrap();
+        assert_eq!(completed, HashSet::from(["6758".to_string(), "7203".to_string()]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_jsonl_line_one_record_per_line() {
+        let mut buffer = Vec::new();
+        for code in ["6758", "7203"] {
+            let data = StockData {
+                code: code.to_string(),
+                name: "名前\n改行入り".to_string(),
+                ..Default::default()
+            };
+            write_jsonl_line(&mut buffer, &data).unwrap();
+        }
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let first: StockData = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first.code, "6758");
+        assert_eq!(first.name, "名前\n改行入り");
+        assert!(text.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_output_format_from_str() {
+        assert_eq!("jsonl".parse::<OutputFormat>(), Ok(OutputFormat::Jsonl));
+        assert_eq!("JSON".parse::<OutputFormat>(), Ok(OutputFormat::Json));
+        assert_eq!("table".parse::<OutputFormat>(), Ok(OutputFormat::Table));
+        assert!("csv".parse::<OutputFormat>().is_err());
+        assert_eq!("parquet".parse::<OutputFormat>().is_ok(), cfg!(feature = "arrow"));
+    }
+
+    #[test]
+    fn test_write_table_aligns_full_width_text() {
+        let records = [
+            StockData {
+                code: "6758".to_string(),
+                name: "ソニーグループ(株)".to_string(),
+                price: "3,456".to_string(),
+                change: "-41".to_string(),
+                change_percent: "(-1.17%)".to_string(),
+                update_time: "15:00".to_string(),
+                ..Default::default()
+            },
+            StockData {
+                code: "USDJPY=FX".to_string(),
+                name: "米ドル/円".to_string(),
+                price: "151.23".to_string(),
+                change: "+0.12".to_string(),
+                update_time: "10:42".to_string(),
+                ..Default::default()
+            },
+        ];
+        let mut buffer = Vec::new();
+        write_table(&mut buffer, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "Code       Name                 Price  Change         %  Time\n\
+             6758       ソニーグループ(株)   3,456     -41  (-1.17%)  15:00\n\
+             USDJPY=FX  米ドル/円           151.23   +0.12            10:42\n"
+        );
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_update_timestamp() {
+        let jst = FixedOffset::east_opt(JST_OFFSET_SECS).unwrap();
+        let now = jst.with_ymd_and_hms(2025, 1, 5, 0, 10, 0).unwrap();
+        let parts = |date: Option<&str>, time: Option<&str>| UpdateTimeParts {
+            date: date.map(str::to_string),
+            time: time.map(str::to_string),
+        };
+        let at = |y, m, d, h, min| Some(jst.with_ymd_and_hms(y, m, d, h, min, 0).unwrap());
+
+        assert_eq!(update_timestamp(&parts(Some("1/5"), Some("0:05")), now), at(2025, 1, 5, 0, 5));
+        assert_eq!(update_timestamp(&parts(Some("12/27"), Some("15:00")), now), at(2024, 12, 27, 15, 0));
+        assert_eq!(update_timestamp(&parts(Some("12/27"), None), now), at(2024, 12, 27, 0, 0));
+        assert_eq!(update_timestamp(&parts(None, Some("0:30")), now), at(2025, 1, 5, 0, 30));
+        assert_eq!(update_timestamp(&parts(None, Some("23:55")), now), at(2025, 1, 4, 23, 55));
+        assert_eq!(update_timestamp(&parts(None, None), now), None);
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_parquet_round_trip() {
+        use arrow_array::cast::AsArray;
+        use arrow_array::Array;
+        use arrow_array::types::{Float64Type, TimestampMillisecondType};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let records = [
+            StockData {
+                code: "6758".to_string(),
+                name: "ソニーグループ(株)".to_string(),
+                price: "3,456".to_string(),
+                change: "-41".to_string(),
+                change_percent: "-1.17%".to_string(),
+                update_time: "12/27 15:00".to_string(),
+                ..Default::default()
+            },
+            StockData {
+                code: "USDJPY=FX".to_string(),
+                name: "米ドル/円".to_string(),
+                price: "151.234".to_string(),
+                ..Default::default()
+            },
+        ];
+        let path = temp_path("parquet");
+        write_parquet(&path, &records).unwrap();
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        assert_eq!(column("code").as_string::<i32>().value(1), "USDJPY=FX");
+        assert_eq!(column("name").as_string::<i32>().value(0), "ソニーグループ(株)");
+        assert_eq!(column("price").as_primitive::<Float64Type>().values(), &[3456.0, 151.234]);
+        let change = column("change");
+        assert_eq!(change.as_primitive::<Float64Type>().value(0), -41.0);
+        assert!(change.is_null(1));
+        assert_eq!(column("change_percent").as_primitive::<Float64Type>().value(0), -1.17);
+
+        let update_time = column("update_time");
+        let update_time = update_time.as_primitive::<TimestampMillisecondType>();
+        assert_eq!(update_time.timezone(), Some("+09:00"));
+        let time = update_time.value_as_datetime_with_tz(0, "+09:00".parse().unwrap()).unwrap();
+        assert_eq!((time.month(), time.day(), time.hour(), time.minute()), (12, 27, 15, 0));
+        assert!(update_time.is_null(1));
+    }
+
+    #[test]
+    fn test_failures_file_round_trip() {
+        let path = temp_path("failures");
+        let errors = [
+            ErrorRecord::new("9999", &ScraperError::NotFound("https://finance.yahoo.co.jp/quote/9999.T".to_string())),
+            ErrorRecord::new("6758", &ScraperError::Network("Request to 6758 failed".to_string())),
+        ];
+        write_failures(&path, &errors).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(r#""error": "not_found""#));
+        assert_eq!(read_failed_codes(&path).unwrap(), ["9999", "6758"]);
+
+        write_failures(&path, &[]).unwrap();
+        assert!(read_failed_codes(&path).unwrap().is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(read_failed_codes(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_completed_codes_missing_file() {
+        let completed = read_completed_codes(&temp_path("missing")).unwrap();
+        assert!(completed.is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+
+/// Longest spacing between dispatches, reached by rates below one request a day.
+const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A token bucket with a burst size of one: tokens are handed out no faster than
+/// `requests_per_second`, so consecutive dispatches are spaced by at least `1 / rate`.
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// A rate that isn't a positive number (zero, negative or NaN) means no limit.
+    pub fn new(requests_per_second: f64) -> Self {
+        let interval = if requests_per_second > 0.0 {
+            Duration::try_from_secs_f64(1.0 / requests_per_second).map_or(MAX_INTERVAL, |interval| interval.min(MAX_INTERVAL))
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            interval,
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    /// Waits until the caller may dispatch its request.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = match *next_slot {
+                Some(next) if next > now => next,
+                _ => now,
+            };
+            *next_slot = Some(slot + self.interval);
+            slot
+        };
+        sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_interval() {
+        assert_eq!(RateLimiter::new(4.0).interval, Duration::from_millis(250));
+        assert_eq!(RateLimiter::new(f64::INFINITY).interval, Duration::ZERO);
+        assert_eq!(RateLimiter::new(1e-300).interval, MAX_INTERVAL);
+        for unlimited in [0.0, -1.0, f64::NAN, f64::NEG_INFINITY] {
+            assert_eq!(RateLimiter::new(unlimited).interval, Duration::ZERO, "{}", unlimited);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_without_a_positive_rate_does_not_wait() {
+        let limiter = RateLimiter::new(0.0);
+        let started = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+    }
+}
@@ -1,17 +1,38 @@
-use crate::models::{self, ScraperError};
+use crate::config::{PriceAnchor, SearchFallback};
+use crate::instruments::is_known_index_name;
+use crate::models::{parse_change_string, Direction, ScraperError, SessionPhase, TradingState};
+use crate::search::code_from_quote_href;
+use crate::selectors::{scrape_field_layered, FieldSelectors};
+pub use crate::selectors::build_selector;
 use scraper::{ElementRef, Html, Selector};
 use std::error::Error;
+use std::ops::RangeInclusive;
 
-/// Finds a search area (an ancestor element) around a given text anchor.
+/// How many ancestors of the anchor the finders search by default.
+const SEARCH_AREA_LEVELS: usize = 8;
+
+/// Finds a search area (an ancestor element) around the first occurrence of a given text anchor.
 fn find_search_area_around_anchor<'a>(document: &'a Html, anchor_text: &str) -> Option<ElementRef<'a>> {
-    const MAX_LEVELS: usize = 8;
-    let mut search_area = None;
+    find_search_areas_around_anchor(document, anchor_text).into_iter().next()
+}
+
+/// Finds the search areas around every occurrence of a given text anchor, in document order.
+/// An anchor such as "前日比" may also appear in e.g. a chart legend, so finders that look for a
+/// value try each area until one yields it instead of committing to the first occurrence.
+fn find_search_areas_around_anchor<'a>(document: &'a Html, anchor_text: &str) -> Vec<ElementRef<'a>> {
+    find_ancestors_of_anchor(document, anchor_text, SEARCH_AREA_LEVELS)
+}
+
+/// The `max_levels`-th ancestor element (or the outermost one, when the document isn't that deep)
+/// of each text node equal to `anchor_text`. Occurrences sharing that ancestor yield it once.
+fn find_ancestors_of_anchor<'a>(document: &'a Html, anchor_text: &str, max_levels: usize) -> Vec<ElementRef<'a>> {
+    let mut search_areas: Vec<ElementRef<'a>> = Vec::new();
     for node in document.root_element().descendants() {
         if let Some(text_node) = node.value().as_text() {
-            if text_node.trim() == anchor_text {
+            if is_anchor_text(text_node, anchor_text) {
                 let mut ancestor = None;
                 let mut current = node.parent();
-                for _ in 0..MAX_LEVELS {
+                for _ in 0..max_levels {
                     if let Some(parent) = current {
                         if let Some(element) = ElementRef::wrap(parent) {
                             ancestor = Some(element);
@@ -21,46 +42,130 @@ fn find_search_area_around_anchor<'a>(document: &'a Html, anchor_text: &str) ->
                         break;
                     }
                 }
-                search_area = ancestor;
-                break;
+                if let Some(ancestor) = ancestor {
+                    if !search_areas.iter().any(|area| area.id() == ancestor.id()) {
+                        search_areas.push(ancestor);
+                    }
+                }
             }
         }
     }
-    search_area
+    search_areas
 }
 
-pub fn build_selector(element: &ElementRef) -> String {
-    let tag_name = element.value().name();
-    let classes = element.value().classes().collect::<Vec<_>>();
-    if !classes.is_empty() {
-        format!("{}.{}", tag_name, classes.join("."))
-    } else {
-        tag_name.to_string()
+/// Runs `search` in the areas around each occurrence of the anchor and, when none of them has a
+/// match, once more in the wider areas `fallback` allows. `what` names the searched field in the
+/// message logged (at info level) when only the widened search finds it.
+fn search_near_anchor<'a, T>(
+    document: &'a Html,
+    anchor_text: &str,
+    fallback: SearchFallback,
+    what: &str,
+    search: impl Fn(ElementRef<'a>) -> Option<T>,
+) -> Option<T> {
+    let areas = find_search_areas_around_anchor(document, anchor_text);
+    if let Some(found) = areas.iter().find_map(|area| search(*area)) {
+        return Some(found);
     }
+    let wider = match fallback {
+        SearchFallback::Off => return None,
+        SearchFallback::Ancestors(levels) => find_ancestors_of_anchor(document, anchor_text, levels),
+        SearchFallback::Document => vec![document.root_element()],
+    };
+    let found = wider
+        .into_iter()
+        .filter(|wide| !areas.iter().any(|area| area.id() == wide.id()))
+        .find_map(&search)?;
+    log::info!(
+        "{} was not found near {:?}; using the widened search ({:?}), the page layout may be unusual",
+        what, anchor_text, fallback
+    );
+    Some(found)
+}
+
+/// Replaces the minus glyphs some layouts use ("−" U+2212 and the full-width "－")
+/// with an ASCII '-' so sign checks work uniformly.
+pub fn normalize_minus(text: &str) -> String {
+    text.replace(['\u{2212}', '\u{FF0D}'], "-")
+}
+
+/// Code lengths accepted unless configured otherwise (`ScraperConfig::code_lengths`).
+pub const DEFAULT_CODE_LENGTHS: RangeInclusive<usize> = 4..=4;
+
+/// Returns true for a TSE security code: four ASCII characters where the first three are digits
+/// and the last is a digit or an uppercase letter (the alphanumeric format, e.g. "130A").
+pub fn is_code_pattern(text: &str) -> bool {
+    is_code_pattern_of_len(text, &DEFAULT_CODE_LENGTHS)
+}
+
+/// Like `is_code_pattern`, for codes whose length is in `lengths` (e.g. `4..=5` for the 5-digit
+/// codes of some new listings). All characters but the last must be digits.
+pub fn is_code_pattern_of_len(text: &str, lengths: &RangeInclusive<usize>) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    let Some((last, rest)) = chars.split_last() else {
+        return false;
+    };
+    lengths.contains(&chars.len())
+        && rest.iter().all(|c| c.is_ascii_digit())
+        && (last.is_ascii_digit() || last.is_ascii_uppercase())
+}
+
+/// Suffixes some page headings append to the instrument name, e.g. "日経平均株価の指数情報・推移".
+const NAME_HEADING_SUFFIXES: [&str; 2] = ["の指数情報・推移", "の株価・株式情報"];
+
+/// `text` without a trailing `NAME_HEADING_SUFFIXES` entry, trimmed.
+fn strip_name_suffix(text: &str) -> &str {
+    let text = text.trim();
+    NAME_HEADING_SUFFIXES
+        .iter()
+        .find_map(|suffix| text.strip_suffix(suffix))
+        .map_or(text, str::trim)
+}
+
+/// Returns true when `text` is the anchor, also when it is a heading that carries the anchor
+/// plus one of the `NAME_HEADING_SUFFIXES` (the name found by `find_name_dynamically`).
+fn is_anchor_text(text: &str, anchor_text: &str) -> bool {
+    text.trim() == anchor_text || strip_name_suffix(text) == anchor_text
 }
 
 /// Dynamically finds the name and its selector from the page.
+/// Both `h1` and `h2` are considered, since index pages such as ^DJI put the name in an `h1`.
+/// An `h1` is taken before the first `h2` only when it looks like an instrument name, or when it is
+/// a heading like "…の指数情報・推移" (the suffix is stripped), so stock pages keep using their `h2`;
+/// otherwise the first `h1` is the last resort for pages without any `h2`.
 pub async fn find_name_dynamically(document: &Html) -> Result<(Option<String>, String), Box<dyn Error>> {
     let mut found_name_selector: Option<String> = None;
     let mut found_name_text = String::new();
 
-    let h2_selector = Selector::parse("h2").map_err(|e| models::ScraperError(format!("{:?}", e)))?;
+    let heading_selector = Selector::parse("h1, h2").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
     let mut best_candidate_selector = None;
     let mut fallback_candidate_selector = None;
+    let mut h1_candidate_selector = None;
     let mut best_candidate_text = None;
     let mut fallback_candidate_text = None;
+    let mut h1_candidate_text = None;
 
-    for element in document.select(&h2_selector) {
-        let text = element.text().collect::<String>().trim().to_string();
+    for element in document.select(&heading_selector) {
+        let heading = element.text().collect::<String>();
+        let text = strip_name_suffix(&heading).to_string();
+        let had_suffix = text != heading.trim();
         if !text.is_empty() && !text.chars().all(char::is_numeric) {
-            if text.contains("(株)") || text == "NYダウ" || text == "日経平均株価" || text.contains("/") {
-                best_candidate_selector = Some(build_selector(&element));
+            let is_h1 = element.value().name() == "h1";
+            if text.contains("(株)")
+                || is_known_index_name(&text)
+                || text.contains("/")
+                || (is_h1 && had_suffix)
+            {
+                best_candidate_selector = Some(selector_for_element(document, element));
                 best_candidate_text = Some(text);
                 break;
             }
-            if fallback_candidate_selector.is_none() {
-                fallback_candidate_selector = Some(build_selector(&element));
+            if fallback_candidate_selector.is_none() && !is_h1 {
+                fallback_candidate_selector = Some(selector_for_element(document, element));
                 fallback_candidate_text = Some(text);
+            } else if h1_candidate_selector.is_none() && is_h1 {
+                h1_candidate_selector = Some(selector_for_element(document, element));
+                h1_candidate_text = Some(text);
             }
         }
     }
@@ -71,41 +176,147 @@ pub async fn find_name_dynamically(document: &Html) -> Result<(Option<String>, S
     } else if fallback_candidate_selector.is_some() {
         found_name_selector = fallback_candidate_selector;
         found_name_text = fallback_candidate_text.unwrap_or_default();
+    } else if h1_candidate_selector.is_some() {
+        found_name_selector = h1_candidate_selector;
+        found_name_text = h1_candidate_text.unwrap_or_default();
     }
 
     Ok((found_name_selector, found_name_text))
 }
 
+/// Finds the English (romanized) name Yahoo sometimes shows as a small subheading next to the main name.
+/// Only the name element's parent and grandparent are searched, so unrelated Latin text elsewhere is ignored.
+pub fn find_english_name(document: &Html, name_anchor: &str) -> Option<String> {
+    const MAX_LEVELS: usize = 2;
+    for node in document.root_element().descendants() {
+        if let Some(text_node) = node.value().as_text() {
+            if is_anchor_text(text_node, name_anchor) {
+                let mut current = node.parent().and_then(|name_element| name_element.parent());
+                for _ in 0..MAX_LEVELS {
+                    if let Some(area) = current.and_then(ElementRef::wrap) {
+                        for text in area.text() {
+                            let trimmed = text.trim();
+                            if is_english_name(trimmed) {
+                                return Some(trimmed.to_string());
+                            }
+                        }
+                        current = area.parent();
+                    } else {
+                        break;
+                    }
+                }
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// The price board around the name: the outermost ancestor of the name whose class mentions
+/// "PriceBoard", or, without one, the name's grandparent.
+fn find_price_board<'a>(document: &'a Html, name_anchor: &str) -> Option<ElementRef<'a>> {
+    let name_node = document
+        .root_element()
+        .descendants()
+        .find(|node| node.value().as_text().is_some_and(|text| is_anchor_text(text, name_anchor)))?;
+    let ancestors: Vec<ElementRef> = name_node.ancestors().filter_map(ElementRef::wrap).collect();
+    ancestors
+        .iter()
+        .take_while(|element| element.value().classes().any(|class| class.contains("PriceBoard")))
+        .last()
+        .or_else(|| ancestors.get(2))
+        .or_else(|| ancestors.last())
+        .copied()
+}
+
+/// Finds the `src` of the first image (a company logo or chart thumbnail) inside the price board
+/// (see `find_price_board`), as written in the page, i.e. possibly relative.
+pub fn find_image_src(document: &Html, name_anchor: &str) -> Option<String> {
+    let board = find_price_board(document, name_anchor)?;
+    let img_selector = Selector::parse("img[src]").ok()?;
+    board
+        .select(&img_selector)
+        .filter_map(|img| img.value().attr("src"))
+        .map(str::trim)
+        .find(|src| !src.is_empty() && !src.starts_with("data:"))
+        .map(str::to_string)
+}
+
+/// Latin-script text with enough letters to be a name rather than a code or a short label like "ETF".
+fn is_english_name(text: &str) -> bool {
+    text.chars().filter(|c| c.is_ascii_alphabetic()).count() >= 4
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || " .,&'-()".contains(c))
+}
+
 /// Helper function to scrape a single field using a selector.
+/// This is the last step of the precedence in `selectors::FieldSelectors`, on its own.
 pub fn scrape_field(document: &Html, selector_opt: &Option<String>, _field_name: &str) -> String {
-    if let Some(selector_str) = selector_opt {
-        if let Ok(selector) = Selector::parse(selector_str) {
-            if let Some(element) = document.select(&selector).next() {
-                let value = element.text().collect::<String>().trim().to_string();
-                return value;
-            }
+    let selectors = FieldSelectors {
+        discovered: selector_opt.as_deref(),
+        ..Default::default()
+    };
+    scrape_field_layered(document, &selectors)
+        .map(|(value, _)| value)
+        .unwrap_or_default()
+}
+
+/// The list-field counterpart of `scrape_field`, for fields such as news headlines or related tickers:
+/// the trimmed text of every element `selector_str` matches, in document order. Empty texts and
+/// repeats are skipped, and at most `max_count` texts are returned. An invalid selector yields nothing.
+pub fn scrape_all_fields(document: &Html, selector_str: &str, max_count: usize) -> Vec<String> {
+    scrape_all_with(document, selector_str, max_count, |element| Some(element.text().collect()))
+}
+
+/// `scrape_all_fields` reading each matched element's `attr` attribute instead of its text, e.g. the
+/// `href` of every link in a list. Elements without the attribute are skipped.
+pub fn scrape_all_attrs(document: &Html, selector_str: &str, attr: &str, max_count: usize) -> Vec<String> {
+    scrape_all_with(document, selector_str, max_count, |element| element.value().attr(attr).map(str::to_string))
+}
+
+fn scrape_all_with(
+    document: &Html,
+    selector_str: &str,
+    max_count: usize,
+    value: impl Fn(ElementRef) -> Option<String>,
+) -> Vec<String> {
+    let Ok(selector) = Selector::parse(selector_str) else {
+        return Vec::new();
+    };
+    let mut values: Vec<String> = Vec::new();
+    for element in document.select(&selector) {
+        if values.len() >= max_count {
+            break;
+        }
+        let Some(value) = value(element).map(|value| value.trim().to_string()) else {
+            continue;
+        };
+        if !value.is_empty() && !values.contains(&value) {
+            values.push(value);
         }
     }
-    String::new()
+    values
 }
 
+/// Finds the element near the anchor whose text matches `pattern_type`;
+/// "code" matches a security code whose length is in `code_lengths`.
 pub async fn find_text_pattern_selector_near_anchor(
     document: &Html,
     anchor_text: &str,
     pattern_type: &str,
+    code_lengths: &RangeInclusive<usize>,
 ) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, anchor_text) {
+    for area in find_search_areas_around_anchor(document, anchor_text) {
         for node in area.descendants() {
             if let Some(text_node) = node.value().as_text() {
                 let trimmed_text = text_node.trim();
                 let is_match = match pattern_type {
-                    "code" => trimmed_text.len() == 4 && trimmed_text.chars().all(char::is_numeric),
+                    "code" => is_code_pattern_of_len(trimmed_text, code_lengths),
                     _ => false,
                 };
 
                 if is_match {
                     if let Some(parent) = node.parent().and_then(ElementRef::wrap) {
-                        return Ok(Some(build_selector(&parent)));
+                        return Ok(Some(selector_for_element(document, parent)));
                     }
                 }
             }
@@ -114,107 +325,446 @@ pub async fn find_text_pattern_selector_near_anchor(
     Ok(None)
 }
 
+/// How many numeric candidates `find_prominent_number` collects before it stops looking.
+pub const DEFAULT_MAX_PRICE_CANDIDATES: usize = 200;
+
+/// Class substring of the blocks that hold the price itself (`PriceBoard__price__1V0k`,
+/// `_CommonPriceBoard__price_1g7gt_64`, ...), as opposed to the changes next to it.
+const PRICE_CONTAINER_CLASS: &str = "__price";
+
+fn is_in_price_container(element: ElementRef, area: ElementRef) -> bool {
+    std::iter::once(element)
+        .chain(element.ancestors().filter_map(ElementRef::wrap))
+        .take_while(|ancestor| ancestor.id() != area.id())
+        .any(|ancestor| ancestor.value().attr("class").is_some_and(|class| class.contains(PRICE_CONTAINER_CLASS)))
+}
+
+/// Picks the most prominent number in `area` (the one with the most classes; the first wins ties),
+/// ignoring `exclude` and numbers of two characters or less.
+///
+/// The work is bounded: a number inside a price container (see `PRICE_CONTAINER_CLASS`) is taken
+/// as soon as it is seen, and at most `max_candidates` numbers are collected otherwise.
+pub fn find_prominent_number<'a>(area: ElementRef<'a>, exclude: &str, max_candidates: usize) -> Option<ElementRef<'a>> {
+    let selector = Selector::parse("*").ok()?;
+    let mut best: Option<(usize, ElementRef)> = None;
+    let numbers = area.select(&selector).filter(|element| {
+        let trimmed = element.text().collect::<String>().trim().replace(",", "");
+        !trimmed.is_empty() && trimmed.parse::<f64>().is_ok() && trimmed.len() > 2 && trimmed != exclude
+    });
+    for element in numbers.take(max_candidates) {
+        if is_in_price_container(element, area) {
+            return Some(element);
+        }
+        let class_count = element.value().classes().count();
+        if best.is_none_or(|(best_count, _)| class_count > best_count) {
+            best = Some((class_count, element));
+        }
+    }
+    best.map(|(_, element)| element)
+}
+
+/// Numbers considered by `find_price_like_number`, in page order; the price is shown near the top.
+const MAX_GUESS_CANDIDATES: usize = 20;
+
+/// Whether a number is shown in bold: a `strong` / `b` / heading tag or a bold `font-weight`.
+fn is_emphasized(element: ElementRef) -> bool {
+    let style = element.value().attr("style").unwrap_or_default().replace(' ', "");
+    matches!(element.value().name(), "strong" | "b" | "h1" | "h2" | "h3")
+        || ["font-weight:bold", "font-weight:600", "font-weight:700", "font-weight:800", "font-weight:900"]
+            .iter()
+            .any(|weight| style.contains(weight))
+}
+
+/// Last-resort price: the most price-like number among the first `MAX_GUESS_CANDIDATES` on the page.
+/// Bold numbers win, then those with the most classes on them and their parent, then the largest.
+/// Only unsigned numbers without a percent sign that aren't `code` are considered. This is a guess
+/// (see `ScraperConfig::aggressive`) and can easily pick the wrong number.
+pub fn find_price_like_number(document: &Html, code: &str) -> Option<String> {
+    let selector = Selector::parse("body *").ok()?;
+    document
+        .select(&selector)
+        .filter(|element| !matches!(element.value().name(), "script" | "style"))
+        .filter(|element| element.children().all(|child| child.value().is_text()))
+        .filter_map(|element| {
+            let text = element.text().collect::<String>().trim().to_string();
+            let value = split_price_unit(&text).0.replace(',', "").parse::<f64>().ok()?;
+            is_price_text(&text, code).then_some((element, text, value))
+        })
+        .take(MAX_GUESS_CANDIDATES)
+        .max_by(|(a, _, a_value), (b, _, b_value)| {
+            let rank = |element: &ElementRef| {
+                let parent_classes = element.parent().and_then(ElementRef::wrap).map_or(0, |parent| parent.value().classes().count());
+                (is_emphasized(*element), element.value().classes().count() + parent_classes)
+            };
+            rank(a).cmp(&rank(b)).then(a_value.total_cmp(b_value))
+        })
+        .map(|(_, text, _)| text)
+}
+
 // --- Stock-specific finders (using "前日比" anchor) ---
 
+/// Class-substring selectors known from the static scraper's layouts (`PriceBoard__price` wrapping a
+/// `StyledNumber__value`). Substrings keep them working across the hashed class suffixes of both the
+/// stock and index boards. They are tried before the DOM walk, which only runs when none of them matches.
+const PRICE_CLASS_HINTS: [&str; 1] = ["span[class*='PriceBoard__price'] span[class*='StyledNumber__value']"];
+
+/// Units some pages print inside the price span, e.g. "149.52円" or "1,234 pt". Percent signs are not
+/// among them: a percentage is a change, never a price.
+const PRICE_UNITS: [&str; 3] = ["円", "ドル", "pt"];
+
+/// Splits a trailing unit from `PRICE_UNITS` off a price text: `("149.52", Some("円"))` for "149.52円".
+/// The number is trimmed; text without a known unit is returned trimmed, with `None`.
+pub fn split_price_unit(text: &str) -> (&str, Option<&'static str>) {
+    let trimmed = text.trim();
+    PRICE_UNITS
+        .iter()
+        .find_map(|unit| Some((trimmed.strip_suffix(unit)?.trim_end(), Some(*unit))))
+        .unwrap_or((trimmed, None))
+}
+
+/// Returns true when `text` looks like a price: a plain number without sign or percent that isn't the code itself.
+/// A trailing unit (see `PRICE_UNITS`) is allowed.
+fn is_price_text(text: &str, code: &str) -> bool {
+    let trimmed_text = normalize_minus(split_price_unit(text).0);
+    let cleaned_text = trimmed_text.replace(",", "");
+
+    !cleaned_text.is_empty()
+        && cleaned_text.parse::<f64>().is_ok()
+        && !trimmed_text.starts_with('+')
+        && !trimmed_text.starts_with('-')
+        && !trimmed_text.contains('%')
+        && cleaned_text != code // <-- The key fix
+}
+
+/// Tries the `PRICE_CLASS_HINTS` selectors; returns the first one whose first match holds a price.
+pub fn find_price_by_class_hint(document: &Html, code: &str) -> Option<String> {
+    PRICE_CLASS_HINTS.iter().find_map(|hint| {
+        let selector = Selector::parse(hint).ok()?;
+        let element = document.select(&selector).next()?;
+        if is_price_text(&element.text().collect::<String>(), code) {
+            Some(hint.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// `<meta>` tags whose `content` is the price.
+const PRICE_META_SELECTORS: [&str; 3] = [
+    "meta[itemprop='price']",
+    "meta[property='product:price:amount']",
+    "meta[property='og:price:amount']",
+];
+
+/// JSON-LD properties that may name the instrument an object describes.
+const JSON_LD_CODE_KEYS: [&str; 5] = ["tickerSymbol", "symbol", "identifier", "url", "@id"];
+
+/// Whether a JSON-LD property value names `code`: the code itself, with an exchange prefix or the
+/// ".T" suffix ("TYO:7203", "7203.T"), or a quote URL of the code.
+fn names_code(text: &str, code: &str) -> bool {
+    let symbol = text.trim().rsplit(':').next().unwrap_or_default();
+    let symbol = symbol.strip_suffix(".T").unwrap_or(symbol);
+    symbol.eq_ignore_ascii_case(code) || crate::code_from_url(text).is_some_and(|found| found.eq_ignore_ascii_case(code))
+}
+
+/// The first `price` in a JSON-LD value, searched depth-first (e.g. in `offers`).
+fn json_ld_any_price(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Object(object) => match object.get("price") {
+            Some(serde_json::Value::String(price)) => Some(price.clone()),
+            Some(serde_json::Value::Number(price)) => Some(price.to_string()),
+            _ => object.values().find_map(json_ld_any_price),
+        },
+        serde_json::Value::Array(items) => items.iter().find_map(json_ld_any_price),
+        _ => None,
+    }
+}
+
+/// The first `price` inside a JSON-LD object that names `code` (see `JSON_LD_CODE_KEYS`), searched
+/// depth-first (e.g. in `@graph`). Prices of objects that don't say whose they are are ignored.
+fn json_ld_price(value: &serde_json::Value, code: &str) -> Option<String> {
+    match value {
+        serde_json::Value::Object(object) => {
+            let names_the_code = JSON_LD_CODE_KEYS
+                .iter()
+                .any(|key| matches!(object.get(*key), Some(serde_json::Value::String(text)) if names_code(text, code)));
+            if names_the_code {
+                json_ld_any_price(value)
+            } else {
+                object.values().find_map(|value| json_ld_price(value, code))
+            }
+        }
+        serde_json::Value::Array(items) => items.iter().find_map(|item| json_ld_price(item, code)),
+        _ => None,
+    }
+}
+
+/// Price from the JSON-LD data a page embeds for machines: a `price` in a
+/// `<script type="application/ld+json">` object whose ticker or URL names `code`.
+/// It doesn't move with the visual layout, so a value that reads as a price is taken as is.
+pub fn find_structured_price(document: &Html, code: &str) -> Option<String> {
+    let script_selector = Selector::parse("script[type='application/ld+json']").ok()?;
+    document
+        .select(&script_selector)
+        .filter_map(|script| {
+            let value = serde_json::from_str(&script.text().collect::<String>()).ok()?;
+            json_ld_price(&value, code)
+        })
+        .map(|price| price.trim().to_string())
+        .find(|price| is_price_text(price, code))
+}
+
+/// Price from one of the `PRICE_META_SELECTORS` tags. Unlike JSON-LD, a tag doesn't say which
+/// instrument it describes, so it is trusted less than the page's own price selectors.
+pub fn find_meta_price(document: &Html, code: &str) -> Option<String> {
+    PRICE_META_SELECTORS
+        .iter()
+        .filter_map(|meta| {
+            let selector = Selector::parse(meta).ok()?;
+            document.select(&selector).find_map(|element| element.value().attr("content").map(str::to_string))
+        })
+        .map(|price| price.trim().to_string())
+        .find(|price| is_price_text(price, code))
+}
+
 pub async fn find_stock_price_selector(
     document: &Html,
     anchor_text: &str,
     code: &str, // New parameter to avoid mistaking the code for the price
 ) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    if let Some(name_area) = find_search_area_around_anchor(document, anchor_text) {
-        let mut zenjitsuhi_element_opt = None;
-        let zenjitsuhi_selector = Selector::parse("*").map_err(|e| ScraperError(format!("{:?}", e)))?;
-        for element in name_area.select(&zenjitsuhi_selector) {
-            if element.text().collect::<String>().trim() == "前日比" {
-                zenjitsuhi_element_opt = Some(element);
-                break;
+    find_stock_price_selector_with_anchors(document, anchor_text, code, &PriceAnchor::defaults()).await
+}
+
+/// Like `find_stock_price_selector`, but tries the given anchors in order until one yields a price.
+///
+/// "前日比" follows the price, so only the elements before it are searched; for the other anchors
+/// the price may sit on either side, so both directions are searched.
+pub async fn find_stock_price_selector_with_anchors(
+    document: &Html,
+    anchor_text: &str,
+    code: &str,
+    anchors: &[PriceAnchor],
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    if let Some(hint) = find_price_by_class_hint(document, code) {
+        return Ok(Some(hint));
+    }
+
+    let search_area = find_search_area_around_anchor(document, anchor_text).unwrap_or_else(|| document.root_element());
+    let element_selector = Selector::parse("*").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+    for anchor in anchors {
+        let anchor_label = match anchor {
+            PriceAnchor::Name => anchor_text,
+            PriceAnchor::Label(label) => label.as_str(),
+        };
+        let anchor_element = search_area
+            .select(&element_selector)
+            .find(|element| is_anchor_text(&element.text().collect::<String>(), anchor_label));
+
+        if let Some(anchor_element) = anchor_element {
+            let include_following = anchor_label != "前日比";
+            if let Some(price_element) = find_price_nearest_element(anchor_element, code, include_following)? {
+                return Ok(Some(selector_for_element(document, price_element)));
             }
         }
+    }
 
-        if let Some(zenjitsuhi_element) = zenjitsuhi_element_opt {
-            let mut current_element = zenjitsuhi_element;
-            loop {
-                for sibling in current_element.prev_siblings() {
-                    if let Some(sibling_element) = ElementRef::wrap(sibling) {
-                        let span_selector = Selector::parse("span").map_err(|e| ScraperError(format!("{:?}", e)))?;
-                        for span_element in sibling_element.select(&span_selector) {
-                            let text = span_element.text().collect::<String>();
-                            let trimmed_text = text.trim();
-                            let cleaned_text = trimmed_text.replace(",", "");
-
-                            if !cleaned_text.is_empty()
-                                && cleaned_text.parse::<f64>().is_ok()
-                                && !trimmed_text.starts_with('+')
-                                && !trimmed_text.starts_with('-')
-                                && !trimmed_text.contains('%')
-                                && cleaned_text != code // <-- The key fix
-                            {
-                                return Ok(Some(build_selector(&span_element)));
-                            }
-                        }
+    Ok(None)
+}
+
+/// Finds the price span closest to `anchor_element`.
+///
+/// Candidates are scored by their DOM distance from the anchor: the number of levels climbed,
+/// plus how many siblings away the containing block is, plus how deep the span sits inside it.
+/// The closest candidate wins; ties go to the first one found (preceding siblings first).
+fn find_price_nearest_element<'a>(
+    anchor_element: ElementRef<'a>,
+    code: &str,
+    include_following: bool,
+) -> Result<Option<ElementRef<'a>>, ScraperError> {
+    let span_selector = Selector::parse("span").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+    let mut best: Option<(usize, ElementRef)> = None;
+    let mut current_element = anchor_element;
+    let mut level = 0;
+    loop {
+        // Nothing found from here on can be closer than level + 1.
+        if matches!(best, Some((score, _)) if score <= level + 1) {
+            break;
+        }
+        let preceding = current_element.prev_siblings().filter_map(ElementRef::wrap).enumerate();
+        let following = current_element
+            .next_siblings()
+            .filter_map(ElementRef::wrap)
+            .enumerate()
+            .filter(|_| include_following);
+        for (offset, sibling_element) in preceding.chain(following) {
+            for span_element in sibling_element.select(&span_selector) {
+                if is_price_text(&span_element.text().collect::<String>(), code) {
+                    let depth = span_element
+                        .ancestors()
+                        .take_while(|ancestor| ancestor.id() != sibling_element.id())
+                        .count();
+                    let score = level + offset + 1 + depth;
+                    if best.is_none_or(|(best_score, _)| score < best_score) {
+                        best = Some((score, span_element));
                     }
                 }
+            }
+        }
 
-                if let Some(parent) = current_element.parent().and_then(ElementRef::wrap) {
-                    current_element = parent;
-                } else {
-                    break; // No more parents to check
-                }
+        if let Some(parent) = current_element.parent().and_then(ElementRef::wrap) {
+            current_element = parent;
+            level += 1;
+        } else {
+            break; // No more parents to check
+        }
+    }
+    Ok(best.map(|(_, element)| element))
+}
+
+/// Price finder for index pages: the index level is the biggest number on its board, so among the
+/// plain numbers in the price board (see `find_price_board`) the largest one is taken. Changes,
+/// percentages and small counts lose against it regardless of where they sit.
+pub fn find_index_price_selector(document: &Html, name_anchor: &str, code: &str) -> Option<String> {
+    let board = find_price_board(document, name_anchor)?;
+    let leaf_selector = Selector::parse("span, div, td, dd").ok()?;
+    let (_, element) = board
+        .select(&leaf_selector)
+        .filter(|element| element.children().all(|child| child.value().is_text()))
+        .filter_map(|element| {
+            let text = element.text().collect::<String>();
+            if !is_price_text(&text, code) {
+                return None;
             }
+            let value = split_price_unit(&text).0.replace(',', "").parse::<f64>().ok()?;
+            Some((value, element))
+        })
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))?;
+    unique_selector_for(document, element)
+}
+
+/// The selector a finder returns for `element`. Usually that is `build_selector`, but a class such as
+/// `StyledNumber__value` can match dozens of elements and the field is read from the first match;
+/// when that isn't `element`, the ambiguity is logged (at debug level) and the selector is pinned to
+/// the element by its ancestors or position instead (see `unique_selector_for`).
+pub fn selector_for_element(document: &Html, element: ElementRef) -> String {
+    let selector_text = build_selector(&element);
+    let Ok(selector) = Selector::parse(&selector_text) else {
+        return selector_text;
+    };
+    let mut matches = document.select(&selector);
+    if matches.next().map(|first| first.id()) == Some(element.id()) {
+        return selector_text;
+    }
+    let count = 1 + matches.count();
+    match unique_selector_for(document, element) {
+        Some(pinned) => {
+            log::debug!("{} matches {} elements and the first is not the one found; using {}", selector_text, count, pinned);
+            pinned
         }
+        None => selector_text,
     }
+}
 
-    Ok(None)
+/// A selector whose first match in `document` is `element`: `build_selector` of the element,
+/// prefixed with its ancestors' (`parent > element`) until the first match is the element itself.
+/// When the classes alone can't tell the element from an earlier look-alike (e.g. two rows built
+/// from the same markup), each step is pinned with `:nth-of-type` as well.
+fn unique_selector_for(document: &Html, element: ElementRef) -> Option<String> {
+    unique_selector_with(document, element, build_selector)
+        .or_else(|| unique_selector_with(document, element, |e| format!("{}:nth-of-type({})", build_selector(e), nth_of_type(e))))
+}
+
+fn unique_selector_with(document: &Html, element: ElementRef, step: impl Fn(&ElementRef) -> String) -> Option<String> {
+    const MAX_LEVELS: usize = 3;
+    let mut selector_text = step(&element);
+    let mut ancestors = element.ancestors().filter_map(ElementRef::wrap);
+    for _ in 0..=MAX_LEVELS {
+        let selector = Selector::parse(&selector_text).ok()?;
+        if document.select(&selector).next().map(|first| first.id()) == Some(element.id()) {
+            return Some(selector_text);
+        }
+        selector_text = format!("{} > {}", step(&ancestors.next()?), selector_text);
+    }
+    None
+}
+
+/// The 1-based position of `element` among its siblings with the same tag.
+fn nth_of_type(element: &ElementRef) -> usize {
+    let tag = element.value().name();
+    1 + element
+        .prev_siblings()
+        .filter_map(ElementRef::wrap)
+        .filter(|sibling| sibling.value().name() == tag)
+        .count()
 }
 
 pub async fn find_stock_change_selector(
     document: &Html,
     anchor_text: &str,
+    fallback: SearchFallback,
 ) -> Result<Option<String>, Box<dyn Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, anchor_text) {
-        let selector = Selector::parse("*").map_err(|e| ScraperError(format!("{:?}", e)))?;
-        for element in area.select(&selector) {
-            let text = element.text().collect::<String>();
-            let trimmed = text.trim();
-
-            if (trimmed.starts_with('+') || trimmed.starts_with('-')) && !trimmed.contains('%') && trimmed.len() > 1 {
-                let after_sign = &trimmed[1..].replace(",", "");
-                if after_sign.parse::<f64>().is_ok() {
-                    return Ok(Some(build_selector(&element)));
-                }
-            }
-        }
-    }
-    Ok(None)
+    let selector = Selector::parse("*").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+    Ok(search_near_anchor(document, anchor_text, fallback, "change", |area| {
+        area.select(&selector)
+            .find(|element| {
+                let text = element.text().collect::<String>();
+                let trimmed = normalize_minus(text.trim());
+                (trimmed.starts_with('+') || trimmed.starts_with('-'))
+                    && !trimmed.contains('%')
+                    && trimmed.len() > 1
+                    && trimmed[1..].replace(",", "").parse::<f64>().is_ok()
+            })
+            .map(|element| selector_for_element(document, element))
+    }))
 }
 
 pub async fn find_stock_change_percent_selector(
     document: &Html,
     anchor_text: &str,
+    fallback: SearchFallback,
 ) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, anchor_text) {
-        let span_selector = Selector::parse("span").map_err(|e| ScraperError(format!("{:?}", e)))?;
-        for span_element in area.select(&span_selector) {
-            let text = span_element.text().collect::<String>();
-            let trimmed = text.trim();
+    let span_selector = Selector::parse("span").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+    Ok(search_near_anchor(document, anchor_text, fallback, "change_percent", |area| {
+        area.select(&span_selector)
+            .find(|span_element| {
+                let text = span_element.text().collect::<String>();
+                let trimmed = normalize_minus(text.trim());
+                trimmed.starts_with('(')
+                    && trimmed.ends_with(')')
+                    && trimmed.contains('%')
+                    && trimmed.chars().any(|c| c.is_numeric())
+            })
+            .map(|span_element| selector_for_element(document, span_element))
+    }))
+}
 
-            if trimmed.starts_with('(')
-                && trimmed.ends_with(')')
-                && trimmed.contains('%')
-                && trimmed.chars().any(|c| c.is_numeric())
-            {
-                return Ok(Some(build_selector(&span_element)));
-            }
-        }
-    }
-    Ok(None)
+/// Finds an element holding both changes at once, e.g. `+120(+1.52%)`.
+/// Used when the page doesn't render the absolute and percent change separately.
+pub async fn find_combined_change_selector(
+    document: &Html,
+    anchor_text: &str,
+    fallback: SearchFallback,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let selector = Selector::parse("*").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+    Ok(search_near_anchor(document, anchor_text, fallback, "combined change", |area| {
+        area.select(&selector)
+            .find(|element| {
+                let text = normalize_minus(element.text().collect::<String>().trim());
+                let (abs, pct) = parse_change_string(&text);
+                let abs_is_signed_number = (abs.starts_with('+') || abs.starts_with('-'))
+                    && abs.len() > 1
+                    && abs[1..].replace(",", "").parse::<f64>().is_ok();
+                abs_is_signed_number && pct.ends_with('%')
+            })
+            .map(|element| selector_for_element(document, element))
+    }))
 }
 
 pub async fn find_stock_update_time_selector(
     document: &Html,
 ) -> Result<Option<String>, Box<dyn Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, "リアルタイム株価") {
-        let footer_selector = Selector::parse("*").map_err(|e| ScraperError(format!("{:?}", e)))?;
+    for area in find_search_areas_around_anchor(document, "リアルタイム株価") {
+        let footer_selector = Selector::parse("*").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
         if let Some(footer_element) = area.select(&footer_selector).find(|element| {
             if let Some(class) = element.value().attr("class") {
                 class.contains("PriceBoard__mainFooter")
@@ -222,9 +772,9 @@ pub async fn find_stock_update_time_selector(
                 false
             }
         }) {
-            let time_tag_selector = Selector::parse("time").map_err(|e| ScraperError(format!("{:?}", e)))?;
+            let time_tag_selector = Selector::parse("time").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
             if let Some(time_element) = footer_element.select(&time_tag_selector).next() {
-                return Ok(Some(build_selector(&time_element)));
+                return Ok(Some(selector_for_element(document, time_element)));
             }
         }
     }
@@ -232,6 +782,284 @@ pub async fn find_stock_update_time_selector(
     Ok(None)
 }
 
+// --- ETF / reference panel finders ---
+
+/// Returns true when the ETF marker label is shown next to the instrument name.
+pub fn is_etf_page(document: &Html, name_anchor: &str) -> bool {
+    if let Some(area) = find_search_area_around_anchor(document, name_anchor) {
+        for node in area.descendants() {
+            if let Some(text_node) = node.value().as_text() {
+                if matches!(text_node.trim(), "ETF" | "ETN") {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Detects the 特別気配 / ストップ高 / ストップ安 markers shown around the price board.
+/// A limit marker wins over the special-quote marker, since limit moves are usually quoted specially too.
+pub fn find_trading_state(document: &Html, name_anchor: &str) -> TradingState {
+    let Some(area) = find_search_area_around_anchor(document, name_anchor) else {
+        return TradingState::Normal;
+    };
+    let mut state = TradingState::Normal;
+    for node in area.descendants() {
+        if let Some(text) = node.value().as_text() {
+            if text.contains("ストップ高") {
+                return TradingState::LimitUp;
+            }
+            if text.contains("ストップ安") {
+                return TradingState::LimitDown;
+            }
+            // 特別気配 is also shown as 特別買気配 / 特別売気配.
+            if text.contains("特別") && text.contains("気配") {
+                state = TradingState::SpecialQuote;
+            }
+        }
+    }
+    state
+}
+
+/// Finds the session status marker (寄付前, ザラ場, 前引け, 引け後, ...) in the price board around the name.
+/// Only short texts are considered, so a sentence that happens to mention 引け isn't taken for the marker.
+pub fn find_session_status(document: &Html, name_anchor: &str) -> Option<String> {
+    const MAX_MARKER_CHARS: usize = 8;
+    let board = find_price_board(document, name_anchor)?;
+    board
+        .text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty() && text.chars().count() <= MAX_MARKER_CHARS)
+        .find(|text| SessionPhase::from_status(text).is_some())
+        .map(str::to_string)
+}
+
+/// Reads the direction from the modifier class of the `PriceChangeLabel` element near the anchor,
+/// e.g. `PriceChangeLabel--up__1cR8` or `_PriceChangeLabel--down_hse06_1` (the hash suffix varies).
+/// Returns `None` when no modifier is present.
+pub fn find_price_direction(document: &Html, name_anchor: &str) -> Option<Direction> {
+    let area = find_search_area_around_anchor(document, name_anchor).unwrap_or_else(|| document.root_element());
+    for element in area.descendants().filter_map(ElementRef::wrap) {
+        for class in element.value().classes() {
+            let Some((block, modifier)) = class.split_once("--") else {
+                continue;
+            };
+            if !block.trim_start_matches('_').starts_with("PriceChangeLabel") {
+                continue;
+            }
+            let modifier = modifier.split('_').next().unwrap_or_default();
+            match modifier {
+                "up" | "plus" | "green" => return Some(Direction::Up),
+                "down" | "minus" | "red" => return Some(Direction::Down),
+                "even" | "flat" | "unchanged" | "gray" => return Some(Direction::Flat),
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Returns the trimmed text of the page's `<title>`, if it has a non-empty one.
+pub fn find_page_title(document: &Html) -> Option<String> {
+    let selector = Selector::parse("title").ok()?;
+    let title = document.select(&selector).next()?.text().collect::<String>();
+    let title = title.trim();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
+
+/// Finds the value displayed next to a label (e.g. "基準価額") in the reference panels.
+/// The value is the first element following the label, or following one of its ancestors
+/// (typically the `dd` that follows the label's `dt`).
+pub fn find_detail_field(document: &Html, label: &str) -> Option<String> {
+    const MAX_LEVELS: usize = 4;
+    for node in document.root_element().descendants() {
+        if let Some(text_node) = node.value().as_text() {
+            if text_node.trim() == label {
+                let mut current = node.parent();
+                for _ in 0..MAX_LEVELS {
+                    if let Some(current_node) = current {
+                        if let Some(sibling) = current_node.next_siblings().find_map(ElementRef::wrap) {
+                            let value = sibling.text().collect::<String>().trim().to_string();
+                            if !value.is_empty() {
+                                return Some(value);
+                            }
+                        }
+                        current = current_node.parent();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Finds the codes listed under "関連銘柄" (related stocks), in page order and without repeats.
+/// The codes come from the `/quote/XXXX.T` links rather than their text, which is usually the company
+/// name. `own_code` (the page's own stock) is left out.
+pub fn find_related_codes(document: &Html, own_code: &str) -> Vec<String> {
+    const MAX_LEVELS: usize = 4;
+    const LINKS: &str = "a[href*='/quote/']";
+    let Ok(link_selector) = Selector::parse(LINKS) else {
+        return Vec::new();
+    };
+    let mut codes: Vec<String> = Vec::new();
+    let headings = document
+        .root_element()
+        .descendants()
+        .filter(|node| node.value().as_text().is_some_and(|text| text.trim() == "関連銘柄"));
+    for heading in headings {
+        // The section is the closest ancestor of the heading that holds quote links.
+        let section = heading
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(MAX_LEVELS)
+            .find(|ancestor| ancestor.select(&link_selector).next().is_some());
+        let Some(section) = section else {
+            continue;
+        };
+        let section_links = format!("{} {}", selector_for_element(document, section), LINKS);
+        for href in scrape_all_attrs(document, &section_links, "href", usize::MAX) {
+            if let Some(code) = code_from_quote_href(&href) {
+                if code != own_code && !codes.contains(&code) {
+                    codes.push(code);
+                }
+            }
+        }
+    }
+    codes
+}
+
+/// A tick time as the 歩み値 table prints it: "H:MM", "HH:MM" or "HH:MM:SS".
+fn is_tick_time(text: &str) -> bool {
+    let parts: Vec<&str> = text.split(':').collect();
+    (2..=3).contains(&parts.len())
+        && parts.iter().enumerate().all(|(i, part)| {
+            let len_ok = if i == 0 { (1..=2).contains(&part.len()) } else { part.len() == 2 };
+            len_ok && part.bytes().all(|b| b.is_ascii_digit())
+        })
+}
+
+/// Reads the first `max_rows` prints of the 歩み値 (time and sales) table as `(time, price)`, in the
+/// order listed (newest first). Rows whose first cell isn't a time, such as the header or a "もっと見る"
+/// link, are skipped; the price is the first numeric cell after the time, ahead of the volume.
+/// Empty when the page doesn't show the table.
+pub fn find_recent_ticks(document: &Html, max_rows: usize) -> Vec<(String, String)> {
+    const MAX_LEVELS: usize = 4;
+    let (Ok(table_selector), Ok(row_selector), Ok(cell_selector)) =
+        (Selector::parse("table"), Selector::parse("tr"), Selector::parse("td"))
+    else {
+        return Vec::new();
+    };
+    let heading = document
+        .root_element()
+        .descendants()
+        .find(|node| node.value().as_text().is_some_and(|text| text.trim() == "歩み値"));
+    let Some(table) = heading.and_then(|heading| {
+        heading
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(MAX_LEVELS)
+            .find_map(|ancestor| ancestor.select(&table_selector).next())
+    }) else {
+        return Vec::new();
+    };
+    table
+        .select(&row_selector)
+        .filter_map(|row| {
+            let mut cells = row.select(&cell_selector).map(|cell| cell.text().collect::<String>().trim().to_string());
+            let time = cells.next().filter(|time| is_tick_time(time))?;
+            let price = cells.find(|price| is_price_text(price, ""))?;
+            Some((time, price))
+        })
+        .take(max_rows)
+        .collect()
+}
+
+/// Finds the PTS (after-hours proprietary trading system) quote as `(price, change)`.
+///
+/// The section is the closest ancestor of the "PTS" label that shows more than the label; the price is
+/// its first unsigned number and the change the first signed one after it, skipping the percent. Either
+/// side is `None` when the page has no PTS section or the section shows no quote yet (e.g. "---" during
+/// the session).
+pub fn find_pts_quote(document: &Html) -> (Option<String>, Option<String>) {
+    const MAX_LEVELS: usize = 4;
+    let texts = |element: ElementRef| -> Vec<String> {
+        element
+            .text()
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let is_change = |text: &str| {
+        let text = normalize_minus(text);
+        text.starts_with(['+', '-']) && !text.contains('%') && text[1..].replace(',', "").parse::<f64>().is_ok()
+    };
+    let labels = document
+        .root_element()
+        .descendants()
+        .filter(|node| node.value().as_text().is_some_and(|text| text.trim() == "PTS"));
+    for label in labels {
+        let section = label
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(MAX_LEVELS)
+            .map(texts)
+            .find(|texts| texts.len() > 1);
+        if let Some(texts) = section {
+            let Some(position) = texts.iter().position(|text| is_price_text(text, "")) else {
+                return (None, None);
+            };
+            let change = texts[position + 1..].iter().find(|text| is_change(text)).cloned();
+            return (Some(texts[position].clone()), change);
+        }
+    }
+    (None, None)
+}
+
+/// Finds the high and low of the range the chart shows, as `(high, low)`.
+///
+/// Inside elements whose class mentions `Chart`, a `data-high` / `data-low` (or `data-range-high` /
+/// `data-range-low`) attribute wins; otherwise an annotation such as "高値 2,891.0" or a "安値" label
+/// followed by the value is used. Being scoped to the chart, the 高値 / 年初来高値 rows of the reference
+/// panel aren't picked up. Either side is `None` when the chart doesn't show it, which is common.
+pub fn find_chart_range(document: &Html) -> (Option<String>, Option<String>) {
+    let Ok(chart_selector) = Selector::parse("[class*='Chart']") else {
+        return (None, None);
+    };
+    let charts: Vec<ElementRef> = document.select(&chart_selector).collect();
+    let attribute = |names: [&str; 2]| {
+        charts.iter().find_map(|chart| {
+            std::iter::once(*chart)
+                .chain(chart.descendants().filter_map(ElementRef::wrap))
+                .find_map(|element| names.iter().find_map(|name| element.value().attr(name)))
+                .map(|value| value.trim().to_string())
+                .filter(|value| is_price_text(value, ""))
+        })
+    };
+    let annotation = |label: &str| charts.iter().find_map(|chart| find_chart_annotation(*chart, label));
+    let high = attribute(["data-range-high", "data-high"]).or_else(|| annotation("高値"));
+    let low = attribute(["data-range-low", "data-low"]).or_else(|| annotation("安値"));
+    (high, low)
+}
+
+/// The value of a "高値 2,891.0"-style annotation in `chart`, or of a bare label followed by the value.
+fn find_chart_annotation(chart: ElementRef, label: &str) -> Option<String> {
+    let texts: Vec<&str> = chart.text().map(str::trim).filter(|text| !text.is_empty()).collect();
+    texts.iter().enumerate().find_map(|(i, text)| {
+        let rest = text.strip_prefix(label)?.trim_start_matches([':', '：', ' ', '\u{3000}']);
+        let value = if rest.is_empty() { *texts.get(i + 1)? } else { rest };
+        is_price_text(value, "").then(|| value.to_string())
+    })
+}
+
 // --- Index-specific finders ---
 pub async fn find_dji_update_time_selector(
     document: &Html,
@@ -239,14 +1067,14 @@ pub async fn find_dji_update_time_selector(
     // Find the footer element which seems to have a stable class name, based on user's provided selector.
     let footer_selector_str = "._CommonPriceBoard__mainFooter_1g7gt_48";
     let footer_selector = Selector::parse(footer_selector_str)
-        .map_err(|e| ScraperError(format!("Failed to parse index footer selector: {:?}", e)))?;
+        .map_err(|e| ScraperError::SelectorParse(format!("Failed to parse index footer selector: {:?}", e)))?;
 
     if let Some(footer_element) = document.select(&footer_selector).next() {
         // Within that footer, find the <time> element.
         let time_selector = Selector::parse("time")
-            .map_err(|e| ScraperError(format!("Failed to parse time tag selector: {:?}", e)))?;
+            .map_err(|e| ScraperError::SelectorParse(format!("Failed to parse time tag selector: {:?}", e)))?;
         if let Some(time_element) = footer_element.select(&time_selector).next() {
-            return Ok(Some(build_selector(&time_element)));
+            return Ok(Some(selector_for_element(document, time_element)));
         }
     }
 
@@ -259,14 +1087,14 @@ pub async fn find_nikkei_update_time_selector(
     // Find the footer element which seems to have a stable class name, based on user's provided selector.
     let footer_selector_str = ".PriceBoard__mainFooter__16pO";
     let footer_selector = Selector::parse(footer_selector_str)
-        .map_err(|e| ScraperError(format!("Failed to parse Nikkei footer selector: {:?}", e)))?;
+        .map_err(|e| ScraperError::SelectorParse(format!("Failed to parse Nikkei footer selector: {:?}", e)))?;
 
     if let Some(footer_element) = document.select(&footer_selector).next() {
         // Within that footer, find the <time> element.
         let time_selector = Selector::parse("time")
-            .map_err(|e| ScraperError(format!("Failed to parse time tag selector: {:?}", e)))?;
+            .map_err(|e| ScraperError::SelectorParse(format!("Failed to parse time tag selector: {:?}", e)))?;
         if let Some(time_element) = footer_element.select(&time_selector).next() {
-            return Ok(Some(build_selector(&time_element)));
+            return Ok(Some(selector_for_element(document, time_element)));
         }
     }
 
@@ -275,19 +1103,40 @@ pub async fn find_nikkei_update_time_selector(
 
 // --- FX-specific finders (using "Bid", "Change" anchors) ---
 
+/// Finds the Bid rate. Bid and Ask usually sit in look-alike rows of one container, so the search
+/// never crosses into the Ask block: the rate is taken from the widest ancestor of the "Bid" label that
+/// doesn't also hold "Ask", or else it is the first number after the label in document order.
+/// The returned selector is pinned to that element (see `unique_selector_for`), since a plain class
+/// selector would match whichever row comes first.
 pub async fn find_fx_price_selector(
     document: &Html,
 ) -> Result<Option<String>, Box<dyn Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, "Bid") {
-        let span_selector = Selector::parse("span").map_err(|e| ScraperError(format!("{:?}", e)))?;
-        for span_element in area.select(&span_selector) {
-            let text = span_element.text().collect::<String>();
-            let trimmed_text = text.trim();
-            let cleaned_text = trimmed_text.replace(",", "");
-
-            if !cleaned_text.is_empty() && cleaned_text.parse::<f64>().is_ok() {
-                return Ok(Some(build_selector(&span_element)));
-            }
+    let span_selector = Selector::parse("span").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+    let is_rate = |span: &ElementRef| {
+        let cleaned_text = split_price_unit(&span.text().collect::<String>()).0.replace(",", "");
+        !cleaned_text.is_empty() && cleaned_text.parse::<f64>().is_ok()
+    };
+    let labels = document
+        .root_element()
+        .descendants()
+        .filter(|node| node.value().as_text().is_some_and(|text| text.trim() == "Bid"));
+    for label in labels {
+        let bid_only_areas = label
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(SEARCH_AREA_LEVELS)
+            .take_while(|area| !area.text().any(|text| text.trim() == "Ask"));
+        let in_bid_area = bid_only_areas.last().and_then(|area| area.select(&span_selector).find(is_rate));
+        let following = || {
+            document
+                .root_element()
+                .descendants()
+                .skip_while(|node| node.id() != label.id())
+                .filter_map(ElementRef::wrap)
+                .find(|element| element.value().name() == "span" && is_rate(element))
+        };
+        if let Some(rate) = in_bid_area.or_else(following) {
+            return Ok(unique_selector_for(document, rate));
         }
     }
     Ok(None)
@@ -296,16 +1145,16 @@ pub async fn find_fx_price_selector(
 pub async fn find_fx_change_selector(
     document: &Html,
 ) -> Result<Option<String>, Box<dyn Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, "Change") {
-        let span_selector = Selector::parse("span").map_err(|e| ScraperError(format!("{:?}", e)))?;
+    for area in find_search_areas_around_anchor(document, "Change") {
+        let span_selector = Selector::parse("span").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
         for span_element in area.select(&span_selector) {
             let text = span_element.text().collect::<String>();
-            let trimmed = text.trim();
+            let trimmed = normalize_minus(text.trim());
 
             if (trimmed.starts_with('+') || trimmed.starts_with('-')) && !trimmed.contains('%') && trimmed.len() > 1 {
                 let after_sign = &trimmed[1..].replace(",", "");
                 if after_sign.parse::<f64>().is_ok() {
-                    return Ok(Some(build_selector(&span_element)));
+                    return Ok(Some(selector_for_element(document, span_element)));
                 }
             }
         }
@@ -316,16 +1165,437 @@ pub async fn find_fx_change_selector(
 pub async fn find_fx_update_time_selector(
     document: &Html,
 ) -> Result<Option<String>, Box<dyn Error>> {
-    if let Some(area) = find_search_area_around_anchor(document, "Bid") {
-        let span_selector = Selector::parse("span").map_err(|e| ScraperError(format!("{:?}", e)))?;
+    for area in find_search_areas_around_anchor(document, "Bid") {
+        let span_selector = Selector::parse("span").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
         for span_element in area.select(&span_selector) {
             let text = span_element.text().collect::<String>();
             let trimmed = text.trim();
 
             if trimmed.contains(':') && trimmed.contains('(') && trimmed.contains(')') && trimmed.len() < 20 {
-                return Ok(Some(build_selector(&span_element)));
+                return Ok(Some(selector_for_element(document, span_element)));
             }
         }
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_structured_price() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7203_jsonld.html"));
+        assert_eq!(find_structured_price(&document, "7203").as_deref(), Some("2845.5"));
+
+        assert_eq!(find_structured_price(&document, "6758"), None);
+
+        // Only objects that name the code count.
+        let document = Html::parse_document(
+            r#"<html><head><script type="application/ld+json">{"offers": {"price": "1,024"}}</script>
+            <script type="application/ld+json">{"@type": "FinancialProduct", "tickerSymbol": "8058", "offers": {"price": "2,900"}}</script>
+            <script type="application/ld+json">not json</script>
+            <script type="application/ld+json">{"@type": "WebPage", "url": "https://finance.yahoo.co.jp/quote/6758.T", "mainEntity": {"price": "3,456"}}</script>
+            </head><body></body></html>"#,
+        );
+        assert_eq!(find_structured_price(&document, "6758").as_deref(), Some("3,456"));
+        assert_eq!(find_structured_price(&document, "8058").as_deref(), Some("2,900"));
+        assert_eq!(find_structured_price(&document, "7203"), None);
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        assert_eq!(find_structured_price(&document, "6758"), None);
+    }
+
+    #[test]
+    fn test_find_meta_price() {
+        let document = Html::parse_document(
+            r#"<html><head><meta itemprop="price" content="n/a"><meta property="og:price:amount" content=" 3,456 "></head></html>"#,
+        );
+        assert_eq!(find_meta_price(&document, "6758").as_deref(), Some("3,456"));
+        let document = Html::parse_document(r#"<html><head><meta property="og:price:amount" content="6758"></head></html>"#);
+        assert_eq!(find_meta_price(&document, "6758"), None);
+    }
+
+    #[test]
+    fn test_names_code() {
+        assert!(names_code("7203", "7203"));
+        assert!(names_code("TYO:7203", "7203"));
+        assert!(names_code("7203.T", "7203"));
+        assert!(names_code("https://finance.yahoo.co.jp/quote/7203.T", "7203"));
+        assert!(!names_code("72030", "7203"));
+        assert!(!names_code("https://finance.yahoo.co.jp/quote/6758.T", "7203"));
+    }
+
+    #[test]
+    fn test_find_prominent_number_bounds_its_work() {
+        let document = Html::parse_fragment(
+            r#"<div id="area"><span class="a">101</span><span class="a b">202</span><span class="a b c">303</span></div>"#,
+        );
+        let area = document.select(&Selector::parse("#area").unwrap()).next().unwrap();
+        let text = |element: Option<ElementRef>| element.map(|e| e.text().collect::<String>());
+        assert_eq!(text(find_prominent_number(area, "", DEFAULT_MAX_PRICE_CANDIDATES)).as_deref(), Some("303"));
+        assert_eq!(text(find_prominent_number(area, "", 2)).as_deref(), Some("202"));
+        assert_eq!(text(find_prominent_number(area, "303", 10)).as_deref(), Some("202"));
+        assert_eq!(find_prominent_number(area, "", 0), None);
+
+        // A number in the price container wins over more prominent ones after it.
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let price = find_prominent_number(document.root_element(), "6758", DEFAULT_MAX_PRICE_CANDIDATES).unwrap();
+        assert_eq!(price.text().collect::<String>().trim(), "3,456");
+    }
+
+    #[test]
+    fn test_find_recent_ticks() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7203_ticks.html"));
+        let ticks = find_recent_ticks(&document, 10);
+        let pair = |time: &str, price: &str| (time.to_string(), price.to_string());
+        assert_eq!(
+            ticks,
+            [
+                pair("15:00", "2,851"),
+                pair("14:59:58", "2,850"),
+                pair("14:59:41", "2,851"),
+                pair("14:59:12", "2,849.5"),
+                pair("14:58:55", "2,849"),
+            ]
+        );
+        assert_eq!(find_recent_ticks(&document, 2), ticks[..2]);
+
+        let document = Html::parse_document(include_str!("fixtures/stock_7203.html"));
+        assert!(find_recent_ticks(&document, 10).is_empty());
+    }
+
+    #[test]
+    fn test_find_related_codes() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_lists.html"));
+        assert_eq!(find_related_codes(&document, "8058"), ["8031", "8001", "8053", "8002"]);
+
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        assert!(find_related_codes(&document, "6758").is_empty());
+    }
+
+    #[test]
+    fn test_scrape_all_fields() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_lists.html"));
+        assert_eq!(
+            scrape_all_fields(&document, "li[class*='StockNews__item'] a", 10),
+            ["三菱商事、通期純利益予想を上方修正", "商社株が軒並み高、資源価格の上昇で", "自社株買いの取得枠を拡大"]
+        );
+        assert_eq!(scrape_all_fields(&document, "li[class*='RelatedStocks__item']", 2), ["三井物産(株)", "伊藤忠商事(株)"]);
+        assert!(scrape_all_fields(&document, "li.missing", 10).is_empty());
+        assert!(scrape_all_fields(&document, "li[", 10).is_empty());
+    }
+
+    #[test]
+    fn test_scrape_all_attrs() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_lists.html"));
+        assert_eq!(
+            scrape_all_attrs(&document, "li[class*='RelatedStocks__item'] a", "href", 3),
+            ["/quote/8031.T", "/quote/8001.T", "/quote/8053.T"]
+        );
+        assert_eq!(scrape_all_attrs(&document, "li[class*='StockNews__item'] a", "href", 10).len(), 5);
+        assert!(scrape_all_attrs(&document, "li[class*='StockNews__item']", "href", 10).is_empty());
+    }
+
+    #[test]
+    fn test_split_price_unit() {
+        assert_eq!(split_price_unit("149.52円"), ("149.52", Some("円")));
+        assert_eq!(split_price_unit(" 1,234 pt "), ("1,234", Some("pt")));
+        assert_eq!(split_price_unit("38.10ドル"), ("38.10", Some("ドル")));
+        assert_eq!(split_price_unit("3,456"), ("3,456", None));
+        assert_eq!(split_price_unit("(-1.17%)"), ("(-1.17%)", None));
+
+        assert!(is_price_text("149.52円", "USDJPY=FX"));
+        assert!(is_price_text("1,234 pt", "^DJI"));
+        assert!(!is_price_text("+12円", "6758"));
+        assert!(!is_price_text("円", "6758"));
+    }
+
+    #[test]
+    fn test_normalize_minus() {
+        assert_eq!(normalize_minus("−41"), "-41");
+        assert_eq!(normalize_minus("－1.17%"), "-1.17%");
+        assert_eq!(normalize_minus("+12.5"), "+12.5");
+    }
+
+    #[test]
+    fn test_find_chart_range() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_chart.html"));
+        assert_eq!(
+            find_chart_range(&document),
+            (Some("2,891.0".to_string()), Some("2,846.5".to_string()))
+        );
+
+        let document = Html::parse_document(
+            r#"<div class="_IndexChart_x1"><div data-range-high="40,210.5" data-range-low="39,870.2"></div>
+               <span>高値</span><span>40,000.0</span></div>"#,
+        );
+        assert_eq!(
+            find_chart_range(&document),
+            (Some("40,210.5".to_string()), Some("39,870.2".to_string()))
+        );
+
+        let document = Html::parse_document(
+            r#"<div class="Chart"><span>高値</span><span>40,000.0</span></div><dl><dt>安値</dt><dd>39,000</dd></dl>"#,
+        );
+        assert_eq!(find_chart_range(&document), (Some("40,000.0".to_string()), None));
+
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_session_open.html"));
+        assert_eq!(find_chart_range(&document), (None, None));
+    }
+
+    #[test]
+    fn test_find_price_like_number() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758_broken_layout.html"));
+        assert_eq!(find_price_like_number(&document, "6758").as_deref(), Some("3,456"));
+
+        // Without emphasis or classes, the largest number wins; the code, signed changes and
+        // percentages are never taken.
+        let document = Html::parse_document(
+            r#"<p><span>6758</span><span>+12,000</span><span>12.5%</span><span>812</span><span>3,456</span></p>"#,
+        );
+        assert_eq!(find_price_like_number(&document, "6758").as_deref(), Some("3,456"));
+
+        let document = Html::parse_document(r#"<p><span>3,456</span><span style="font-weight: 700">812</span></p>"#);
+        assert_eq!(find_price_like_number(&document, "6758").as_deref(), Some("812"));
+
+        let document = Html::parse_document("<p>株価は表示できません</p>");
+        assert_eq!(find_price_like_number(&document, "6758"), None);
+    }
+
+    #[test]
+    fn test_find_pts_quote() {
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_pts.html"));
+        assert_eq!(find_pts_quote(&document), (Some("2,880.0".to_string()), Some("+5.5".to_string())));
+
+        // During the session the section is shown without a quote.
+        let document = Html::parse_document(
+            r#"<section><h3>PTS</h3><span class="StyledNumber__value">---</span><span>(---)</span></section>
+               <section><h3>参考指標</h3><span>3,497</span></section>"#,
+        );
+        assert_eq!(find_pts_quote(&document), (None, None));
+
+        let document = Html::parse_document(include_str!("fixtures/stock_8058_session_open.html"));
+        assert_eq!(find_pts_quote(&document), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_find_fx_price_skips_the_ask_block() {
+        let document = Html::parse_document(include_str!("fixtures/fx_usdjpy.html"));
+        let selector = find_fx_price_selector(&document).await.unwrap();
+        assert_eq!(scrape_field(&document, &selector, "price"), "149.8520");
+
+        // Ask precedes Bid in the same container, with identical markup.
+        let document = Html::parse_document(include_str!("fixtures/fx_eurjpy_ask_first.html"));
+        let selector = find_fx_price_selector(&document).await.unwrap();
+        assert_eq!(scrape_field(&document, &selector, "price"), "161.2340");
+
+        // A unit inside the rate's span.
+        let document = Html::parse_document(
+            r#"<dl><dt><span>Bid</span></dt><dd><span class="rate">149.52円</span></dd></dl><dl><dt><span>Ask</span></dt><dd><span class="ask">149.56円</span></dd></dl>"#,
+        );
+        let selector = find_fx_price_selector(&document).await.unwrap();
+        assert_eq!(scrape_field(&document, &selector, "price"), "149.52円");
+
+        // No per-rate wrapper: the number right after the label wins.
+        let document = Html::parse_document(
+            r#"<div class="rates"><span>Ask</span><span class="rate">161.2410</span><span>Bid</span><span class="rate">161.2340</span></div>"#,
+        );
+        let selector = find_fx_price_selector(&document).await.unwrap();
+        assert_eq!(scrape_field(&document, &selector, "price"), "161.2340");
+    }
+
+    #[tokio::test]
+    async fn test_find_name_prefers_index_name_in_h1() {
+        let document = Html::parse_document(include_str!("fixtures/index_dji.html"));
+        let (selector, name) = find_name_dynamically(&document).await.unwrap();
+
+        assert_eq!(name, "NYダウ");
+        assert_eq!(selector.as_deref(), Some("h1._BasePriceBoard__name_1tkwp_66"));
+    }
+
+    #[tokio::test]
+    async fn test_find_name_in_h1_without_h2() {
+        let document = Html::parse_document(
+            r#"<html><body><h1 class="name">東証グロース市場250指数の指数情報・推移</h1><span>前日比</span></body></html>"#,
+        );
+        let (selector, name) = find_name_dynamically(&document).await.unwrap();
+        assert_eq!(name, "東証グロース市場250指数");
+        assert_eq!(selector.as_deref(), Some("h1.name"));
+
+        // A plain h1 is only used when there is no h2.
+        let document = Html::parse_document(r#"<html><body><h1>TOPIX</h1></body></html>"#);
+        assert_eq!(find_name_dynamically(&document).await.unwrap().1, "TOPIX");
+        let document = Html::parse_document(r#"<html><body><h1>TOPIX</h1><h2>東証株価指数</h2></body></html>"#);
+        assert_eq!(find_name_dynamically(&document).await.unwrap().1, "東証株価指数");
+    }
+
+    #[test]
+    fn test_find_english_name() {
+        let document = Html::parse_document(include_str!("fixtures/stock_7203.html"));
+        assert_eq!(
+            find_english_name(&document, "トヨタ自動車(株)").as_deref(),
+            Some("TOYOTA MOTOR CORPORATION")
+        );
+
+        let document = Html::parse_document(include_str!("fixtures/etf_1306.html"));
+        assert_eq!(find_english_name(&document, "ＮＥＸＴ　ＦＵＮＤＳ　ＴＯＰＩＸ連動型上場投信"), None);
+    }
+
+    #[test]
+    fn test_is_code_pattern() {
+        assert!(is_code_pattern("9432"));
+        assert!(is_code_pattern("130A"));
+        assert!(!is_code_pattern("130a"));
+        assert!(!is_code_pattern("A130"));
+        assert!(!is_code_pattern("943"));
+        assert!(!is_code_pattern("94321"));
+        assert!(is_code_pattern_of_len("25935", &(4..=5)));
+        assert!(is_code_pattern_of_len("130A", &(4..=5)));
+        assert!(!is_code_pattern_of_len("259A5", &(4..=5)));
+        assert!(!is_code_pattern_of_len("", &(0..=5)));
+    }
+
+    #[tokio::test]
+    async fn test_code_selector_accepts_alphanumeric_code() {
+        let html = r#"<div><header>
+            <h2 class="PriceBoard__name">(株)ジェイ・イー・ティ</h2>
+            <span class="PriceBoard__code">130A</span>
+        </header></div>"#;
+        let document = Html::parse_document(html);
+
+        let selector = find_text_pattern_selector_near_anchor(&document, "(株)ジェイ・イー・ティ", "code", &DEFAULT_CODE_LENGTHS)
+            .await
+            .unwrap();
+        assert_eq!(scrape_field(&document, &selector, "code"), "130A");
+    }
+
+    #[tokio::test]
+    async fn test_code_selector_five_digit_code() {
+        let document = Html::parse_document(include_str!("fixtures/stock_25935_five_digit.html"));
+
+        let selector = find_text_pattern_selector_near_anchor(&document, "(株)ネクストリスト", "code", &DEFAULT_CODE_LENGTHS)
+            .await
+            .unwrap();
+        assert_eq!(selector, None);
+
+        let selector = find_text_pattern_selector_near_anchor(&document, "(株)ネクストリスト", "code", &(4..=5))
+            .await
+            .unwrap();
+        assert_eq!(scrape_field(&document, &selector, "code"), "25935");
+    }
+
+    #[tokio::test]
+    async fn test_change_selector_recognizes_unicode_minus() {
+        let html = r#"<div><dl>
+            <dt>前日比</dt>
+            <dd><span class="PriceChangeLabel__primary"><span class="StyledNumber__value">−1,234</span></span>
+            <span class="PriceChangeLabel__secondary"><span class="StyledNumber__value">(−1.17%)</span></span></dd>
+        </dl></div>"#;
+        let document = Html::parse_document(html);
+
+        let selector = find_stock_change_selector(&document, "前日比", SearchFallback::Off).await.unwrap();
+        let value = scrape_field(&document, &selector, "change");
+        assert_eq!(normalize_minus(&value), "-1,234");
+    }
+
+    #[tokio::test]
+    async fn test_change_selectors_try_every_anchor_occurrence() {
+        // The first "前日比" is a chart legend whose area has no change; the second is the price board's.
+        let document = Html::parse_document(include_str!("fixtures/stock_4063_two_zenjitsuhi.html"));
+        assert_eq!(find_search_areas_around_anchor(&document, "前日比").len(), 2);
+
+        let change = find_stock_change_selector(&document, "前日比", SearchFallback::Off).await.unwrap();
+        assert_eq!(scrape_field(&document, &change, "change"), "+75");
+        let percent = find_stock_change_percent_selector(&document, "前日比", SearchFallback::Off).await.unwrap();
+        assert_eq!(scrape_field(&document, &percent, "change_percent"), "(+1.22%)");
+    }
+
+    #[tokio::test]
+    async fn test_price_selector_prefers_class_hint() {
+        let document = Html::parse_document(include_str!("fixtures/stock_6758.html"));
+        let selector = find_stock_price_selector(&document, "ソニーグループ(株)", "6758").await.unwrap();
+        assert_eq!(selector.as_deref(), Some(PRICE_CLASS_HINTS[0]));
+        assert_eq!(scrape_field(&document, &selector, "price"), "3,456");
+
+        let document = Html::parse_document(include_str!("fixtures/index_dji.html"));
+        let selector = find_stock_price_selector(&document, "NYダウ", "^DJI").await.unwrap();
+        assert_eq!(scrape_field(&document, &selector, "price"), "40,123.45");
+    }
+
+    #[tokio::test]
+    async fn test_price_selector_falls_back_to_dom_walk_without_hint_classes() {
+        let html = r#"<div class="board">
+            <h2 class="name">テスト(株)</h2>
+            <div class="quote"><span class="value">1,234</span></div>
+            <dl><dt>前日比</dt><dd><span class="delta">+5</span></dd></dl>
+        </div>"#;
+        let document = Html::parse_document(html);
+        assert_eq!(find_price_by_class_hint(&document, "9999"), None);
+
+        let selector = find_stock_price_selector(&document, "テスト(株)", "9999").await.unwrap();
+        assert_eq!(selector.as_deref(), Some("span.value"));
+    }
+
+    #[tokio::test]
+    async fn test_price_selector_picks_candidate_nearest_anchor() {
+        // The year-range decoy comes first in document order but sits deeper than the current price.
+        let document = Html::parse_document(include_str!("fixtures/stock_decoy_price.html"));
+        let selector = find_stock_price_selector(&document, "ソニーグループ(株)", "6758").await.unwrap();
+        assert_eq!(selector.as_deref(), Some("span.Number__current__6PoA"));
+        assert_eq!(scrape_field(&document, &selector, "price"), "3,456");
+    }
+
+    #[tokio::test]
+    async fn test_price_selector_falls_back_to_alternate_anchor() {
+        // No "前日比" (only "前日比率") and no hint classes; "現在値" labels the price.
+        let document = Html::parse_document(include_str!("fixtures/stock_7974_genzaine.html"));
+        let anchors = vec![
+            PriceAnchor::Label("前日比".to_string()),
+            PriceAnchor::Label("現在値".to_string()),
+        ];
+        let selector = find_stock_price_selector_with_anchors(&document, "任天堂(株)", "7974", &anchors)
+            .await
+            .unwrap();
+        assert_eq!(scrape_field(&document, &selector, "price"), "8,123");
+
+        let only_missing_anchor = vec![PriceAnchor::Label("前日比".to_string())];
+        let selector = find_stock_price_selector_with_anchors(&document, "任天堂(株)", "7974", &only_missing_anchor)
+            .await
+            .unwrap();
+        assert_eq!(selector, None);
+    }
+
+    #[test]
+    fn test_find_index_price_prefers_largest_number() {
+        let document = Html::parse_document(include_str!("fixtures/index_dji_numbers.html"));
+        let selector = find_index_price_selector(&document, "NYダウ", "^DJI");
+        assert_eq!(
+            selector.as_deref(),
+            Some("span._BasePriceBoard__level_8rt2c_49 > span._Number_4kq1p_1")
+        );
+        assert_eq!(scrape_field(&document, &selector, "price"), "40,123.45");
+    }
+
+    #[test]
+    fn test_find_price_direction_ignores_text_sign() {
+        // The class says down even though the text has no sign.
+        let html = r#"<div><h2>テスト(株)</h2><div class="_PriceChangeLabel_hse06_1 _PriceChangeLabel--down_hse06_1">
+            <span class="StyledNumber__item--secondary__RTJc">12</span></div></div>"#;
+        let document = Html::parse_document(html);
+        assert_eq!(find_price_direction(&document, "テスト(株)"), Some(Direction::Down));
+    }
+
+    #[test]
+    fn test_find_trading_state_special_quote() {
+        let html = r#"<div><header><h2>テスト(株)</h2></header>
+            <div><span class="price">2,000</span><span>特別売気配</span></div></div>"#;
+        let document = Html::parse_document(html);
+        assert_eq!(find_trading_state(&document, "テスト(株)"), TradingState::SpecialQuote);
+
+        let html = r#"<div><header><h2>テスト(株)</h2></header>
+            <div><span class="price">2,000</span><span>ストップ安</span></div></div>"#;
+        let document = Html::parse_document(html);
+        assert_eq!(find_trading_state(&document, "テスト(株)"), TradingState::LimitDown);
+    }
+}
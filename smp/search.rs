@@ -0,0 +1,156 @@
+use crate::fetcher::{Fetcher, ReqwestFetcher};
+use crate::models::ScraperError;
+use crate::scraper_logic::is_code_pattern;
+use scraper::{Html, Selector};
+
+const SEARCH_BASE: &str = "https://finance.yahoo.co.jp/search/";
+
+fn build_search_url(name: &str) -> String {
+    format!("{}?query={}", SEARCH_BASE, urlencoding::encode(name))
+}
+
+/// Extracts the stock code from a quote link such as `/quote/6758.T` or `https://finance.yahoo.co.jp/quote/6758.T?ref=x`.
+pub(crate) fn code_from_quote_href(href: &str) -> Option<String> {
+    let (_, rest) = href.split_once("/quote/")?;
+    let symbol = rest.split(['?', '#', '/']).next()?;
+    let code = symbol.strip_suffix(".T").unwrap_or(symbol);
+    if is_code_pattern(code) {
+        Some(code.to_string())
+    } else {
+        None
+    }
+}
+
+/// Parses the search result page into (code, name) pairs in the order they are listed.
+/// Each code appears once; its name is the first link text that isn't the code itself.
+fn parse_search_results(body: &str) -> Result<Vec<(String, String)>, ScraperError> {
+    let document = Html::parse_document(body);
+    let link_selector = Selector::parse("a[href]").map_err(|e| ScraperError::SelectorParse(format!("{:?}", e)))?;
+
+    let mut results: Vec<(String, String)> = Vec::new();
+    for link in document.select(&link_selector) {
+        let Some(code) = link.value().attr("href").and_then(code_from_quote_href) else {
+            continue;
+        };
+        let text = link.text().collect::<String>().trim().to_string();
+        let name = if text == code { String::new() } else { text };
+        match results.iter_mut().find(|(c, _)| *c == code) {
+            Some((_, existing)) if existing.is_empty() => *existing = name,
+            Some(_) => {}
+            None => results.push((code, name)),
+        }
+    }
+    Ok(results)
+}
+
+/// Name used for exact matching: the `(株)` marker and whitespace are ignored.
+fn normalize_name(name: &str) -> String {
+    name.replace("(株)", "")
+        .replace("（株）", "")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// Picks the code for `name` from the search results.
+///
+/// A result whose name matches exactly wins; otherwise the results must contain exactly one entry.
+fn pick_code(name: &str, results: &[(String, String)]) -> Result<String, ScraperError> {
+    let wanted = normalize_name(name);
+    if let Some((code, _)) = results.iter().find(|(_, n)| normalize_name(n) == wanted) {
+        return Ok(code.clone());
+    }
+    match results {
+        [] => Err(ScraperError::NoSearchResult(name.to_string())),
+        [(code, _)] => Ok(code.clone()),
+        _ => Err(ScraperError::AmbiguousName(
+            name.to_string(),
+            results.iter().map(|(code, n)| format!("{} {}", code, n)).collect(),
+        )),
+    }
+}
+
+/// Resolves a company name to its stock code using the given fetcher.
+pub async fn resolve_code_with(fetcher: &impl Fetcher, name: &str) -> Result<String, ScraperError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ScraperError::NoSearchResult(name.to_string()));
+    }
+    let url = build_search_url(name);
+    let response = fetcher.get(&url).await?;
+
+    // A unique hit may be answered with a redirect straight to the quote page.
+    if response.is_redirect() {
+        if let Some(code) = response.location.as_deref().and_then(code_from_quote_href) {
+            return Ok(code);
+        }
+    }
+    if !response.is_success() {
+        return Err(ScraperError::from_status(response.status, &url));
+    }
+    let results = parse_search_results(&response.body)?;
+    pick_code(name, &results)
+}
+
+/// Resolves a company name (e.g. "ソニーグループ") to its stock code via Yahoo's search.
+pub async fn resolve_code(name: &str) -> Result<String, ScraperError> {
+    resolve_code_with(&ReqwestFetcher::new(), name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::mock::MockFetcher;
+    use crate::fetcher::Response;
+
+    #[test]
+    fn test_code_from_quote_href() {
+        assert_eq!(code_from_quote_href("/quote/6758.T").as_deref(), Some("6758"));
+        assert_eq!(code_from_quote_href("https://finance.yahoo.co.jp/quote/130A.T?ref=x").as_deref(), Some("130A"));
+        assert_eq!(code_from_quote_href("/quote/998407.O"), None);
+        assert_eq!(code_from_quote_href("/news/6758"), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_code_exact_match() {
+        let fetcher = MockFetcher::new().with_page(
+            &build_search_url("ソニーグループ"),
+            200,
+            include_str!("fixtures/search_sony.html"),
+        );
+        assert_eq!(resolve_code_with(&fetcher, "ソニーグループ").await.unwrap(), "6758");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_code_ambiguous_and_missing() {
+        let fetcher = MockFetcher::new()
+            .with_page(&build_search_url("ソニー"), 200, include_str!("fixtures/search_sony.html"))
+            .with_page(&build_search_url("存在しない会社"), 200, "<html><body><p>該当なし</p></body></html>");
+
+        match resolve_code_with(&fetcher, "ソニー").await {
+            Err(ScraperError::AmbiguousName(query, candidates)) => {
+                assert_eq!(query, "ソニー");
+                assert_eq!(candidates, vec!["6758 ソニーグループ(株)", "8729 ソニーフィナンシャルグループ(株)"]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            resolve_code_with(&fetcher, "存在しない会社").await,
+            Err(ScraperError::NoSearchResult(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_code_follows_quote_redirect() {
+        let fetcher = MockFetcher::new();
+        fetcher.push(
+            &build_search_url("トヨタ自動車"),
+            Ok(Response {
+                status: 302,
+                location: Some("https://finance.yahoo.co.jp/quote/7203.T".to_string()),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(resolve_code_with(&fetcher, "トヨタ自動車").await.unwrap(), "7203");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::models::{Confidence, Market};
+use crate::CodeType;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Builds the selector for an element from its tag and classes, e.g. `span.a.b`.
+/// This is the one implementation both the smp scraper and the dynamic scraper in `src` use.
+///
+/// The classes are sorted (and deduplicated), so the same element always gives the same string
+/// whatever order the page lists them in; the selector matches the same elements either way.
+pub fn build_selector(element: &ElementRef) -> String {
+    let tag_name = element.value().name();
+    let mut classes = element.value().attr("class").unwrap_or_default().split_whitespace().collect::<Vec<_>>();
+    classes.sort_unstable();
+    classes.dedup();
+    if !classes.is_empty() {
+        format!("{}.{}", tag_name, classes.join("."))
+    } else {
+        tag_name.to_string()
+    }
+}
+
+/// Where the selector that produced a field's value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectorSource {
+    /// Supplied by the user (`ScraperConfig::selector_overrides`).
+    User,
+    /// Discovered on an earlier page of the same market and kept in a `SelectorCache`.
+    Cached,
+    /// One of the hardcoded selectors the static scraper uses (see `static_selector`).
+    Static,
+    /// Found by the dynamic finders on this page.
+    Discovered,
+}
+
+/// The candidate selectors for one field.
+///
+/// They are tried in a fixed order: user-supplied → cached discovered → static hardcoded →
+/// freshly discovered. A step is skipped when it has no selector, when the selector doesn't
+/// parse, or when it matches nothing or only whitespace; the first non-empty value wins.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldSelectors<'a> {
+    pub user: Option<&'a str>,
+    pub cached: Option<&'a str>,
+    pub static_selector: Option<&'a str>,
+    pub discovered: Option<&'a str>,
+}
+
+impl SelectorSource {
+    pub fn confidence(self) -> Confidence {
+        match self {
+            SelectorSource::User => Confidence::High,
+            SelectorSource::Cached | SelectorSource::Static => Confidence::Medium,
+            SelectorSource::Discovered => Confidence::Low,
+        }
+    }
+}
+
+impl<'a> FieldSelectors<'a> {
+    /// The selector of the step `source` names, if it has one.
+    pub fn selector(&self, source: SelectorSource) -> Option<&'a str> {
+        self.in_precedence_order()
+            .into_iter()
+            .find_map(|(selector, step)| if step == source { selector } else { None })
+    }
+
+    fn in_precedence_order(&self) -> [(Option<&'a str>, SelectorSource); 4] {
+        [
+            (self.user, SelectorSource::User),
+            (self.cached, SelectorSource::Cached),
+            (self.static_selector, SelectorSource::Static),
+            (self.discovered, SelectorSource::Discovered),
+        ]
+    }
+}
+
+/// Scrapes one field with the first of its selectors that yields a value (see `FieldSelectors`).
+/// Returns the trimmed text together with the step that produced it.
+pub fn scrape_field_layered(document: &Html, selectors: &FieldSelectors) -> Option<(String, SelectorSource)> {
+    selectors
+        .in_precedence_order()
+        .into_iter()
+        .find_map(|(selector, source)| Some((select_text(document, selector?)?, source)))
+}
+
+fn select_text(document: &Html, selector: &str) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    let value = document.select(&selector).next()?.text().collect::<String>();
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Markup of the first element `selector` matches (see `ScraperConfig::debug_elements`).
+pub fn element_html(document: &Html, selector: &str) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    document.select(&selector).next().map(|element| element.html())
+}
+
+/// How many elements `selector` matches; a field is read from the first of them.
+pub fn match_count(document: &Html, selector: &str) -> usize {
+    Selector::parse(selector).map_or(0, |selector| document.select(&selector).count())
+}
+
+/// The hardcoded selector the static scraper uses for `field` on pages of `code_type`, if any.
+/// Only selectors specific enough to not match unrelated numbers on the page are listed.
+pub fn static_selector(code_type: CodeType, field: &str) -> Option<&'static str> {
+    match (code_type, field) {
+        (CodeType::Stock(_), "code") => Some("span.PriceBoard__code__SnMF"),
+        (CodeType::Stock(_), "price") => Some("span.PriceBoard__price__1V0k > span.StyledNumber__value__3rXW"),
+        (CodeType::Stock(_), "change") => Some("span.PriceChangeLabel__primary__Y_ut > span.StyledNumber__value__3rXW"),
+        (CodeType::Dji, "change") => Some("span._PriceChangeLabel__primary_hse06_56 > span._StyledNumber__value_1lush_9"),
+        (CodeType::Dji, "change_percent") => {
+            Some("span._PriceChangeLabel__secondary_hse06_62 > span._StyledNumber__value_1lush_9")
+        }
+        _ => None,
+    }
+}
+
+/// Whether `selector` picks its element by position (`:nth-of-type`), as the finders do when nothing
+/// else tells it from earlier look-alikes. Such a selector fits the page it was found on only.
+pub fn is_positional(selector: &str) -> bool {
+    selector.contains(":nth-of-type(")
+}
+
+/// Selectors discovered on earlier pages, keyed by market and field name,
+/// so later pages of the same layout can skip straight to a known selector.
+#[derive(Debug, Default)]
+pub struct SelectorCache {
+    entries: Mutex<HashMap<(Market, String), String>>,
+}
+
+impl SelectorCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, market: Market, field: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(&(market, field.to_string())).cloned()
+    }
+
+    pub fn insert(&self, market: Market, field: &str, selector: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert((market, field.to_string()), selector.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <span class="user">111</span>
+        <span class="cached">222</span>
+        <span class="static">333</span>
+        <span class="discovered">444</span>
+        <span class="blank">  </span>
+    </body></html>"#;
+
+    #[test]
+    fn test_scrape_field_layered_precedence() {
+        let document = Html::parse_document(PAGE);
+        let mut selectors = FieldSelectors {
+            user: Some("span.user"),
+            cached: Some("span.cached"),
+            static_selector: Some("span.static"),
+            discovered: Some("span.discovered"),
+        };
+        assert_eq!(
+            scrape_field_layered(&document, &selectors),
+            Some(("111".to_string(), SelectorSource::User))
+        );
+
+        // A user selector that matches nothing falls through to the cache.
+        selectors.user = Some("span.missing");
+        assert_eq!(
+            scrape_field_layered(&document, &selectors),
+            Some(("222".to_string(), SelectorSource::Cached))
+        );
+
+        // A cached selector that only matches whitespace falls through to the static one.
+        selectors.cached = Some("span.blank");
+        assert_eq!(
+            scrape_field_layered(&document, &selectors),
+            Some(("333".to_string(), SelectorSource::Static))
+        );
+
+        // An unparsable static selector falls through to the discovered one.
+        selectors.static_selector = Some("span[");
+        assert_eq!(
+            scrape_field_layered(&document, &selectors),
+            Some(("444".to_string(), SelectorSource::Discovered))
+        );
+
+        selectors.discovered = None;
+        assert_eq!(scrape_field_layered(&document, &selectors), None);
+        assert_eq!(selectors.selector(SelectorSource::Cached), Some("span.blank"));
+        assert_eq!(selectors.selector(SelectorSource::Discovered), None);
+    }
+
+    #[test]
+    fn test_element_html() {
+        let document = Html::parse_document(PAGE);
+        assert_eq!(element_html(&document, "span.cached").as_deref(), Some(r#"<span class="cached">222</span>"#));
+        assert_eq!(element_html(&document, "span.missing"), None);
+        assert_eq!(match_count(&document, "span"), 5);
+        assert_eq!(match_count(&document, "span.cached"), 1);
+        assert_eq!(match_count(&document, "span["), 0);
+    }
+
+    #[test]
+    fn test_build_selector_output() {
+        let document = Html::parse_fragment(r#"<span class="a b">1</span><div>2</div><p class="x">3</p>"#);
+        let first = |tag: &str| document.select(&Selector::parse(tag).unwrap()).next().unwrap();
+        assert_eq!(build_selector(&first("span")), "span.a.b");
+        assert_eq!(build_selector(&first("div")), "div");
+        assert_eq!(build_selector(&first("p")), "p.x");
+    }
+
+    #[test]
+    fn test_build_selector_sorts_classes() {
+        let document = Html::parse_fragment(r#"<span class="c a b a">1</span><div class="b  c a">2</div>"#);
+        let first = |tag: &str| document.select(&Selector::parse(tag).unwrap()).next().unwrap();
+        assert_eq!(build_selector(&first("span")), "span.a.b.c");
+        assert_eq!(build_selector(&first("div")), "div.a.b.c");
+    }
+
+    #[test]
+    fn test_selector_cache() {
+        let cache = SelectorCache::new();
+        assert_eq!(cache.get(Market::TokyoStock, "price"), None);
+        cache.insert(Market::TokyoStock, "price", "span.price");
+        assert_eq!(cache.get(Market::TokyoStock, "price").as_deref(), Some("span.price"));
+        assert_eq!(cache.get(Market::Dji, "price"), None);
+    }
+}
@@ -0,0 +1,517 @@
+use crate::models::{ParsedStockData, Percent, StockData, UpdateTimeParts};
+use crate::scraper_logic::{normalize_minus, split_price_unit};
+use crate::{get_code_type, CodeType};
+
+/// Converts scraped strings into numbers, knowing the format expected for an instrument type:
+/// stock prices are comma-grouped yen with at most one decimal, FX rates have up to 4 decimals
+/// and no grouping, and indices are comma-grouped with 2 decimals.
+/// Values that don't fit the expected format are rejected instead of being mis-parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueParser {
+    /// Maximum number of digits allowed after the decimal point.
+    pub max_decimals: usize,
+    /// Whether ',' thousands separators are expected.
+    pub thousands_separator: bool,
+}
+
+impl ValueParser {
+    pub const STOCK: ValueParser = ValueParser { max_decimals: 1, thousands_separator: true };
+    pub const FX: ValueParser = ValueParser { max_decimals: 4, thousands_separator: false };
+    pub const INDEX: ValueParser = ValueParser { max_decimals: 2, thousands_separator: true };
+    pub const PERCENT: ValueParser = ValueParser { max_decimals: 2, thousands_separator: false };
+
+    /// Picks the parser for an instrument type.
+    pub fn for_code_type(code_type: &CodeType) -> Self {
+        match code_type {
+            CodeType::Stock(_) => Self::STOCK,
+            CodeType::Fx => Self::FX,
+            CodeType::Dji | CodeType::Nikkei => Self::INDEX,
+        }
+    }
+
+    /// Picks the parser for the instrument type a code is classified as.
+    pub fn for_code(code: &str) -> Self {
+        Self::for_code_type(&get_code_type(code))
+    }
+
+    /// Parses a scraped value such as "3,456", "+12.5", "−143.215" or "149.52円" (a unit in `StockData::unit` is ignored).
+    /// Both separator conventions are understood ("1,234.5" and "1.234,5"; see `normalize_separators`).
+    pub fn parse(&self, raw: &str) -> Option<f64> {
+        let normalized = normalize_minus(split_price_unit(raw).0);
+        let unsigned = normalized.strip_prefix('+').unwrap_or(&normalized);
+        let (negative, magnitude) = match unsigned.strip_prefix('-') {
+            Some(magnitude) => (true, magnitude),
+            None => (false, unsigned),
+        };
+        let (digits, grouped) = normalize_separators(magnitude)?;
+        if grouped && !self.thousands_separator {
+            return None;
+        }
+        if let Some((_, decimals)) = digits.split_once('.') {
+            if decimals.len() > self.max_decimals {
+                return None;
+            }
+        }
+        let value = digits.parse::<f64>().ok()?;
+        Some(if negative { -value } else { value })
+    }
+
+    /// Parses a percent value such as "(-1.17%)", "+0.46%" or "1.17".
+    pub fn parse_percent(raw: &str) -> Option<f64> {
+        let trimmed = raw.trim();
+        let inner = trimmed
+            .strip_prefix('(')
+            .and_then(|s| s.strip_suffix(')'))
+            .unwrap_or(trimmed);
+        Self::PERCENT.parse(inner.trim().trim_end_matches('%'))
+    }
+
+    /// Parses a share count such as "2,345,600株", "123.4万株" or "1.2億株" into a number of shares.
+    pub fn parse_share_count(raw: &str) -> Option<f64> {
+        let trimmed = raw.trim();
+        let without_suffix = trimmed.strip_suffix('株').unwrap_or(trimmed).trim_end();
+        let (number, multiplier) = if let Some(number) = without_suffix.strip_suffix('万') {
+            (number, 10_000.0)
+        } else if let Some(number) = without_suffix.strip_suffix('億') {
+            (number, 100_000_000.0)
+        } else {
+            (without_suffix, 1.0)
+        };
+        let (digits, _) = normalize_separators(number.trim())?;
+        let value = digits.parse::<f64>().ok()?;
+        Some(value * multiplier)
+    }
+
+    /// Splits an update time in one of Yahoo's formats (`HH:MM`, `MM/DD HH:MM` or `MM/DD`) into its
+    /// date and time-of-day. A trailing note such as "(日本時間)" is ignored; pieces that don't
+    /// look like a valid date or time are left `None`.
+    pub fn parse_update_time(raw: &str) -> UpdateTimeParts {
+        let mut parts = UpdateTimeParts::default();
+        for token in raw.split_whitespace() {
+            let end = token
+                .find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '/'))
+                .unwrap_or(token.len());
+            let token = &token[..end];
+            if parts.time.is_none() && is_clock_time(token) {
+                parts.time = Some(token.to_string());
+            } else if parts.date.is_none() && is_month_day(token) {
+                parts.date = Some(token.to_string());
+            }
+        }
+        parts
+    }
+
+    /// Converts the raw strings of a scraped record into numbers, and derives the gap at the open.
+    pub fn parse_stock_data(&self, data: &StockData) -> ParsedStockData {
+        let open = data
+            .open
+            .as_deref()
+            .and_then(|open| self.parse(open.split(['(', '（']).next().unwrap_or(open)));
+        let previous_close = data.previous_close.as_deref().and_then(|close| self.parse(close));
+        let (gap, gap_percent) = gap_at_open(open, previous_close);
+        let change = self.parse(&data.change);
+        let percent = Percent::parse(&data.change_percent, change);
+        ParsedStockData {
+            price: self.parse(&data.price),
+            change,
+            change_percent: percent.map(|percent| percent.0),
+            percent,
+            open,
+            previous_close,
+            gap,
+            gap_percent,
+            margin_buy: data.margin_buy.as_deref().and_then(Self::parse_share_count),
+            margin_sell: data.margin_sell.as_deref().and_then(Self::parse_share_count),
+            update_time: Self::parse_update_time(&data.update_time),
+        }
+    }
+}
+
+/// Decimal places `ValueParser::normalize_decimals` writes the display fields with, per instrument type.
+/// `None` leaves that type's values as scraped. More places than the type's parser accepts
+/// (`ValueParser::max_decimals`) are cut to that many, so the written values still parse.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DecimalPrecision {
+    /// Stock price and change.
+    pub stock: Option<usize>,
+    /// FX rate and change.
+    pub fx: Option<usize>,
+    /// Index level and change.
+    pub index: Option<usize>,
+    /// Percent change, whatever the instrument.
+    pub percent: Option<usize>,
+}
+
+impl DecimalPrecision {
+    /// FX rates to 3 decimals, index levels and percentages to 2, stock prices as scraped.
+    pub const DISPLAY: DecimalPrecision = DecimalPrecision { stock: None, fx: Some(3), index: Some(2), percent: Some(2) };
+
+    fn for_code_type(&self, code_type: &CodeType) -> Option<usize> {
+        match code_type {
+            CodeType::Stock(_) => self.stock,
+            CodeType::Fx => self.fx,
+            CodeType::Dji | CodeType::Nikkei => self.index,
+        }
+    }
+}
+
+impl ValueParser {
+    /// Rewrites `price`, `change` and `change_percent` of `data` from their parsed values with the
+    /// decimal places `precision` gives for `code_type`, so a table doesn't mix "1234", "1234.5" and
+    /// "1234.50". Grouping, the '+' of a rise, the '%' and `data.unit` are kept as the field shows
+    /// them. A field whose type has no precision, or that doesn't parse (e.g. "---"), is left as is.
+    pub fn normalize_decimals(&self, data: &mut StockData, code_type: &CodeType, precision: &DecimalPrecision) {
+        let parsed = self.parse_stock_data(data);
+        let value_decimals = precision.for_code_type(code_type).map(|decimals| decimals.min(self.max_decimals));
+        let percent_decimals = precision.percent.map(|decimals| decimals.min(ValueParser::PERCENT.max_decimals));
+        if let (Some(decimals), Some(price)) = (value_decimals, parsed.price) {
+            let unit = data.unit.as_deref().unwrap_or_default();
+            data.price = format!("{}{}", format_decimal(price, decimals, self.thousands_separator, false), unit);
+        }
+        if let (Some(decimals), Some(change)) = (value_decimals, parsed.change) {
+            data.change = format_decimal(change, decimals, self.thousands_separator, true);
+        }
+        if let (Some(decimals), Some(percent)) = (percent_decimals, parsed.change_percent) {
+            data.change_percent = format!("{}%", format_decimal(percent, decimals, false, true));
+        }
+    }
+}
+
+/// Formats `value` with exactly `decimals` decimal places, e.g. "1,234.50" for 1234.5 with 2 and
+/// `grouping`. With `signed` a positive value gets a leading '+', as the page shows changes; zero stays
+/// unsigned.
+pub fn format_decimal(value: f64, decimals: usize, grouping: bool, signed: bool) -> String {
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = formatted.split_at(formatted.find('.').unwrap_or(formatted.len()));
+    let integer = if grouping {
+        let groups: Vec<&str> = integer
+            .as_bytes()
+            .rchunks(3)
+            .rev()
+            .map(|group| std::str::from_utf8(group).unwrap_or_default())
+            .collect();
+        groups.join(",")
+    } else {
+        integer.to_string()
+    };
+    let sign = match value {
+        // A value that rounds to zero is shown without a sign, not as "-0.00".
+        _ if formatted.chars().all(|c| c == '0' || c == '.') => "",
+        value if value < 0.0 => "-",
+        _ if signed => "+",
+        _ => "",
+    };
+    format!("{}{}{}", sign, integer, fraction)
+}
+
+/// The gap at the open and its percentage of the previous close, as `(gap, gap_percent)`.
+/// Both are `None` when either input is missing; the percentage is also `None` for a zero close.
+pub fn gap_at_open(open: Option<f64>, previous_close: Option<f64>) -> (Option<f64>, Option<f64>) {
+    let (Some(open), Some(previous_close)) = (open, previous_close) else {
+        return (None, None);
+    };
+    let gap = open - previous_close;
+    let gap_percent = (previous_close != 0.0).then(|| gap / previous_close * 100.0);
+    (Some(gap), gap_percent)
+}
+
+/// Rewrites an unsigned number written with either separator convention, "1,234.56" or
+/// "1.234,56", as "1234.56", and tells whether it had thousands grouping.
+///
+/// When both separators appear, the last one is the decimal point. A lone '.' is a decimal point;
+/// a lone ',' is one too unless it is followed by exactly three digits and preceded by a group
+/// that doesn't start with 0, so "1,234" is 1234 while "1,5" and "0,125" are decimals. Separators
+/// that occur more than once are grouping. Grouping must be well formed (a first group of 1-3
+/// digits, then groups of exactly 3); anything else is rejected rather than guessed at.
+fn normalize_separators(text: &str) -> Option<(String, bool)> {
+    let last_comma = text.rfind(',');
+    let last_dot = text.rfind('.');
+    let count = |separator: char| text.matches(separator).count();
+    let decimal_separator = match (last_comma, last_dot) {
+        (Some(comma), Some(dot)) => Some(if comma > dot { ',' } else { '.' }),
+        (Some(comma), None) if count(',') == 1 => {
+            let (before, after) = (&text[..comma], &text[comma + 1..]);
+            let is_grouping = after.len() == 3 && !before.starts_with('0');
+            (!is_grouping).then_some(',')
+        }
+        (None, Some(_)) if count('.') == 1 => Some('.'),
+        _ => None,
+    };
+    let (integer, fraction) = match decimal_separator {
+        Some(separator) => {
+            let (integer, fraction) = text.rsplit_once(separator)?;
+            (integer, Some(fraction))
+        }
+        None => (text, None),
+    };
+    let grouping_separator = match decimal_separator {
+        Some(',') => '.',
+        Some(_) => ',',
+        None if last_dot.is_some() => '.',
+        None => ',',
+    };
+
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let groups: Vec<&str> = integer.split(grouping_separator).collect();
+    let grouped = groups.len() > 1;
+    let well_formed = all_digits(groups[0])
+        && (!grouped || groups[0].len() <= 3)
+        && groups[1..].iter().all(|group| group.len() == 3 && all_digits(group));
+    if !well_formed || fraction.is_some_and(|fraction| !all_digits(fraction)) {
+        return None;
+    }
+
+    let mut digits = groups.concat();
+    if let Some(fraction) = fraction {
+        digits.push('.');
+        digits.push_str(fraction);
+    }
+    Some((digits, grouped))
+}
+
+/// Splits `text` at `separator` into two numbers of one or two digits.
+fn two_numbers(text: &str, separator: char) -> Option<(u32, u32)> {
+    let (first, second) = text.split_once(separator)?;
+    let number = |s: &str| (1..=2).contains(&s.len()).then(|| s.parse::<u32>().ok()).flatten();
+    Some((number(first)?, number(second)?))
+}
+
+/// "H:MM" or "HH:MM".
+fn is_clock_time(text: &str) -> bool {
+    text.split_once(':').is_some_and(|(_, minutes)| minutes.len() == 2)
+        && two_numbers(text, ':').is_some_and(|(hours, minutes)| hours < 24 && minutes < 60)
+}
+
+/// "M/D", "MM/DD" and the like.
+fn is_month_day(text: &str) -> bool {
+    two_numbers(text, '/').is_some_and(|(month, day)| (1..=12).contains(&month) && (1..=31).contains(&day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StockKind;
+
+    #[test]
+    fn test_stock_parser() {
+        let parser = ValueParser::for_code("6758");
+        assert_eq!(parser, ValueParser::STOCK);
+        assert_eq!(parser.parse("3,456"), Some(3456.0));
+        assert_eq!(parser.parse("2,745.5"), Some(2745.5));
+        assert_eq!(parser.parse("−41"), Some(-41.0));
+        assert_eq!(parser.parse("143.21"), None);
+        assert_eq!(parser.parse(""), None);
+    }
+
+    #[test]
+    fn test_fx_parser() {
+        let parser = ValueParser::for_code("USDJPY=FX");
+        assert_eq!(parser, ValueParser::FX);
+        assert_eq!(parser.parse("143.21"), Some(143.21));
+        assert_eq!(parser.parse("143.2155"), Some(143.2155));
+        assert_eq!(parser.parse("+0.125"), Some(0.125));
+        assert_eq!(parser.parse("143.21555"), None);
+        assert_eq!(parser.parse("1,143.21"), None);
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(ValueParser::parse_percent("(-1.17%)"), Some(-1.17));
+        assert_eq!(ValueParser::parse_percent("(−1.17%)"), Some(-1.17));
+        assert_eq!(ValueParser::parse_percent("+0.46%"), Some(0.46));
+        assert_eq!(ValueParser::parse_percent("1.2"), Some(1.2));
+        assert_eq!(ValueParser::parse_percent(""), None);
+    }
+
+    #[test]
+    fn test_parse_stock_data() {
+        let data = StockData {
+            price: "3,456".to_string(),
+            change: "-41".to_string(),
+            change_percent: "(-1.17%)".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            ValueParser::STOCK.parse_stock_data(&data),
+            ParsedStockData {
+                price: Some(3456.0),
+                change: Some(-41.0),
+                change_percent: Some(-1.17),
+                percent: Some(Percent(-1.17)),
+                ..Default::default()
+            }
+        );
+
+        // An unsigned percentage next to a falling change is negative in both fields.
+        for change_percent in ["(1.17%)", "1.17%", "(-1.17%)", "−1.17%", "+0.46%", "0.00%", "---"] {
+            let data = StockData {
+                change: "-41".to_string(),
+                change_percent: change_percent.to_string(),
+                ..Default::default()
+            };
+            let parsed = ValueParser::STOCK.parse_stock_data(&data);
+            assert_eq!(parsed.change_percent, parsed.percent.map(|percent| percent.0), "{}", change_percent);
+        }
+        let data = StockData {
+            change: "-41".to_string(),
+            change_percent: "(1.17%)".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(ValueParser::STOCK.parse_stock_data(&data).change_percent, Some(-1.17));
+    }
+
+    #[test]
+    fn test_format_decimal() {
+        assert_eq!(format_decimal(1234.0, 2, true, false), "1,234.00");
+        assert_eq!(format_decimal(1234.5, 1, true, false), "1,234.5");
+        assert_eq!(format_decimal(1234567.891, 0, true, false), "1,234,568");
+        assert_eq!(format_decimal(123.0, 2, true, false), "123.00");
+        assert_eq!(format_decimal(149.5, 3, false, false), "149.500");
+        assert_eq!(format_decimal(1234.5, 2, false, false), "1234.50");
+        assert_eq!(format_decimal(22.5, 1, true, true), "+22.5");
+        assert_eq!(format_decimal(-1041.25, 2, true, true), "-1,041.25");
+        assert_eq!(format_decimal(-0.001, 2, false, true), "0.00");
+        assert_eq!(format_decimal(0.0, 2, false, true), "0.00");
+    }
+
+    #[test]
+    fn test_normalize_decimals() {
+        let mut fx = StockData {
+            price: "149.5円".to_string(),
+            unit: Some("円".to_string()),
+            change: "+0.1234".to_string(),
+            change_percent: "+0.1%".to_string(),
+            ..Default::default()
+        };
+        ValueParser::FX.normalize_decimals(&mut fx, &CodeType::Fx, &DecimalPrecision::DISPLAY);
+        assert_eq!((fx.price.as_str(), fx.change.as_str(), fx.change_percent.as_str()), ("149.500円", "+0.123", "+0.10%"));
+
+        let mut index = StockData {
+            price: "40,123.4".to_string(),
+            change: "-41".to_string(),
+            change_percent: "-1.17%".to_string(),
+            ..Default::default()
+        };
+        ValueParser::INDEX.normalize_decimals(&mut index, &CodeType::Dji, &DecimalPrecision::DISPLAY);
+        assert_eq!((index.price.as_str(), index.change.as_str(), index.change_percent.as_str()), ("40,123.40", "-41.00", "-1.17%"));
+
+        // Stock prices are left as scraped by default; text that doesn't parse is never touched.
+        let mut stock = StockData {
+            price: "3,456".to_string(),
+            change: "---".to_string(),
+            change_percent: "0%".to_string(),
+            ..Default::default()
+        };
+        ValueParser::STOCK.normalize_decimals(&mut stock, &CodeType::Stock(StockKind::Equity), &DecimalPrecision::DISPLAY);
+        assert_eq!((stock.price.as_str(), stock.change.as_str(), stock.change_percent.as_str()), ("3,456", "---", "0.00%"));
+
+        let precision = DecimalPrecision { stock: Some(1), ..Default::default() };
+        ValueParser::STOCK.normalize_decimals(&mut stock, &CodeType::Stock(StockKind::Equity), &precision);
+        assert_eq!((stock.price.as_str(), stock.change_percent.as_str()), ("3,456.0", "0.00%"));
+
+        // Places beyond what the type's parser accepts are cut to its maximum, so the output parses again.
+        let precision = DecimalPrecision { stock: Some(4), fx: Some(6), index: Some(3), percent: Some(5) };
+        for (parser, code_type) in [
+            (ValueParser::STOCK, CodeType::Stock(StockKind::Equity)),
+            (ValueParser::FX, CodeType::Fx),
+            (ValueParser::INDEX, CodeType::Nikkei),
+        ] {
+            let mut data = StockData {
+                price: "1,234.5".replace(',', if parser.thousands_separator { "," } else { "" }),
+                change: "+12.5".to_string(),
+                change_percent: "+1.02%".to_string(),
+                ..Default::default()
+            };
+            parser.normalize_decimals(&mut data, &code_type, &precision);
+            assert_eq!(parser.parse(&data.price), Some(1234.5), "{}", data.price);
+            assert_eq!(parser.parse(&data.change), Some(12.5), "{}", data.change);
+            assert_eq!(ValueParser::parse_percent(&data.change_percent), Some(1.02), "{}", data.change_percent);
+        }
+    }
+
+    #[test]
+    fn test_gap_at_open() {
+        let (gap, gap_percent) = gap_at_open(Some(3480.0), Some(3497.0));
+        assert_eq!(gap, Some(-17.0));
+        assert!((gap_percent.unwrap() - -0.486131).abs() < 1e-6);
+        assert_eq!(gap_at_open(Some(110.0), Some(100.0)), (Some(10.0), Some(10.0)));
+        assert_eq!(gap_at_open(Some(100.0), Some(100.0)), (Some(0.0), Some(0.0)));
+        assert_eq!(gap_at_open(Some(5.0), Some(0.0)), (Some(5.0), None));
+        assert_eq!(gap_at_open(None, Some(3497.0)), (None, None));
+        assert_eq!(gap_at_open(Some(3480.0), None), (None, None));
+
+        let data = StockData {
+            open: Some("3,480(09:00)".to_string()),
+            previous_close: Some("3,497".to_string()),
+            ..Default::default()
+        };
+        let parsed = ValueParser::STOCK.parse_stock_data(&data);
+        assert_eq!((parsed.open, parsed.previous_close, parsed.gap), (Some(3480.0), Some(3497.0), Some(-17.0)));
+
+        let data = StockData {
+            open: Some("---".to_string()),
+            previous_close: Some("3,497".to_string()),
+            ..Default::default()
+        };
+        let parsed = ValueParser::STOCK.parse_stock_data(&data);
+        assert_eq!((parsed.open, parsed.gap, parsed.gap_percent), (None, None, None));
+    }
+
+    #[test]
+    fn test_parse_update_time() {
+        let parts = |date: Option<&str>, time: Option<&str>| UpdateTimeParts {
+            date: date.map(str::to_string),
+            time: time.map(str::to_string),
+        };
+        assert_eq!(ValueParser::parse_update_time("15:00"), parts(None, Some("15:00")));
+        assert_eq!(ValueParser::parse_update_time("12/27 15:00"), parts(Some("12/27"), Some("15:00")));
+        assert_eq!(ValueParser::parse_update_time("12/27"), parts(Some("12/27"), None));
+        assert_eq!(ValueParser::parse_update_time("9:05(日本時間)"), parts(None, Some("9:05")));
+        assert_eq!(ValueParser::parse_update_time("25:00"), parts(None, None));
+        assert_eq!(ValueParser::parse_update_time("13/45"), parts(None, None));
+        assert_eq!(ValueParser::parse_update_time(""), parts(None, None));
+    }
+
+    #[test]
+    fn test_parse_share_count() {
+        assert_eq!(ValueParser::parse_share_count("2,345,600株"), Some(2_345_600.0));
+        assert_eq!(ValueParser::parse_share_count("123.4万株"), Some(1_234_000.0));
+        assert_eq!(ValueParser::parse_share_count("1.2億株"), Some(120_000_000.0));
+        assert_eq!(ValueParser::parse_share_count("312,400"), Some(312_400.0));
+        assert_eq!(ValueParser::parse_share_count("---"), None);
+    }
+
+    #[test]
+    fn test_index_parser() {
+        let parser = ValueParser::for_code("^DJI");
+        assert_eq!(parser, ValueParser::INDEX);
+        assert_eq!(ValueParser::for_code("998407.O"), ValueParser::INDEX);
+        assert_eq!(parser.parse("40,123.45"), Some(40123.45));
+        assert_eq!(parser.parse("-123.45"), Some(-123.45));
+        assert_eq!(parser.parse("40,123.456"), None);
+    }
+
+    #[test]
+    fn test_parse_both_separator_conventions() {
+        let parser = ValueParser::INDEX;
+        assert_eq!(parser.parse("1,234.56"), Some(1234.56));
+        assert_eq!(parser.parse("1.234,56"), Some(1234.56));
+        assert_eq!(parser.parse("-40.123,45"), Some(-40123.45));
+        assert_eq!(parser.parse("1.234.567"), Some(1_234_567.0));
+        assert_eq!(parser.parse("1,5"), Some(1.5));
+        assert_eq!(parser.parse("0,12"), Some(0.12));
+        // A lone comma before three digits is read as grouping, as Yahoo writes it.
+        assert_eq!(parser.parse("1,234"), Some(1234.0));
+        assert_eq!(ValueParser::FX.parse("149,52"), Some(149.52));
+        assert_eq!(ValueParser::FX.parse("0,125"), Some(0.125));
+        assert_eq!(ValueParser::parse_share_count("2.345.600株"), Some(2_345_600.0));
+
+        // Malformed grouping is rejected instead of being squeezed into a number.
+        assert_eq!(parser.parse("12,34,567"), None);
+        assert_eq!(parser.parse("1.23,45"), None);
+        assert_eq!(parser.parse("1,234,56.7"), None);
+        assert_eq!(parser.parse("1,,234"), None);
+        assert_eq!(parser.parse(",5"), None);
+        assert_eq!(parser.parse("5."), None);
+    }
+}
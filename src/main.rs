@@ -1,8 +1,13 @@
-use reqwest;
+use auto_selecter1::instruments::{known_instrument, known_instrument_by_name, KNOWN_INSTRUMENTS};
+use auto_selecter1::models::{normalize_change_percent, Market};
+use auto_selecter1::scraper_logic::{find_prominent_number, DEFAULT_MAX_PRICE_CANDIDATES};
+use auto_selecter1::selectors::build_selector;
 use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
 
 mod static_scraper;
 use crate::static_scraper::scrape_statically;
@@ -18,13 +23,22 @@ impl std::fmt::Display for ScraperError {
 
 impl Error for ScraperError {}
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// A quote as returned by `fetch_data_rust`.
+///
+/// JSON keys are camelCase like the library's `StockData`: `ratio` is `changeAbs`, `percent` is
+/// `changePercent` and `selector_type` is `selectorType`. Compatibility: the old keys (`ratio`,
+/// `percent`, `selector_type`) are still accepted when deserializing.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct StockData {
     pub code: String,
     pub name: String,
     pub price: String,
+    #[serde(rename = "changeAbs", alias = "ratio")]
     pub ratio: String,
+    #[serde(rename = "changePercent", alias = "percent")]
     pub percent: String,
+    #[serde(alias = "selector_type")]
     pub selector_type: String,
 }
 
@@ -37,19 +51,56 @@ struct ScrapedSelectors {
     percent_selector: Option<String>,
 }
 
-fn build_selector(element: &ElementRef) -> String {
-    let mut selector_parts: Vec<String> = Vec::new();
-    let tag_name = element.value().name();
-    selector_parts.push(tag_name.to_string());
-    let classes = element.value().classes().map(|c| format!(".{}", c)).collect::<Vec<String>>().join("");
-    selector_parts.push(classes);
-    selector_parts.join("")
+/// Replaces the minus glyphs some layouts use ("−" U+2212 and the full-width "－") with an ASCII '-'.
+fn normalize_minus(text: &str) -> String {
+    text.replace(['\u{2212}', '\u{FF0D}'], "-")
 }
 
 #[derive(Deserialize)]
 struct ScrapingRequest {
     static_codes: Vec<String>,
     dynamic_codes: Vec<String>,
+    /// JSON file mapping codes to the names used as search anchors, e.g. `{"6758": "ソニーグループ(株)"}`.
+    #[serde(default)]
+    known_names_file: Option<String>,
+    /// Extra code → name entries; these take precedence over the file and the built-in ones.
+    #[serde(default)]
+    known_names: HashMap<String, String>,
+    /// Cap on the numbers the price finder looks at on one page (default `DEFAULT_MAX_PRICE_CANDIDATES`).
+    #[serde(default)]
+    max_price_candidates: Option<usize>,
+}
+
+/// Names the dynamic scraper knows out of the box (see `KNOWN_INSTRUMENTS`).
+fn default_known_names() -> HashMap<String, String> {
+    KNOWN_INSTRUMENTS
+        .iter()
+        .map(|instrument| (instrument.code.to_string(), instrument.name.to_string()))
+        .collect()
+}
+
+/// Reads a JSON object of code → name pairs.
+fn load_known_names(path: &Path) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| ScraperError(format!("Failed to read known names file {}: {}", path.display(), e)))?;
+    let names = serde_json::from_str(&text)
+        .map_err(|e| ScraperError(format!("Invalid known names file {}: {}", path.display(), e)))?;
+    Ok(names)
+}
+
+/// Builds the known-names map for a request: built-in entries, then the file, then inline entries.
+fn known_names_for(request: &ScrapingRequest) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut names = default_known_names();
+    if let Some(path) = &request.known_names_file {
+        names.extend(load_known_names(Path::new(path))?);
+    }
+    names.extend(request.known_names.clone());
+    Ok(names)
+}
+
+/// The anchor name for a code, falling back to the code itself when it isn't known.
+fn known_name<'a>(known_names: &'a HashMap<String, String>, code: &'a str) -> &'a str {
+    known_names.get(code).map(String::as_str).unwrap_or(code)
 }
 
 pub async fn fetch_data_rust(codes_json: String) -> Result<String, Box<dyn Error>> {
@@ -58,6 +109,8 @@ pub async fn fetch_data_rust(codes_json: String) -> Result<String, Box<dyn Error
 
 async fn fetch_and_scrape_multiple(codes_json: &str) -> Result<String, Box<dyn std::error::Error>> {
     let request: ScrapingRequest = serde_json::from_str(codes_json)?;
+    let known_names = known_names_for(&request)?;
+    let max_candidates = request.max_price_candidates.unwrap_or(DEFAULT_MAX_PRICE_CANDIDATES);
     let mut all_stock_data: Vec<StockData> = Vec::new();
 
     for code in request.static_codes {
@@ -69,7 +122,7 @@ async fn fetch_and_scrape_multiple(codes_json: &str) -> Result<String, Box<dyn s
     }
 
     for code in request.dynamic_codes {
-        if let Ok(stock_info) = scrape_dynamically(&code).await {
+        if let Ok(stock_info) = scrape_dynamically(&code, &known_names, max_candidates).await {
             all_stock_data.push(stock_info);
         } else {
             eprintln!("Error fetching dynamic data for: {}", code);
@@ -80,36 +133,31 @@ async fn fetch_and_scrape_multiple(codes_json: &str) -> Result<String, Box<dyn s
     Ok(scraped_data.to_string())
 }
 
-async fn scrape_dynamically(code: &str) -> Result<StockData, Box<dyn Error>> {
-    match code {
-        "%5EDJI" => fetch_and_scrape_dow_dynamic().await,
+async fn scrape_dynamically(
+    code: &str,
+    known_names: &HashMap<String, String>,
+    max_candidates: usize,
+) -> Result<StockData, Box<dyn Error>> {
+    let instrument = known_instrument(code);
+    match instrument.map(|instrument| instrument.market) {
+        Some(Market::Dji) => fetch_and_scrape_dow_dynamic(max_candidates).await,
         _ => {
-            let url = if code == "998407.O" {
-                format!("https://finance.yahoo.co.jp/quote/{}", code)
-            } else {
-                format!("https://finance.yahoo.co.jp/quote/{}.T", code)
+            let url = match instrument.and_then(|instrument| instrument.url) {
+                Some(url) => url.to_string(),
+                None => format!("https://finance.yahoo.co.jp/quote/{}.T", code),
             };
-            let known_name = if code == "6758" {
-                "ソニーグループ(株)"
-            } else if code == "7203" {
-                "トヨタ自動車(株)"
-            } else if code == "998407.O" {
-                "日経平均株価"
-            } else {
-                code
-            };
-            fetch_and_scrape_stock_dynamic(&url, known_name).await
+            fetch_and_scrape_stock_dynamic(&url, known_name(known_names, code), max_candidates).await
         }
     }
 }
 
-async fn fetch_and_scrape_dow_dynamic() -> Result<StockData, Box<dyn Error>> {
+async fn fetch_and_scrape_dow_dynamic(max_candidates: usize) -> Result<StockData, Box<dyn Error>> {
     let url = format!("https://finance.yahoo.co.jp/quote/{}", "%5EDJI");
     let response = reqwest::get(url).await?;
     let body = response.text().await?;
     let document = Html::parse_document(&body);
 
-    let selectors = get_dow_dynamic_selectors(&document).await;
+    let selectors = get_dow_dynamic_selectors(&document, max_candidates).await;
 
     let code_selector_str = selectors.code_selector.ok_or_else(|| ScraperError("Dynamic code selector not found for DOW".to_string()))?;
     let name_selector_str = selectors.name_selector.ok_or_else(|| ScraperError("Dynamic name selector not found for DOW".to_string()))?;
@@ -134,17 +182,21 @@ async fn fetch_and_scrape_dow_dynamic() -> Result<StockData, Box<dyn Error>> {
         name,
         price,
         ratio,
-        percent,
+        percent: normalize_change_percent(&percent),
         selector_type: "dynamic".to_string(),
     })
 }
 
-async fn fetch_and_scrape_stock_dynamic(url: &str, known_name: &str) -> Result<StockData, Box<dyn std::error::Error>> {
+async fn fetch_and_scrape_stock_dynamic(
+    url: &str,
+    known_name: &str,
+    max_candidates: usize,
+) -> Result<StockData, Box<dyn std::error::Error>> {
     let response = reqwest::get(url).await?;
     let body = response.text().await?;
     let document = Html::parse_document(&body);
 
-    let selectors = get_stock_dynamic_selectors(&document, known_name).await?;
+    let selectors = get_stock_dynamic_selectors(&document, known_name, max_candidates).await?;
 
     let code_selector_str = selectors.code_selector.ok_or_else(|| ScraperError("Dynamic code selector not found for stock".to_string()))?;
     let name_selector_str = selectors.name_selector.ok_or_else(|| ScraperError("Dynamic name selector not found for stock".to_string()))?;
@@ -169,7 +221,7 @@ async fn fetch_and_scrape_stock_dynamic(url: &str, known_name: &str) -> Result<S
         name,
         price,
         ratio,
-        percent,
+        percent: normalize_change_percent(&percent),
         selector_type: "dynamic".to_string(),
     })
 }
@@ -284,7 +336,7 @@ async fn find_percent_selector_near_zenjitsuhi(
         // ElementRef::select を使って子孫要素をイテレートし、候補をすべて集める
         for element in area.select(&selector) {
             let text = element.text().collect::<String>();
-            let trimmed = text.trim();
+            let trimmed = normalize_minus(text.trim());
 
             // パーセント値判定をより厳密にする
             if trimmed.contains('%')
@@ -345,7 +397,7 @@ async fn find_ratio_selector_near_zenjitsuhi(
         // ElementRef::select を使って子孫要素をイテレートする
         for element in area.select(&selector) {
             let text = element.text().collect::<String>();
-            let trimmed = text.trim();
+            let trimmed = normalize_minus(text.trim());
 
             // 変動幅の判定（"+" or "-"で始まり、数字が続き、"%"を含まない）
             if (trimmed.starts_with('+') || trimmed.starts_with('-'))
@@ -368,11 +420,12 @@ async fn find_ratio_selector_near_zenjitsuhi(
 }
 
 /// Finds a CSS selector for the price, which is assumed to be a prominent numeric value
-/// near the anchor text element.
+/// near the anchor text element. At most `max_candidates` numbers are considered (see `find_prominent_number`).
 async fn find_price_selector_near_anchor(
     document: &Html,
     anchor_text: &str,
     max_levels: usize,
+    max_candidates: usize,
 ) -> Result<Option<String>, Box<dyn std::error::Error>> {
     // 1. Find the anchor text node and the search area (ancestor element)
     let mut search_area = None;
@@ -399,33 +452,15 @@ async fn find_price_selector_near_anchor(
 
     // 2. Find a prominent numeric element within the search area
     if let Some(area) = search_area {
-        let mut candidate_elements = Vec::new();
-        let selector = Selector::parse("*").map_err(|e| ScraperError(format!("{:?}", e)))?;
-
-        for element in area.select(&selector) {
-            let text = element.text().collect::<String>();
-            let trimmed = text.trim().replace(",", ""); // Remove commas for parsing
-
-            // Check if the text is a plausible price (contains digits, optionally commas/periods, and is not just the stock code)
-            if !trimmed.is_empty() && trimmed.parse::<f64>().is_ok() && trimmed.len() > 2 && trimmed != anchor_text {
-                // Simple heuristic: consider tag name and class count for prominence
-                let class_count = element.value().classes().count();
-                candidate_elements.push((element, class_count));
-            }
-        }
-
-        // Sort candidates by prominence (more classes = higher)
-        candidate_elements.sort_by(|a, b| b.1.cmp(&a.1));
-
-        if let Some((best_candidate, _)) = candidate_elements.first() {
-            return Ok(Some(build_selector(best_candidate)));
+        if let Some(best_candidate) = find_prominent_number(area, anchor_text, max_candidates) {
+            return Ok(Some(build_selector(&best_candidate)));
         }
     }
 
     Ok(None)
 }
 
-async fn get_dow_dynamic_selectors(document: &Html) -> ScrapedSelectors {
+async fn get_dow_dynamic_selectors(document: &Html, max_candidates: usize) -> ScrapedSelectors {
     let mut scraped_selectors = ScrapedSelectors::default();
 
     if let Ok(Some(selector)) = find_dynamic_selector(document, "NYダウ").await {
@@ -436,7 +471,7 @@ async fn get_dow_dynamic_selectors(document: &Html) -> ScrapedSelectors {
         scraped_selectors.code_selector = Some(selector);
     }
 
-    if let Ok(Some(selector)) = find_price_selector_near_anchor(document, "NYダウ", 4).await {
+    if let Ok(Some(selector)) = find_price_selector_near_anchor(document, "NYダウ", 4, max_candidates).await {
         scraped_selectors.price_selector = Some(selector);
     }
 
@@ -451,7 +486,11 @@ async fn get_dow_dynamic_selectors(document: &Html) -> ScrapedSelectors {
     scraped_selectors
 }
 
-async fn get_stock_dynamic_selectors(document: &Html, known_name: &str) -> Result<ScrapedSelectors, Box<dyn Error>> {
+async fn get_stock_dynamic_selectors(
+    document: &Html,
+    known_name: &str,
+    max_candidates: usize,
+) -> Result<ScrapedSelectors, Box<dyn Error>> {
     let mut scraped_selectors = ScrapedSelectors::default();
     let zenjitsuhi_anchor = "前日比";
 
@@ -489,9 +528,10 @@ async fn get_stock_dynamic_selectors(document: &Html, known_name: &str) -> Resul
         }
     }
 
-    // Try to find code selector dynamically
-    if known_name == "日経平均株価" {
-        if let Ok(Some(selector)) = find_dynamic_selector(document, "998407.O").await {
+    // Try to find code selector dynamically; index codes don't look like stock codes, so they are anchored on as-is.
+    let known_index = known_instrument_by_name(known_name).filter(|instrument| instrument.market != Market::TokyoStock);
+    if let Some(index) = known_index {
+        if let Ok(Some(selector)) = find_dynamic_selector(document, index.code).await {
             scraped_selectors.code_selector = Some(selector);
         }
     } else if let Ok(Some(selector)) = find_text_pattern_selector_near_anchor(document, known_name, "code").await {
@@ -499,7 +539,7 @@ async fn get_stock_dynamic_selectors(document: &Html, known_name: &str) -> Resul
     }
 
     // Try to find price selector dynamically
-    if let Ok(Some(selector)) = find_price_selector_near_anchor(document, known_name, 4).await {
+    if let Ok(Some(selector)) = find_price_selector_near_anchor(document, known_name, 4, max_candidates).await {
         scraped_selectors.price_selector = Some(selector);
     }
 
@@ -572,6 +612,78 @@ async fn main() -> Result<(), Box<dyn Error>> {
 mod tests {
     use super::*;
 
+    // `build_selector` is shared with the smp scraper; this pins the output this scraper relies on.
+    #[test]
+    fn test_build_selector_output() {
+        let document = Html::parse_fragment(r#"<span class="a b">1</span><div>2</div><p class="x">3</p>"#);
+        let first = |tag: &str| document.select(&Selector::parse(tag).unwrap()).next().unwrap();
+        assert_eq!(build_selector(&first("span")), "span.a.b");
+        assert_eq!(build_selector(&first("div")), "div");
+        assert_eq!(build_selector(&first("p")), "p.x");
+    }
+
+    #[test]
+    fn test_known_names_from_file_and_request() {
+        let path = std::env::temp_dir().join(format!("auto_selecter1_known_names_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"8729": "ソニーフィナンシャルグループ(株)", "7203": "トヨタ"}"#).unwrap();
+
+        let request: ScrapingRequest = serde_json::from_value(json!({
+            "static_codes": [],
+            "dynamic_codes": ["6758", "7203", "8729", "4755"],
+            "known_names_file": path.to_str().unwrap(),
+            "known_names": {"4755": "楽天グループ(株)"}
+        }))
+        .unwrap();
+        let names = known_names_for(&request).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(known_name(&names, "6758"), "ソニーグループ(株)");
+        assert_eq!(known_name(&names, "7203"), "トヨタ");
+        assert_eq!(known_name(&names, "8729"), "ソニーフィナンシャルグループ(株)");
+        assert_eq!(known_name(&names, "4755"), "楽天グループ(株)");
+        assert_eq!(known_name(&names, "5016"), "5016");
+    }
+
+    #[test]
+    fn test_known_names_file_errors() {
+        let request: ScrapingRequest = serde_json::from_value(json!({
+            "static_codes": [],
+            "dynamic_codes": [],
+            "known_names_file": "/nonexistent/known_names.json"
+        }))
+        .unwrap();
+        assert!(known_names_for(&request).is_err());
+    }
+
+    #[test]
+    fn test_stock_data_json_keys() {
+        let data = StockData {
+            code: "6758".to_string(),
+            name: "ソニーグループ(株)".to_string(),
+            price: "3,456".to_string(),
+            ratio: "-41".to_string(),
+            percent: "-1.17%".to_string(),
+            selector_type: "dynamic".to_string(),
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["changeAbs"], "-41");
+        assert_eq!(json["changePercent"], "-1.17%");
+        assert_eq!(json["selectorType"], "dynamic");
+        assert!(json.get("ratio").is_none());
+        assert_eq!(serde_json::from_value::<StockData>(json).unwrap(), data);
+
+        // Output written with the old keys still reads back.
+        let legacy = json!({
+            "code": "6758",
+            "name": "ソニーグループ(株)",
+            "price": "3,456",
+            "ratio": "-41",
+            "percent": "-1.17%",
+            "selector_type": "dynamic"
+        });
+        assert_eq!(serde_json::from_value::<StockData>(legacy).unwrap(), data);
+    }
+
     fn is_numeric_str(s: &str) -> bool {
         s.replace(",", "").parse::<f64>().is_ok()
     }
@@ -591,7 +703,7 @@ mod tests {
 
         assert!(is_numeric_str(&data.price));
         assert!(is_numeric_str(&data.ratio));
-        assert!(is_numeric_str(&data.percent));
+        assert!(is_numeric_str(data.percent.trim_end_matches('%')));
     }
 
     #[tokio::test]
@@ -1,5 +1,5 @@
 use crate::{StockData, ScraperError};
-use reqwest;
+use auto_selecter1::models::normalize_change_percent;
 use scraper::{Html, Selector};
 use std::error::Error;
 
@@ -20,8 +20,11 @@ pub async fn scrape_statically(code: &str) -> Result<StockData, Box<dyn Error>>
 pub async fn fetch_and_scrape_stock(url: &str) -> Result<StockData, Box<dyn std::error::Error>> {
     let response = reqwest::get(url).await?;
     let body = response.text().await?;
-    let document = Html::parse_document(&body);
+    scrape_stock_document(&Html::parse_document(&body))
+}
 
+/// Scrapes a stock (or Nikkei) page with the hardcoded selectors.
+pub fn scrape_stock_document(document: &Html) -> Result<StockData, Box<dyn Error>> {
     let code_selector = Selector::parse("span.PriceBoard__code__SnMF").map_err(|e| ScraperError(format!("{:?}", e)))?;
     let name_selector = Selector::parse("h2.PriceBoard__name__166W").map_err(|e| ScraperError(format!("{:?}", e)))?;
     let price_selector = Selector::parse("span.StyledNumber__value__3rXW").map_err(|e| ScraperError(format!("{:?}", e)))?;
@@ -39,7 +42,7 @@ pub async fn fetch_and_scrape_stock(url: &str) -> Result<StockData, Box<dyn std:
         name,
         price,
         ratio,
-        percent,
+        percent: normalize_change_percent(&percent),
         selector_type: "static".to_string(),
     })
 }
@@ -49,33 +52,77 @@ pub async fn fetch_and_scrape_dow() -> Result<StockData, Box<dyn Error>> {
     let response = reqwest::get(url).await?;
     let body = response.text().await?;
     let document = Html::parse_document(&body);
+    scrape_dow_document(&document)
+}
 
-    let code_selector = Selector::parse("span._CommonPriceBoard__code_1g7gt_11").map_err(|e| ScraperError(format!("{:?}", e)))?;
-    let name_selector = Selector::parse("h2._BasePriceBoard__name_1tkwp_66").map_err(|e| ScraperError(format!("{:?}", e)))?;
-    let price_selector = Selector::parse("span._StyledNumber__value_1lush_9").map_err(|e| ScraperError(format!("{:?}", e)))?;
-    let ratio_selector = Selector::parse("span._PriceChangeLabel__primary_hse06_56 > span._StyledNumber__value_1lush_9").map_err(|e| ScraperError(format!("{:?}", e)))?;
-    let percent_selector = Selector::parse("span._PriceChangeLabel__secondary_hse06_62 > span._StyledNumber__value_1lush_9").map_err(|e| ScraperError(format!("{:?}", e)))?;
-
-    let code = document.select(&code_selector).next().map(|n| n.text().collect::<String>()).unwrap_or_default();
-    let name = document.select(&name_selector).next().map(|n| n.text().collect::<String>()).unwrap_or_default();
-    let price = document.select(&price_selector).next().map(|n| n.text().collect::<String>()).unwrap_or_default();
-    let ratio = document.select(&ratio_selector).next().map(|n| n.text().collect::<String>()).unwrap_or_default();
-    let percent = document.select(&percent_selector).next().map(|n| n.text().collect::<String>()).unwrap_or_default();
+/// Scrapes the DOW page. The class names carry a build hash (`_1g7gt_` etc.) that changes now and then,
+/// so each field tries the exact hashed class first and then a `[class*=...]` match on the stable part.
+fn scrape_dow_document(document: &Html) -> Result<StockData, Box<dyn Error>> {
+    let code = select_first_matching(document, &[
+        "span._CommonPriceBoard__code_1g7gt_11",
+        "[class*='_CommonPriceBoard__code']",
+    ])?;
+    let name = select_first_matching(document, &[
+        "h2._BasePriceBoard__name_1tkwp_66",
+        "[class*='_BasePriceBoard__name']",
+    ])?;
+    let price = select_first_matching(document, &[
+        "span._StyledNumber__value_1lush_9",
+        "[class*='_BasePriceBoard__price'] [class*='_StyledNumber__value']",
+    ])?;
+    let ratio = select_first_matching(document, &[
+        "span._PriceChangeLabel__primary_hse06_56 > span._StyledNumber__value_1lush_9",
+        "[class*='_PriceChangeLabel__primary'] > [class*='_StyledNumber__value']",
+    ])?;
+    let percent = select_first_matching(document, &[
+        "span._PriceChangeLabel__secondary_hse06_62 > span._StyledNumber__value_1lush_9",
+        "[class*='_PriceChangeLabel__secondary'] > [class*='_StyledNumber__value']",
+    ])?;
 
     Ok(StockData {
         code,
         name,
         price,
         ratio,
-        percent,
+        percent: normalize_change_percent(&percent),
         selector_type: "static".to_string(),
     })
 }
 
+/// Returns the text of the first element matched by the first selector (in order) that matches a non-empty one.
+fn select_first_matching(document: &Html, selectors: &[&str]) -> Result<String, Box<dyn Error>> {
+    for selector in selectors {
+        let selector = Selector::parse(selector).map_err(|e| ScraperError(format!("{:?}", e)))?;
+        if let Some(text) = document
+            .select(&selector)
+            .map(|n| n.text().collect::<String>())
+            .find(|text| !text.trim().is_empty())
+        {
+            return Ok(text);
+        }
+    }
+    Ok(String::new())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_scrape_dow_falls_back_when_the_class_hash_changes() {
+        let html = include_str!("../smp/fixtures/index_dji.html")
+            .replace("_1g7gt_", "_9zq0x_")
+            .replace("_1tkwp_", "_7abcd_")
+            .replace("_1lush_", "_2mnop_")
+            .replace("_hse06_", "_k3l4m_");
+        let data = scrape_dow_document(&Html::parse_document(&html)).unwrap();
+        assert_eq!(data.code, "^DJI");
+        assert_eq!(data.name, "NYダウ");
+        assert_eq!(data.price, "40,123.45");
+        assert_eq!(data.ratio, "+123.45");
+        assert_eq!(data.percent, "+0.31%");
+    }
+
     fn is_numeric_str(s: &str) -> bool {
         s.replace(",", "").parse::<f64>().is_ok()
     }
@@ -95,7 +142,31 @@ mod tests {
 
         assert!(is_numeric_str(&data.price));
         assert!(is_numeric_str(&data.ratio));
-        assert!(is_numeric_str(&data.percent));
+        assert!(is_numeric_str(data.percent.trim_end_matches('%')));
+    }
+
+    /// One page, whatever URL is asked for, so both scrapers see the same snapshot.
+    struct OnePageFetcher(String);
+
+    impl auto_selecter1::fetcher::Fetcher for OnePageFetcher {
+        async fn get(&self, _url: &str) -> Result<auto_selecter1::fetcher::Response, auto_selecter1::models::ScraperError> {
+            Ok(auto_selecter1::fetcher::Response { status: 200, body: self.0.clone(), ..Default::default() })
+        }
+    }
+
+    /// The static selectors here and the library's dynamic finders are two independent ways of
+    /// reading the same page; if they disagree, one of them has drifted from the live layout.
+    /// Opt-in, as it needs the network: `cargo test --bin auto_selecter1 -- --ignored agree`.
+    #[tokio::test]
+    #[ignore = "needs network access"]
+    async fn test_static_and_dynamic_scrapers_agree() {
+        let body = reqwest::get("https://finance.yahoo.co.jp/quote/6758.T").await.unwrap().text().await.unwrap();
+        let static_data = scrape_stock_document(&Html::parse_document(&body)).unwrap();
+        let dynamic_data = auto_selecter1::scrape_dynamically(&OnePageFetcher(body), "6758").await.unwrap();
+
+        assert!(!static_data.price.is_empty());
+        assert_eq!(static_data.name, dynamic_data.name);
+        assert_eq!(static_data.price, dynamic_data.price);
     }
 
     #[tokio::test]
@@ -112,7 +183,7 @@ mod tests {
 
         assert!(is_numeric_str(&data.price));
         assert!(is_numeric_str(&data.ratio));
-        assert!(is_numeric_str(&data.percent));
+        assert!(is_numeric_str(data.percent.trim_end_matches('%')));
     }
 
     #[tokio::test]
@@ -130,6 +201,6 @@ mod tests {
 
         assert!(is_numeric_str(&data.price));
         assert!(is_numeric_str(&data.ratio));
-        assert!(is_numeric_str(&data.percent));
+        assert!(is_numeric_str(data.percent.trim_end_matches('%')));
     }
 }
\ No newline at end of file